@@ -0,0 +1,513 @@
+//! Reading ELF core files, for post-mortem debugging with `deet <program> <core>`.
+//!
+//! A core file is an ELF file whose PT_LOAD segments hold the memory of the crashed process and
+//! whose PT_NOTE segments hold its registers and other details. Memory that the kernel didn't
+//! dump, such as the program's code, is read from the executable instead.
+
+use crate::target::{auxv_entry_point, Target};
+use nix::errno::Errno;
+use nix::libc::{user_fpregs_struct, user_regs_struct};
+use nix::sys::signal::Signal;
+use std::convert::TryFrom;
+use std::fs;
+use std::mem::size_of;
+use std::ptr;
+
+const ELFCLASS64: u8 = 2;
+const ELFDATA2LSB: u8 = 1;
+const ET_CORE: u16 = 4;
+const EM_X86_64: u16 = 62;
+const PT_LOAD: u32 = 1;
+const PT_NOTE: u32 = 4;
+const NT_PRSTATUS: u32 = 1;
+const NT_PRFPREG: u32 = 2;
+const NT_PRPSINFO: u32 = 3;
+const NT_AUXV: u32 = 6;
+
+// Sizes of the ELF header and of one program header in a 64-bit file
+const EHDR_SIZE: usize = 64;
+const PHDR_SIZE: usize = 56;
+
+// Offsets into struct elf_prstatus and struct elf_prpsinfo on x86-64
+const PRSTATUS_CURSIG: usize = 12;
+const PRSTATUS_REGS: usize = 112;
+const PRPSINFO_PSARGS: usize = 56;
+const PRPSINFO_PSARGS_LEN: usize = 80;
+
+pub struct CoreDump {
+    /// The command line of the crashed process (truncated to 80 characters by the kernel)
+    pub command: String,
+    /// The signal that killed the process
+    pub signal: Option<Signal>,
+    /// How far the executable was moved from the addresses it was linked at (see
+    /// Inferior::load_bias)
+    pub load_bias: usize,
+    regs: user_regs_struct,
+    fpregs: Option<user_fpregs_struct>,
+    /// The memory dumped in the core file, followed by the loadable segments of the executable
+    segments: Vec<Segment>,
+}
+
+/// A range of the process's memory and its contents
+struct Segment {
+    addr: usize,
+    data: Vec<u8>,
+}
+
+impl CoreDump {
+    /// Loads a core file produced by running the given executable.
+    pub fn load(core_path: &str, executable_path: &str) -> Result<CoreDump, String> {
+        let core = fs::read(core_path).map_err(|err| format!("{}: {}", core_path, err))?;
+        let core_elf =
+            Elf::parse(&core).ok_or_else(|| format!("{}: not an ELF file", core_path))?;
+        if core_elf.file_type != ET_CORE {
+            return Err(format!("\"{}\" is not a core dump", core_path));
+        }
+
+        let mut dump = CoreDump {
+            command: String::new(),
+            signal: None,
+            load_bias: 0,
+            regs: unsafe { std::mem::zeroed() },
+            fpregs: None,
+            segments: Vec::new(),
+        };
+        let mut found_regs = false;
+        let mut entry_point = None;
+        for header in &core_elf.program_headers {
+            let data = match core_elf.segment_data(header) {
+                Some(data) => data,
+                None => return Err(format!("{}: truncated core file", core_path)),
+            };
+            match header.p_type {
+                PT_LOAD => dump.segments.push(Segment {
+                    addr: header.vaddr,
+                    data: data.to_vec(),
+                }),
+                PT_NOTE => {
+                    for (name, note_type, desc) in notes(data) {
+                        if name != b"CORE" {
+                            continue;
+                        }
+                        match note_type {
+                            // There is one per thread; the first is the thread that crashed
+                            NT_PRSTATUS if !found_regs => {
+                                if desc.len() < PRSTATUS_REGS + size_of::<user_regs_struct>() {
+                                    return Err(format!("{}: invalid NT_PRSTATUS note", core_path));
+                                }
+                                let cursig = read_u16(desc, PRSTATUS_CURSIG) as i32;
+                                dump.signal = Signal::try_from(cursig).ok();
+                                dump.regs = unsafe {
+                                    ptr::read_unaligned(
+                                        desc[PRSTATUS_REGS..].as_ptr() as *const user_regs_struct
+                                    )
+                                };
+                                found_regs = true;
+                            }
+                            NT_PRFPREG if desc.len() >= size_of::<user_fpregs_struct>() => {
+                                dump.fpregs = Some(unsafe {
+                                    ptr::read_unaligned(desc.as_ptr() as *const user_fpregs_struct)
+                                });
+                            }
+                            NT_PRPSINFO if desc.len() >= PRPSINFO_PSARGS + PRPSINFO_PSARGS_LEN => {
+                                let args =
+                                    &desc[PRPSINFO_PSARGS..PRPSINFO_PSARGS + PRPSINFO_PSARGS_LEN];
+                                let len = args.iter().position(|&b| b == 0).unwrap_or(args.len());
+                                dump.command =
+                                    String::from_utf8_lossy(&args[..len]).trim_end().to_string();
+                            }
+                            NT_AUXV => entry_point = auxv_entry_point(desc),
+                            _ => {}
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        if !found_regs {
+            return Err(format!("{}: core file has no registers", core_path));
+        }
+
+        // The kernel usually leaves out file-backed memory that wasn't written to, such as code
+        if let Ok(executable) = fs::read(executable_path) {
+            if let Some(elf) = Elf::parse(&executable) {
+                if let Some(entry_point) = entry_point {
+                    dump.load_bias = entry_point.wrapping_sub(elf.entry);
+                }
+                for header in elf.program_headers.iter().filter(|h| h.p_type == PT_LOAD) {
+                    if let Some(data) = elf.segment_data(header) {
+                        dump.segments.push(Segment {
+                            addr: header.vaddr.wrapping_add(dump.load_bias),
+                            data: data.to_vec(),
+                        });
+                    }
+                }
+            }
+        }
+        Ok(dump)
+    }
+}
+
+impl Target for CoreDump {
+    fn get_registers(&self) -> Result<user_regs_struct, nix::Error> {
+        Ok(self.regs)
+    }
+
+    fn get_fpregs(&self) -> Result<user_fpregs_struct, nix::Error> {
+        self.fpregs.ok_or(nix::Error::Sys(Errno::ENODATA))
+    }
+
+    /// Reads memory from the segments, failing like ptrace does (with EIO) if part of the range
+    /// isn't in any of them.
+    fn read_memory(&self, addr: usize, len: usize) -> Result<Vec<u8>, nix::Error> {
+        let mut bytes = Vec::with_capacity(len);
+        while bytes.len() < len {
+            let next = addr
+                .checked_add(bytes.len())
+                .ok_or(nix::Error::Sys(Errno::EIO))?;
+            let segment = self
+                .segments
+                .iter()
+                .find(|segment| next >= segment.addr && next - segment.addr < segment.data.len())
+                .ok_or(nix::Error::Sys(Errno::EIO))?;
+            let start = next - segment.addr;
+            let end = segment.data.len().min(start + len - bytes.len());
+            bytes.extend_from_slice(&segment.data[start..end]);
+        }
+        Ok(bytes)
+    }
+}
+
+/// The parts of a 64-bit little-endian ELF file that we need
+struct Elf<'a> {
+    data: &'a [u8],
+    file_type: u16,
+    entry: usize,
+    program_headers: Vec<ProgramHeader>,
+}
+
+struct ProgramHeader {
+    p_type: u32,
+    offset: usize,
+    vaddr: usize,
+    file_size: usize,
+}
+
+impl<'a> Elf<'a> {
+    fn parse(data: &'a [u8]) -> Option<Elf<'a>> {
+        // Only 64-bit, little-endian x86-64 files are understood (e_ident's class and data
+        // encoding, then e_machine)
+        if data.len() < EHDR_SIZE || &data[..4] != b"\x7fELF" {
+            return None;
+        }
+        if data[4] != ELFCLASS64 || data[5] != ELFDATA2LSB || read_u16(data, 18) != EM_X86_64 {
+            return None;
+        }
+        let phoff = read_u64(data, 32) as usize;
+        let phentsize = read_u16(data, 54) as usize;
+        let phnum = read_u16(data, 56) as usize;
+        if phnum > 0 && phentsize < PHDR_SIZE {
+            return None;
+        }
+        let mut program_headers = Vec::new();
+        for i in 0..phnum {
+            // Every header has to lie within the file
+            let start = phoff.checked_add(i * phentsize)?;
+            let header = data.get(start..start.checked_add(PHDR_SIZE)?)?;
+            program_headers.push(ProgramHeader {
+                p_type: read_u32(header, 0),
+                offset: read_u64(header, 8) as usize,
+                vaddr: read_u64(header, 16) as usize,
+                file_size: read_u64(header, 32) as usize,
+            });
+        }
+        Some(Elf {
+            data,
+            file_type: read_u16(data, 16),
+            entry: read_u64(data, 24) as usize,
+            program_headers,
+        })
+    }
+
+    fn segment_data(&self, header: &ProgramHeader) -> Option<&'a [u8]> {
+        self.data
+            .get(header.offset..header.offset.checked_add(header.file_size)?)
+    }
+}
+
+/// Splits the contents of a PT_NOTE segment into (name, type, descriptor) triples. The name and
+/// descriptor are each padded to a multiple of 4 bytes.
+fn notes(mut data: &[u8]) -> Vec<(&[u8], u32, &[u8])> {
+    let pad = |len: usize| (len + 3) & !3;
+    let mut notes = Vec::new();
+    while data.len() >= 12 {
+        let name_size = read_u32(data, 0) as usize;
+        let desc_size = read_u32(data, 4) as usize;
+        let note_type = read_u32(data, 8);
+        // Both sizes come from the file, so make sure the note fits in what is left of it
+        let desc_start = 12 + pad(name_size);
+        let end = desc_start + pad(desc_size);
+        if data.len() < desc_start + desc_size {
+            break;
+        }
+        // The name includes its terminating NUL
+        let name = &data[12..12 + name_size.saturating_sub(1)];
+        notes.push((name, note_type, &data[desc_start..desc_start + desc_size]));
+        data = &data[end.min(data.len())..];
+    }
+    notes
+}
+
+fn read_u16(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    let mut bytes = [0; 4];
+    bytes.copy_from_slice(&data[offset..offset + 4]);
+    u32::from_le_bytes(bytes)
+}
+
+fn read_u64(data: &[u8], offset: usize) -> u64 {
+    let mut bytes = [0; 8];
+    bytes.copy_from_slice(&data[offset..offset + 8]);
+    u64::from_le_bytes(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process;
+
+    const ET_EXEC: u16 = 2;
+
+    fn elf_header(file_type: u16, phnum: usize) -> Vec<u8> {
+        let mut header = vec![0; EHDR_SIZE];
+        header[..4].copy_from_slice(b"\x7fELF");
+        header[4] = ELFCLASS64;
+        header[5] = ELFDATA2LSB;
+        header[6] = 1;
+        header[16..18].copy_from_slice(&file_type.to_le_bytes());
+        header[18..20].copy_from_slice(&EM_X86_64.to_le_bytes());
+        header[24..32].copy_from_slice(&0x401000u64.to_le_bytes());
+        header[32..40].copy_from_slice(&(EHDR_SIZE as u64).to_le_bytes());
+        header[54..56].copy_from_slice(&(PHDR_SIZE as u16).to_le_bytes());
+        header[56..58].copy_from_slice(&(phnum as u16).to_le_bytes());
+        header
+    }
+
+    fn program_header(p_type: u32, offset: usize, vaddr: usize, file_size: usize) -> Vec<u8> {
+        let mut header = vec![0; PHDR_SIZE];
+        header[..4].copy_from_slice(&p_type.to_le_bytes());
+        header[8..16].copy_from_slice(&(offset as u64).to_le_bytes());
+        header[16..24].copy_from_slice(&(vaddr as u64).to_le_bytes());
+        header[32..40].copy_from_slice(&(file_size as u64).to_le_bytes());
+        header
+    }
+
+    fn note(name: &[u8], note_type: u32, desc: &[u8]) -> Vec<u8> {
+        let mut note = Vec::new();
+        note.extend_from_slice(&(name.len() as u32 + 1).to_le_bytes());
+        note.extend_from_slice(&(desc.len() as u32).to_le_bytes());
+        note.extend_from_slice(&note_type.to_le_bytes());
+        note.extend_from_slice(name);
+        note.push(0);
+        note.resize((note.len() + 3) & !3, 0);
+        note.extend_from_slice(desc);
+        note.resize((note.len() + 3) & !3, 0);
+        note
+    }
+
+    /// Builds an ELF file with the given segments, laid out one after the other following the
+    /// program headers.
+    fn elf_file(file_type: u16, segments: &[(u32, usize, Vec<u8>)]) -> Vec<u8> {
+        let mut file = elf_header(file_type, segments.len());
+        let mut offset = EHDR_SIZE + segments.len() * PHDR_SIZE;
+        for (p_type, vaddr, data) in segments {
+            file.extend(program_header(*p_type, offset, *vaddr, data.len()));
+            offset += data.len();
+        }
+        for (_, _, data) in segments {
+            file.extend_from_slice(data);
+        }
+        file
+    }
+
+    fn prstatus(signal: Signal, rip: u64) -> Vec<u8> {
+        let mut desc = vec![0; PRSTATUS_REGS + size_of::<user_regs_struct>()];
+        desc[PRSTATUS_CURSIG..PRSTATUS_CURSIG + 2].copy_from_slice(&(signal as u16).to_le_bytes());
+        // rip is the 17th register in struct user_regs_struct
+        let rip_offset = PRSTATUS_REGS + 16 * 8;
+        desc[rip_offset..rip_offset + 8].copy_from_slice(&rip.to_le_bytes());
+        desc
+    }
+
+    fn load(name: &str, core: &[u8]) -> Result<CoreDump, String> {
+        let path = std::env::temp_dir().join(format!("deet-test-{}-{}", process::id(), name));
+        let path = path.to_str().unwrap();
+        fs::write(path, core).unwrap();
+        let result = CoreDump::load(path, "/nonexistent").map_err(|err| err.replace(path, "core"));
+        fs::remove_file(path).unwrap();
+        result
+    }
+
+    #[test]
+    fn parse_reads_program_headers() {
+        let file = elf_file(
+            ET_CORE,
+            &[(PT_NOTE, 0, vec![1, 2, 3]), (PT_LOAD, 0x600000, vec![4, 5])],
+        );
+        let elf = Elf::parse(&file).unwrap();
+        assert_eq!(elf.file_type, ET_CORE);
+        assert_eq!(elf.entry, 0x401000);
+        assert_eq!(elf.program_headers.len(), 2);
+        let load = &elf.program_headers[1];
+        assert_eq!(
+            (load.p_type, load.vaddr, load.file_size),
+            (PT_LOAD, 0x600000, 2)
+        );
+        assert_eq!(
+            elf.segment_data(&elf.program_headers[0]),
+            Some(&[1, 2, 3][..])
+        );
+        assert_eq!(elf.segment_data(load), Some(&[4, 5][..]));
+    }
+
+    #[test]
+    fn parse_rejects_other_kinds_of_file() {
+        let file = elf_file(ET_CORE, &[]);
+        assert!(Elf::parse(&file[..EHDR_SIZE - 1]).is_none());
+        let mut not_elf = file.clone();
+        not_elf[0] = b'E';
+        assert!(Elf::parse(&not_elf).is_none());
+        let mut elf32 = file.clone();
+        elf32[4] = 1;
+        assert!(Elf::parse(&elf32).is_none());
+        let mut big_endian = file.clone();
+        big_endian[5] = 2;
+        assert!(Elf::parse(&big_endian).is_none());
+        let mut aarch64 = file;
+        aarch64[18..20].copy_from_slice(&183u16.to_le_bytes());
+        assert!(Elf::parse(&aarch64).is_none());
+    }
+
+    #[test]
+    fn parse_rejects_malformed_program_headers() {
+        let file = elf_file(ET_CORE, &[(PT_LOAD, 0x600000, vec![0; 8])]);
+        // Cut off in the middle of the program header
+        assert!(Elf::parse(&file[..EHDR_SIZE + PHDR_SIZE - 1]).is_none());
+        let mut small_entries = file.clone();
+        small_entries[54..56].copy_from_slice(&(PHDR_SIZE as u16 - 1).to_le_bytes());
+        assert!(Elf::parse(&small_entries).is_none());
+        let mut far_offset = file;
+        far_offset[32..40].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert!(Elf::parse(&far_offset).is_none());
+    }
+
+    #[test]
+    fn segment_data_must_lie_within_the_file() {
+        let mut file = elf_file(ET_CORE, &[(PT_LOAD, 0x600000, vec![0; 8])]);
+        let elf = Elf::parse(&file).unwrap();
+        assert!(elf.segment_data(&elf.program_headers[0]).is_some());
+        file.truncate(file.len() - 1);
+        let elf = Elf::parse(&file).unwrap();
+        assert!(elf.segment_data(&elf.program_headers[0]).is_none());
+        let overflowing = ProgramHeader {
+            p_type: PT_LOAD,
+            offset: 1,
+            vaddr: 0,
+            file_size: usize::MAX,
+        };
+        assert!(elf.segment_data(&overflowing).is_none());
+    }
+
+    #[test]
+    fn notes_are_split_at_their_padding() {
+        let mut data = note(b"CORE", NT_PRSTATUS, &[1, 2, 3]);
+        data.extend(note(b"LINUX", 0x202, &[4; 8]));
+        assert_eq!(
+            notes(&data),
+            vec![
+                (&b"CORE"[..], NT_PRSTATUS, &[1, 2, 3][..]),
+                (&b"LINUX"[..], 0x202, &[4; 8][..]),
+            ]
+        );
+    }
+
+    #[test]
+    fn notes_stop_at_a_truncated_note() {
+        let mut data = note(b"CORE", NT_AUXV, &[7; 4]);
+        let mut truncated = note(b"CORE", NT_PRSTATUS, &[1; 16]);
+        truncated.truncate(truncated.len() - 1);
+        data.extend(truncated);
+        assert_eq!(notes(&data), vec![(&b"CORE"[..], NT_AUXV, &[7; 4][..])]);
+        // Too short for a note header
+        assert_eq!(notes(&[0; 11]), vec![]);
+        // A huge size can't run past the end
+        let mut huge = note(b"CORE", NT_AUXV, &[]);
+        huge[4..8].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert_eq!(notes(&huge), vec![]);
+    }
+
+    #[test]
+    fn load_reads_registers_command_and_memory() {
+        let mut psinfo = vec![0; PRPSINFO_PSARGS + PRPSINFO_PSARGS_LEN];
+        psinfo[PRPSINFO_PSARGS..PRPSINFO_PSARGS + 10].copy_from_slice(b"./prog -v ");
+        let mut notes = note(b"CORE", NT_PRSTATUS, &prstatus(Signal::SIGSEGV, 0x401136));
+        notes.extend(note(b"CORE", NT_PRPSINFO, &psinfo));
+        // A second thread's registers are ignored
+        notes.extend(note(
+            b"CORE",
+            NT_PRSTATUS,
+            &prstatus(Signal::SIGUSR1, 0x402000),
+        ));
+        let core = elf_file(
+            ET_CORE,
+            &[(PT_NOTE, 0, notes), (PT_LOAD, 0x600000, vec![1, 2, 3, 4])],
+        );
+        let dump = load("core", &core).unwrap();
+        assert_eq!(dump.signal, Some(Signal::SIGSEGV));
+        assert_eq!(dump.command, "./prog -v");
+        assert_eq!(dump.get_registers().unwrap().rip, 0x401136);
+        assert_eq!(
+            dump.get_fpregs().err(),
+            Some(nix::Error::Sys(Errno::ENODATA))
+        );
+        assert_eq!(dump.read_memory(0x600001, 3), Ok(vec![2, 3, 4]));
+        assert_eq!(
+            dump.read_memory(0x600001, 4),
+            Err(nix::Error::Sys(Errno::EIO))
+        );
+    }
+
+    #[test]
+    fn load_reports_unusable_core_files() {
+        assert_eq!(
+            load("empty", &[]).err(),
+            Some("core: not an ELF file".to_string())
+        );
+        assert_eq!(
+            load("exec", &elf_file(ET_EXEC, &[])).err(),
+            Some("\"core\" is not a core dump".to_string())
+        );
+        assert_eq!(
+            load(
+                "noregs",
+                &elf_file(ET_CORE, &[(PT_LOAD, 0x600000, vec![0; 4])])
+            )
+            .err(),
+            Some("core: core file has no registers".to_string())
+        );
+        let short_prstatus = note(b"CORE", NT_PRSTATUS, &[0; PRSTATUS_REGS]);
+        assert_eq!(
+            load("short", &elf_file(ET_CORE, &[(PT_NOTE, 0, short_prstatus)])).err(),
+            Some("core: invalid NT_PRSTATUS note".to_string())
+        );
+        let mut truncated = elf_file(ET_CORE, &[(PT_LOAD, 0x600000, vec![0; 4])]);
+        truncated.pop();
+        assert_eq!(
+            load("truncated", &truncated).err(),
+            Some("core: truncated core file".to_string())
+        );
+    }
+}
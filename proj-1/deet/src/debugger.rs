@@ -1,20 +1,123 @@
-use crate::debugger_command::DebuggerCommand;
+use crate::core_dump::CoreDump;
+use crate::debugger_command::{self, DebuggerCommand};
+use crate::disassembler;
+use crate::dwarf_data::{DwarfData, Error as DwarfError, Type};
+use crate::expression::{self, Evaluator, Expression};
 use crate::inferior::Inferior;
-use crate::inferior::Status;
+use crate::inferior::{Status, WatchKind};
+use crate::registers;
+use crate::shared_library;
+use crate::signals::{self, SignalTable};
+use crate::source::SourceFiles;
+use crate::target::Target;
+use crate::value::{
+    escape_c_char, format_float, format_value, quote_c_string, read_signed, read_unsigned,
+    value_as_integer, x87_extended_to_f64, MAX_ELEMENTS,
+};
+use nix::errno::Errno;
+use nix::libc;
+use nix::sys::signal::Signal;
+use nix::unistd::{self, Pid};
 use rustyline::error::ReadlineError;
 use rustyline::Editor;
+use std::collections::{HashMap, VecDeque};
+use std::fs;
 
 pub struct Debugger {
     target: String,
     history_path: String,
     readline: Editor<()>,
+    /// Lines from `-ex`, `-x` and `source` still to be run, before reading from the terminal
+    pending_commands: VecDeque<String>,
+    /// Whether to quit once the pending commands are done, rather than prompt for more
+    batch: bool,
+    /// Commands created with `define`, and the lines they run
+    user_commands: HashMap<String, Vec<String>>,
+    /// For each user-defined command being run, how many pending commands follow its lines
+    user_call_stack: Vec<usize>,
+    /// How the last inferior to terminate ended, as a shell would report it (128 + signal
+    /// number if it was killed)
+    exit_status: i32,
     inferior: Option<Inferior>,
+    /// Number of the current inferior, as shown by `info inferiors`
+    inferior_num: usize,
+    /// Forked processes that are still traced (with detach-on-fork off). They stay stopped until
+    /// switched to with `inferior <num>`.
+    other_inferiors: Vec<(usize, Inferior)>,
+    next_inferior_num: usize,
+    /// A core dump being examined. While a process is running, commands look at it instead.
+    core: Option<CoreDump>,
+    /// The process and number of the thread that the last stop was reported in
+    shown_thread: Option<(Pid, usize)>,
+    follow_fork_child: bool,
+    detach_on_fork: bool,
+    signals: SignalTable,
+    debug_data: DwarfData,
+    breakpoints: Vec<Breakpoint>,
+    watchpoints: Vec<Watchpoint>,
+    /// Breakpoints and watchpoints share numbers, so this counts both
+    next_breakpoint_num: usize,
+    /// Format letter and unit size last used by `x`
+    examine_format: (char, usize),
+    /// Where an `x` without an address continues: just past what the last one showed
+    examine_next: Option<usize>,
+    sources: SourceFiles,
+    /// Where a `list` without arguments continues: file name and next line number. Cleared
+    /// whenever the inferior stops, so that listing starts around the new location.
+    list_position: Option<(String, usize)>,
+}
+
+struct Breakpoint {
+    num: usize,
+    /// None while the breakpoint is pending: its location is in a shared library that isn't
+    /// loaded (yet)
+    addr: Option<usize>,
+    /// The location as given to `break`, so that it can be resolved again in a new program
+    location: String,
+    /// Only stop if this condition (as typed, and parsed) holds
+    condition: Option<(String, Expression)>,
+    hit_count: usize,
+    /// Number of upcoming hits to resume from without stopping
+    ignore_count: usize,
+    /// Commands to run when the breakpoint stops the inferior, as set with `commands`
+    commands: Vec<String>,
+}
+
+/// A hardware watchpoint on a value in the inferior's memory. Values wider than 8 bytes (or
+/// unaligned ones) need several debug registers.
+struct Watchpoint {
+    num: usize,
+    expression: String,
+    entity_type: Type,
+    addr: usize,
+    kind: WatchKind,
+    slots: Vec<usize>,
+    old_value: Vec<u8>,
+    hit_count: usize,
+    ignore_count: usize,
+    /// For values in a stack frame: the frame's return address and canonical frame address.
+    /// The watchpoint is deleted once the frame returns, since its memory gets reused.
+    scope: Option<(usize, usize)>,
+}
+
+/// A line of `info breakpoints`, for breakpoints and watchpoints alike.
+struct BreakpointRow<'a> {
+    num: usize,
+    kind: &'a str,
+    addr: String,
+    what: String,
+    condition: Option<&'a str>,
+    hit_count: usize,
+    ignore_count: usize,
 }
 
 impl Debugger {
     /// Initializes the debugger.
     pub fn new(target: &str) -> Debugger {
-        // TODO (milestone 3): initialize the DwarfData
+        let debug_data = match load_debug_data(target) {
+            Some(val) => val,
+            None => std::process::exit(1),
+        };
 
         let history_path = format!("{}/.deet_history", std::env::var("HOME").unwrap());
         let mut readline = Editor::<()>::new();
@@ -25,101 +128,2382 @@ impl Debugger {
             target: target.to_string(),
             history_path,
             readline,
+            pending_commands: VecDeque::new(),
+            user_call_stack: Vec::new(),
+            batch: false,
+            user_commands: HashMap::new(),
+            exit_status: 0,
             inferior: None,
+            inferior_num: 1,
+            other_inferiors: Vec::new(),
+            next_inferior_num: 2,
+            core: None,
+            shown_thread: None,
+            follow_fork_child: false,
+            detach_on_fork: true,
+            signals: SignalTable::new(),
+            debug_data,
+            breakpoints: Vec::new(),
+            watchpoints: Vec::new(),
+            next_breakpoint_num: 0,
+            examine_format: ('x', 4),
+            examine_next: None,
+            sources: SourceFiles::new(),
+            list_position: None,
         }
     }
 
-    pub fn run(&mut self) {
+    /// Queues commands to run before any typed at the prompt. In batch mode, the debugger quits
+    /// once they are done instead of prompting.
+    pub fn queue_commands(&mut self, commands: Vec<String>, batch: bool) {
+        self.pending_commands.extend(commands);
+        self.batch = batch;
+    }
+
+    /// Runs commands until told to quit, returning the exit status of the last inferior to
+    /// terminate (0 if none did).
+    pub fn run(&mut self) -> i32 {
         loop {
-            match self.get_next_command() {
+            let cmd = self.get_next_command();
+            match cmd {
                 DebuggerCommand::Run(args) => {
-                    if let Some(ref mut inferior) = self.inferior {
-                        inferior.kill();
-                    }
+                    self.discard_inferiors();
+                    self.watchpoints.clear();
+                    self.debug_data.clear_libraries();
 
-                    if let Some(inferior) = Inferior::new(&self.target, &args) {
+                    if let Some(mut inferior) = Inferior::new(&self.target, &args) {
                         // Create the inferior
+                        inferior.set_fork_mode(self.follow_fork_child, self.detach_on_fork);
+                        inferior.set_signal_table(&self.signals);
+                        self.relocate_program(&inferior);
+                        self.set_startup_breakpoint(&mut inferior);
                         self.inferior = Some(inferior);
-                        // (milestone 1): make the inferior run
-                        // You may use self.inferior.as_mut().unwrap() to get a mutable reference
-                        // to the Inferior object
-
-                        let mut inferior = self.inferior.as_mut().unwrap();
-                        match inferior.continu3() {
-                            Ok(status) => match status {
-                                Status::Stopped(signal, _) => println!("Child stopped: {}", signal),
-                                Status::Signaled(signal) => println!("Child signaled: {}", signal),
-                                Status::Exited(code) => println!("Child exited: {}", code),
-                            }
-                            Err(error) => println!("Failed to continue child: {}", error)
-                        }
-
+                        // Breakpoints in libraries stay pending until the libraries are loaded
+                        self.resolve_breakpoints();
+                        self.continue_inferior();
                     } else {
                         println!("Error starting subprocess");
                     }
                 }
                 DebuggerCommand::Continue => {
+                    if self.inferior.is_some() {
+                        self.continue_inferior();
+                    } else {
+                        println!("There has no child.")
+                    }
+                }
+                DebuggerCommand::Break(location, condition) => {
+                    let addr = self.parse_location(&location);
+                    if addr.is_none() && !is_function_location(&location) {
+                        println!("Could not resolve breakpoint location {}", location);
+                        continue;
+                    }
+                    // It may be in a library that the program loads later, but it may just as
+                    // well be a typo, so only make it pending if the user says so
+                    if addr.is_none() {
+                        println!("Function \"{}\" not defined.", location);
+                        if !self.query("Make breakpoint pending on future shared library load?") {
+                            continue;
+                        }
+                    }
+                    let condition = match condition {
+                        Some(condition) => match expression::parse(&condition) {
+                            Ok(expr) => Some((condition, expr)),
+                            Err(err) => {
+                                println!("{}", err);
+                                continue;
+                            }
+                        },
+                        None => None,
+                    };
+                    if let (Some(addr), Some(inferior)) = (addr, self.inferior.as_mut()) {
+                        if let Err(err) = inferior.set_breakpoint(addr) {
+                            println!("Failed to set breakpoint at {:#x}: {}", addr, err);
+                            continue;
+                        }
+                    }
+                    let num = self.next_breakpoint_num;
+                    self.next_breakpoint_num += 1;
+                    match addr {
+                        Some(addr) => println!("Set breakpoint {} at {:#x}", num, addr),
+                        None => println!("Breakpoint {} ({}) pending.", num, location),
+                    }
+                    self.breakpoints.push(Breakpoint {
+                        num,
+                        addr,
+                        location,
+                        condition,
+                        hit_count: 0,
+                        ignore_count: 0,
+                        commands: Vec::new(),
+                    });
+                }
+                DebuggerCommand::Ignore(num, count) => self.ignore(num, count),
+                DebuggerCommand::InfoBreakpoints => self.print_breakpoints(),
+                DebuggerCommand::Backtrace => {
+                    if let Some(target) = self.target() {
+                        if let Err(err) = target.print_backtrace(&self.debug_data) {
+                            println!("Failed to print backtrace: {}", err);
+                        }
+                    } else {
+                        println!("There has no child.")
+                    }
+                }
+                DebuggerCommand::StepInstruction => {
+                    if let Some(ref mut inferior) = self.inferior {
+                        let result = inferior.step_instruction();
+                        self.report_status(result, false);
+                    } else {
+                        println!("There has no child.")
+                    }
+                }
+                DebuggerCommand::Step | DebuggerCommand::Next => {
+                    let step_into = matches!(cmd, DebuggerCommand::Step);
                     if let Some(ref mut inferior) = self.inferior {
-                        match inferior.continu3() {
-                            Ok(status) => match status {
-                                Status::Stopped(signal, _) => println!("Child stopped: {}", signal),
-                                Status::Signaled(signal) => println!("Child signaled: {}", signal),
-                                Status::Exited(code) => println!("Child exited: {}", code),
+                        let rip = inferior.rip();
+                        // Without line numbers, the best we can do is get out of the function
+                        let debug_data = &self.debug_data;
+                        let (breakpoints, pending_commands) =
+                            (&mut self.breakpoints, &mut self.pending_commands);
+                        let mut should_stop = |inferior: &Inferior, rip| {
+                            breakpoint_hit(breakpoints, pending_commands, debug_data, inferior, rip)
+                        };
+                        let result = if debug_data.get_line_from_addr(rip).is_some() {
+                            inferior.step_line(debug_data, step_into, &mut should_stop)
+                        } else if let Some(name) = debug_data.get_function_from_addr(rip) {
+                            println!(
+                                "Single stepping until exit from function {},\nwhich has no line \
+                                 number information.",
+                                name
+                            );
+                            inferior.finish(debug_data, &mut should_stop)
+                        } else {
+                            println!("Cannot find bounds of current function");
+                            continue;
+                        };
+                        self.report_status(result, false);
+                    } else {
+                        println!("There has no child.")
+                    }
+                }
+                DebuggerCommand::Finish => {
+                    if let Some(ref mut inferior) = self.inferior {
+                        if self.debug_data.get_function_from_addr(inferior.rip())
+                            == Some("main".to_string())
+                        {
+                            println!("\"finish\" not meaningful in the outermost frame.");
+                            continue;
+                        }
+                        let debug_data = &self.debug_data;
+                        let (breakpoints, pending_commands) =
+                            (&mut self.breakpoints, &mut self.pending_commands);
+                        let mut should_stop = |inferior: &Inferior, rip| {
+                            breakpoint_hit(breakpoints, pending_commands, debug_data, inferior, rip)
+                        };
+                        let result = inferior.finish(debug_data, &mut should_stop);
+                        self.report_status(result, false);
+                    } else {
+                        println!("There has no child.")
+                    }
+                }
+                DebuggerCommand::Print(input) => {
+                    if let Some(target) = self.target() {
+                        let evaluator = Evaluator::new(&self.debug_data, target);
+                        match expression::parse(&input).and_then(|expr| evaluator.evaluate(&expr)) {
+                            Ok(value) => println!(
+                                "{} = {}",
+                                input,
+                                format_value(
+                                    &self.debug_data,
+                                    &value.entity_type,
+                                    &value.bytes,
+                                    &|addr, len| target.read_memory(addr, len)
+                                )
+                            ),
+                            Err(err) => println!("{}", err),
+                        }
+                    } else {
+                        println!("There has no child.")
+                    }
+                }
+                DebuggerCommand::InfoLocals => {
+                    if let Some(target) = self.target() {
+                        let func = match self.debug_data.get_function_containing(target.rip()) {
+                            Some(func) => func,
+                            None => {
+                                println!("No symbol table info available.");
+                                continue;
+                            }
+                        };
+                        if func.variables.is_empty() {
+                            println!("No locals.");
+                        }
+                        let evaluator = Evaluator::new(&self.debug_data, target);
+                        for var in &func.variables {
+                            match evaluator.read_variable(var) {
+                                Ok(value) => println!(
+                                    "{} = {}",
+                                    var.name,
+                                    format_value(
+                                        &self.debug_data,
+                                        &value.entity_type,
+                                        &value.bytes,
+                                        &|addr, len| target.read_memory(addr, len)
+                                    )
+                                ),
+                                Err(err) => println!("{} = <error: {}>", var.name, err),
                             }
-                            Err(error) => println!("Failed to continue child: {}", error)
                         }
                     } else {
                         println!("There has no child.")
                     }
                 }
-                DebuggerCommand::Quit => {
+                DebuggerCommand::Attach(pid) => match pid.parse::<i32>() {
+                    Ok(pid) => self.attach(Pid::from_raw(pid)),
+                    Err(_) => println!("Invalid process id {}", pid),
+                },
+                DebuggerCommand::Detach => {
+                    self.delete_watchpoints();
                     if let Some(ref mut inferior) = self.inferior {
-                        inferior.kill();
+                        let pid = inferior.pid();
+                        match inferior.detach() {
+                            Ok(()) => println!("Detached from process {}", pid),
+                            Err(err) => println!("Failed to detach from process {}: {}", pid, err),
+                        }
+                        self.inferior = None;
+                    } else {
+                        println!("There has no child.")
                     }
-                    return;
                 }
+                DebuggerCommand::Watch(ref input) | DebuggerCommand::ReadWatch(ref input) => {
+                    let kind = match cmd {
+                        DebuggerCommand::Watch(_) => WatchKind::Write,
+                        _ => WatchKind::ReadWrite,
+                    };
+                    if self.inferior.is_some() {
+                        self.set_watchpoint(input, kind);
+                    } else {
+                        println!("There has no child.")
+                    }
+                }
+                DebuggerCommand::InfoRegisters(names) => self.print_registers(&names),
+                DebuggerCommand::InfoFloat => self.print_float_registers(),
+                DebuggerCommand::Set(input) => self.set(&input),
+                DebuggerCommand::List(location) => self.list(location),
+                DebuggerCommand::Directory(Some(dir)) => {
+                    self.sources.add_directory(&dir);
+                    println!(
+                        "Source directories searched: {}",
+                        self.sources.search_path()
+                    );
+                }
+                DebuggerCommand::Directory(None) => {
+                    self.sources.reset_search_path();
+                    println!(
+                        "Source directories searched: {}",
+                        self.sources.search_path()
+                    );
+                }
+                DebuggerCommand::Disassemble(function) => self.disassemble(function),
+                DebuggerCommand::Examine(format, input) => self.examine(&format, &input),
+                DebuggerCommand::InfoInferiors => self.print_inferiors(),
+                DebuggerCommand::InfoSharedLibrary => self.print_shared_libraries(),
+                DebuggerCommand::InfoThreads => self.print_threads(),
+                DebuggerCommand::Handle(signal, keywords) => self.handle(&signal, &keywords),
+                DebuggerCommand::InfoSignals(signal) => self.print_signals(signal),
+                DebuggerCommand::Signal(signal) => self.continue_with_signal(&signal),
+                DebuggerCommand::Thread(num) => self.select_thread(num),
+                DebuggerCommand::Inferior(num) => self.switch_inferior(num),
+                DebuggerCommand::Source(path) => self.source(&path),
+                DebuggerCommand::Define(name) => self.define(name),
+                DebuggerCommand::Commands(num) => self.set_breakpoint_commands(num),
+                DebuggerCommand::End => {
+                    println!("This command cannot be used at the top level.")
+                }
+                DebuggerCommand::Quit => {
+                    self.discard_inferiors();
+                    return self.exit_status;
+                }
+            }
+        }
+    }
+
+    /// Attaches to a running process, loading its debugging symbols from /proc/<pid>/exe (the
+    /// file on disk may have been replaced since the process started).
+    pub fn attach(&mut self, pid: Pid) {
+        if self.inferior.is_some() {
+            println!("A program is being debugged already.");
+            return;
+        }
+        let exe_path = format!("/proc/{}/exe", pid);
+        if self.target != exe_path {
+            match load_debug_data(&exe_path) {
+                Some(debug_data) => self.debug_data = debug_data,
+                None => return,
+            }
+        }
+        match Inferior::attach(pid) {
+            Ok(mut inferior) => {
+                inferior.set_fork_mode(self.follow_fork_child, self.detach_on_fork);
+                self.relocate_program(&inferior);
+                // Let `run` start a fresh copy of the same program later
+                if let Ok(path) = fs::read_link(&exe_path) {
+                    self.target = path.to_string_lossy().to_string();
+                }
+                println!("Attached to process {}", pid);
+                let rip = inferior.rip();
+                self.inferior = Some(inferior);
+                self.debug_data.clear_libraries();
+                self.update_shared_libraries();
+                self.resolve_breakpoints();
+                self.print_stop_location(rip);
             }
+            Err(nix::Error::Sys(Errno::EPERM)) => println!(
+                "Could not attach to process {}: Operation not permitted (check \
+                 /proc/sys/kernel/yama/ptrace_scope)",
+                pid
+            ),
+            Err(err) => println!("Could not attach to process {}: {}", pid, err),
         }
     }
 
-    /// This function prompts the user to enter a command, and continues re-prompting until the user
-    /// enters a valid command. It uses DebuggerCommand::from_tokens to do the command parsing.
+    /// Loads a core dump of the program, for looking at the state it crashed in.
+    pub fn load_core(&mut self, path: &str) {
+        let core = match CoreDump::load(path, &self.target) {
+            Ok(core) => core,
+            Err(err) => {
+                println!("{}", err);
+                return;
+            }
+        };
+        if !core.command.is_empty() {
+            println!("Core was generated by `{}'.", core.command);
+        }
+        if let Some(signal) = core.signal {
+            println!(
+                "Program terminated with signal {}, {}.",
+                signal,
+                signals::describe(signal)
+            );
+        }
+        self.debug_data.set_bias(core.load_bias);
+        let rip = core.rip();
+        self.core = Some(core);
+        self.print_stop_location(rip);
+    }
+
+    /// Returns what the commands that only inspect state look at: the running inferior if there
+    /// is one, or else the core dump.
+    fn target(&self) -> Option<&dyn Target> {
+        match (&self.inferior, &self.core) {
+            (Some(inferior), _) => Some(inferior),
+            (None, Some(core)) => Some(core),
+            (None, None) => None,
+        }
+    }
+
+    /// Resumes the inferior and reports how it stopped.
+    fn continue_inferior(&mut self) {
+        loop {
+            let result = self.inferior.as_mut().unwrap().continu3();
+            if self.report_status(result, true) {
+                return;
+            }
+        }
+    }
+
+    /// Reports how the inferior stopped. SIGTRAP stops are only announced if show_trap is set,
+    /// since they are the expected outcome of stepping. If the inferior terminated, it is
+    /// dropped so that a later `run` starts from scratch.
     ///
-    /// You don't need to read, understand, or modify this function.
-    fn get_next_command(&mut self) -> DebuggerCommand {
+    /// When continuing (show_trap), a write watchpoint that fired without changing the value
+    /// isn't worth stopping for, and neither is a breakpoint whose condition is false or which
+    /// is being ignored. In that case nothing is printed and false is returned, meaning the
+    /// inferior should be resumed.
+    fn report_status(&mut self, result: Result<Status, nix::Error>, show_trap: bool) -> bool {
+        let followed = match result {
+            Ok(Status::Forked(child)) => Some(child),
+            _ => None,
+        };
+        self.adopt_forks(followed);
+        match result {
+            Ok(status) => match status {
+                Status::Stopped(signal, rip) => {
+                    let library_event =
+                        signal == Signal::SIGTRAP && self.at_library_breakpoint(rip);
+                    self.update_shared_libraries();
+                    let watch_reports = match signal {
+                        Signal::SIGTRAP => self.check_watchpoints(),
+                        _ => None,
+                    };
+                    match watch_reports {
+                        Some(ref reports) if !reports.is_empty() => {
+                            self.announce_thread_switch();
+                            for report in reports {
+                                println!("{}", report);
+                            }
+                        }
+                        Some(_) if show_trap => return false,
+                        _ => {
+                            if show_trap
+                                && signal == Signal::SIGTRAP
+                                && (library_event || !self.breakpoint_hit(rip))
+                            {
+                                return false;
+                            }
+                            self.announce_thread_switch();
+                            if show_trap || signal != Signal::SIGTRAP {
+                                println!("Child stopped: {}", signal);
+                            }
+                        }
+                    }
+                    self.print_stop_location(rip);
+                }
+                Status::Signaled(signal) => {
+                    println!("Child signaled: {}", signal);
+                    self.exit_status = 128 + signal as i32;
+                    self.delete_watchpoints();
+                    self.inferior = None;
+                }
+                Status::Exited(code) => {
+                    println!("Child exited: {}", code);
+                    self.exit_status = code;
+                    self.delete_watchpoints();
+                    self.inferior = None;
+                }
+                Status::Forked(..) => {
+                    // adopt_forks switched to the child
+                    if show_trap {
+                        return false;
+                    }
+                    let rip = self.inferior.as_ref().unwrap().rip();
+                    self.print_stop_location(rip);
+                }
+                Status::Execed(rip) => {
+                    self.load_new_program();
+                    if show_trap {
+                        return false;
+                    }
+                    self.print_stop_location(rip);
+                }
+            },
+            Err(error) => println!("Failed to continue child: {}", error),
+        }
+        true
+    }
+
+    /// Deals with the processes the inferior forked. The one being followed (if any) becomes the
+    /// current inferior, and the parent is detached unless detach-on-fork is off. Children that
+    /// are still traced are kept stopped as extra inferiors.
+    fn adopt_forks(&mut self, followed: Option<Pid>) {
+        let forks = match self.inferior {
+            Some(ref mut inferior) => inferior.take_forks(),
+            None => return,
+        };
+        for fork in forks {
+            let child = match fork.child {
+                Some(child) => child,
+                None => {
+                    println!("[Detaching after fork from child process {}]", fork.pid);
+                    continue;
+                }
+            };
+            if Some(fork.pid) != followed {
+                self.add_inferior(child);
+                continue;
+            }
+
+            // The watchpoints are in the parent's debug registers, which the child doesn't inherit
+            self.delete_watchpoints();
+            let mut parent = self.inferior.replace(child).unwrap();
+            println!(
+                "[Attaching after process {} fork to child process {}]",
+                parent.pid(),
+                fork.pid
+            );
+            let parent_num = self.inferior_num;
+            self.inferior_num = self.next_inferior_num;
+            self.next_inferior_num += 1;
+            if !self.detach_on_fork {
+                println!("[New inferior {} (process {})]", parent_num, parent.pid());
+                self.other_inferiors.push((parent_num, parent));
+                continue;
+            }
+            // After a vfork this also removes the child's breakpoints, as they share memory
+            match parent.detach() {
+                Ok(()) => println!(
+                    "[Detaching after fork from parent process {}]",
+                    parent.pid()
+                ),
+                Err(err) => println!("Failed to detach from process {}: {}", parent.pid(), err),
+            }
+        }
+    }
+
+    /// Passes the fork and signal settings on to all inferiors.
+    fn update_inferior_settings(&mut self) {
+        let (follow_fork_child, detach_on_fork) = (self.follow_fork_child, self.detach_on_fork);
+        let others = self
+            .other_inferiors
+            .iter_mut()
+            .map(|(_, inferior)| inferior);
+        for inferior in self.inferior.iter_mut().chain(others) {
+            inferior.set_fork_mode(follow_fork_child, detach_on_fork);
+            inferior.set_signal_table(&self.signals);
+        }
+    }
+
+    fn add_inferior(&mut self, inferior: Inferior) {
+        let num = self.next_inferior_num;
+        self.next_inferior_num += 1;
+        println!("[New inferior {} (process {})]", num, inferior.pid());
+        self.other_inferiors.push((num, inferior));
+    }
+
+    /// Reloads the debugging symbols after the inferior execs a new program, and installs the
+    /// breakpoints again at their locations in it. Breakpoints whose locations aren't in the new
+    /// program are deleted.
+    fn load_new_program(&mut self) {
+        let pid = self.inferior.as_ref().unwrap().pid();
+        let exe_path = format!("/proc/{}/exe", pid);
+        let path = fs::read_link(&exe_path)
+            .map(|path| path.to_string_lossy().to_string())
+            .unwrap_or_else(|_| exe_path.clone());
+        println!("process {} is executing new program: {}", pid, path);
+        // The kernel cleared the debug registers
+        self.watchpoints.clear();
+        self.list_position = None;
+        match load_debug_data(&exe_path) {
+            Some(debug_data) => {
+                self.debug_data = debug_data;
+                self.target = path;
+            }
+            None => {
+                for bp in self.breakpoints.drain(..) {
+                    println!(
+                        "Breakpoint {} deleted: {} is not in the new program.",
+                        bp.num, bp.location
+                    );
+                }
+                return;
+            }
+        }
+        let mut inferior = self.inferior.take().unwrap();
+        self.relocate_program(&inferior);
+        self.set_startup_breakpoint(&mut inferior);
+        self.inferior = Some(inferior);
+        self.update_shared_libraries();
+        self.resolve_breakpoints();
+    }
+
+    /// Moves the program's symbols to where the inferior has loaded it, which differs from run
+    /// to run for position-independent executables.
+    fn relocate_program(&mut self, inferior: &Inferior) {
+        if let Some(bias) = inferior.load_bias(&self.debug_data) {
+            self.debug_data.set_bias(bias);
+        }
+    }
+
+    /// Stops the new inferior at the program's entry point, by which time the dynamic linker has
+    /// loaded the libraries the program was linked with.
+    fn set_startup_breakpoint(&self, inferior: &mut Inferior) {
+        let entry_point = self.debug_data.entry_point();
+        if let Err(err) = inferior.set_library_breakpoint(entry_point, false) {
+            println!("Failed to set breakpoint at {:#x}: {}", entry_point, err);
+        }
+    }
+
+    /// Returns true if the inferior trapped at the breakpoint that marks library changes, rather
+    /// than at one of the user's.
+    fn at_library_breakpoint(&self, rip: usize) -> bool {
+        let inferior = match self.inferior {
+            Some(ref inferior) => inferior,
+            None => return false,
+        };
+        inferior.library_breakpoint() == Some(rip)
+            && !self.breakpoints.iter().any(|bp| bp.addr == Some(rip))
+    }
+
+    /// Brings the list of shared libraries up to date after the inferior stopped, resolving the
+    /// breakpoints again if it changed. Once the dynamic linker has set up its r_brk function
+    /// (which it calls after loading or unloading libraries), the library breakpoint is moved
+    /// there from the entry point.
+    fn update_shared_libraries(&mut self) {
+        let inferior = match self.inferior {
+            Some(ref mut inferior) => inferior,
+            None => return,
+        };
+        let entry_point = self.debug_data.entry_point();
+        if inferior
+            .library_breakpoint()
+            .is_none_or(|addr| addr == entry_point)
+        {
+            let r_brk = self
+                .debug_data
+                .dynamic_address()
+                .and_then(|addr| shared_library::dynamic_linker_breakpoint(&*inferior, addr));
+            if let Some(r_brk) = r_brk {
+                let keep_old = self
+                    .breakpoints
+                    .iter()
+                    .any(|bp| bp.addr == Some(entry_point));
+                if let Err(err) = inferior.set_library_breakpoint(r_brk, keep_old) {
+                    println!("Failed to set breakpoint at {:#x}: {}", r_brk, err);
+                }
+            }
+        }
+        let mapped = shared_library::mapped_libraries(inferior.pid());
+        if self.debug_data.update_libraries(mapped) {
+            self.resolve_breakpoints();
+        }
+    }
+
+    /// Works out the breakpoints' addresses again, now that the shared libraries have changed,
+    /// and installs them. Breakpoints whose locations can't be found are left pending.
+    fn resolve_breakpoints(&mut self) {
+        let old_addrs = self.breakpoint_addrs();
+        for i in 0..self.breakpoints.len() {
+            self.breakpoints[i].addr = self.parse_location(&self.breakpoints[i].location);
+        }
+        let new_addrs = self.breakpoint_addrs();
+        let inferior = match self.inferior {
+            Some(ref mut inferior) => inferior,
+            None => return,
+        };
+        for &addr in old_addrs.iter().filter(|addr| !new_addrs.contains(addr)) {
+            if inferior.library_breakpoint() != Some(addr) {
+                // Fails if the library has been unmapped, which is fine
+                let _ = inferior.remove_breakpoint(addr);
+            }
+        }
+        for addr in new_addrs {
+            if let Err(err) = inferior.set_breakpoint(addr) {
+                println!("Failed to set breakpoint at {:#x}: {}", addr, err);
+            }
+        }
+    }
+
+    /// Implements `info sharedlibrary`, showing where each library's code is.
+    fn print_shared_libraries(&self) {
+        let libraries = self.debug_data.libraries();
+        if libraries.is_empty() {
+            println!("No shared libraries loaded at this time.");
+            return;
+        }
+        println!(
+            "{:<20}{:<20}{:<12}Shared Object Library",
+            "From", "To", "Syms Read"
+        );
+        let mut missing_debug_info = false;
+        for library in libraries {
+            let (from, to, syms_read) = match library.debug_data {
+                Some(ref data) => {
+                    let (from, to) = data.text_range();
+                    if data.has_debug_info() {
+                        (from, to, "Yes")
+                    } else {
+                        missing_debug_info = true;
+                        (from, to, "Yes (*)")
+                    }
+                }
+                None => (library.start, library.end, "No"),
+            };
+            println!(
+                "{:<20}{:<20}{:<12}{}",
+                format!("{:#018x}", from),
+                format!("{:#018x}", to),
+                syms_read,
+                library.path
+            );
+        }
+        if missing_debug_info {
+            println!("(*): Shared library is missing debugging information.");
+        }
+    }
+
+    /// Kills (or detaches from, if attached to) all processes being debugged.
+    fn discard_inferiors(&mut self) {
+        let others = std::mem::take(&mut self.other_inferiors);
+        let inferiors = self
+            .inferior
+            .take()
+            .into_iter()
+            .chain(others.into_iter().map(|(_, inferior)| inferior));
+        for mut inferior in inferiors {
+            if inferior.is_attached() {
+                let _ = inferior.detach();
+            } else {
+                // Batch output stays the same from run to run
+                if !self.batch {
+                    println!("Killing running inferior (pid {})", inferior.pid());
+                }
+                inferior.kill();
+            }
+        }
+        self.inferior_num = 1;
+        self.next_inferior_num = 2;
+    }
+
+    /// Removes all watchpoints from the current inferior's debug registers, along with the
+    /// breakpoints that mark the end of their frames, and forgets them.
+    fn delete_watchpoints(&mut self) {
+        if let Some(ref mut inferior) = self.inferior {
+            for watchpoint in &self.watchpoints {
+                for &slot in &watchpoint.slots {
+                    let _ = inferior.remove_watchpoint(slot);
+                }
+                // A user or library breakpoint may be at the same address
+                if let Some((return_addr, _)) = watchpoint.scope {
+                    let shared = self
+                        .breakpoints
+                        .iter()
+                        .any(|bp| bp.addr == Some(return_addr))
+                        || inferior.library_breakpoint() == Some(return_addr);
+                    if !shared {
+                        let _ = inferior.remove_breakpoint(return_addr);
+                    }
+                }
+            }
+        }
+        self.watchpoints.clear();
+    }
+
+    /// Mentions it when a stop happened in a different thread than the previous one.
+    fn announce_thread_switch(&mut self) {
+        let inferior = self.inferior.as_ref().unwrap();
+        let thread = inferior.current_thread();
+        let shown = (inferior.pid(), thread.num);
+        if let Some((pid, num)) = self.shown_thread {
+            if pid == shown.0 && num != shown.1 {
+                println!("[Switching to thread {} (LWP {})]", thread.num, thread.tid);
+            }
+        }
+        self.shown_thread = Some(shown);
+    }
+
+    fn print_threads(&self) {
+        let inferior = match self.inferior {
+            Some(ref inferior) => inferior,
+            None => {
+                println!("No threads.");
+                return;
+            }
+        };
+        println!("  {:<5}{:<28}Frame", "Id", "Target Id");
+        for thread in inferior.threads() {
+            let name =
+                fs::read_to_string(format!("/proc/{}/task/{}/comm", inferior.pid(), thread.tid))
+                    .unwrap_or_default();
+            let rip = inferior.thread_rip(thread.tid);
+            let frame = match (
+                self.debug_data.get_function_from_addr(rip),
+                self.debug_data.get_line_from_addr(rip),
+            ) {
+                (Some(function), Some(line)) => format!("{} ({})", function, line),
+                (Some(function), None) => format!("{} ({:#x})", function, rip),
+                (None, _) => format!("{:#x}", rip),
+            };
+            println!(
+                "{} {:<5}{:<27} {}",
+                if thread.tid == inferior.tid() {
+                    "*"
+                } else {
+                    " "
+                },
+                thread.num,
+                format!("LWP {} \"{}\"", thread.tid, name.trim_end()),
+                frame
+            );
+        }
+    }
+
+    /// Implements `thread [<num>]`: selects a thread for the commands that look at registers and
+    /// the stack, or shows which one is selected.
+    fn select_thread(&mut self, num: Option<usize>) {
+        let inferior = match self.inferior {
+            Some(ref mut inferior) => inferior,
+            None => {
+                println!("There has no child.");
+                return;
+            }
+        };
+        let num = match num {
+            Some(num) => num,
+            None => {
+                let thread = inferior.current_thread();
+                println!("[Current thread is {} (LWP {})]", thread.num, thread.tid);
+                return;
+            }
+        };
+        if !inferior.select_thread(num) {
+            println!("Invalid thread ID: {}", num);
+            return;
+        }
+        let (tid, pid) = (inferior.tid(), inferior.pid());
+        println!("[Switching to thread {} (LWP {})]", num, tid);
+        self.shown_thread = Some((pid, num));
+        let rip = inferior.rip();
+        self.print_stop_location(rip);
+    }
+
+    /// Implements `handle <signal>|all [keywords]`, which changes what happens when the inferior
+    /// receives a signal. `all` leaves out SIGTRAP and SIGINT, which the debugger relies on.
+    fn handle(&mut self, name: &str, keywords: &[String]) {
+        let signals: Vec<Signal> = if name == "all" {
+            Signal::iterator()
+                .filter(|&signal| signal != Signal::SIGTRAP && signal != Signal::SIGINT)
+                .collect()
+        } else {
+            match signals::parse_signal(name) {
+                Some(signal) => vec![signal],
+                None => {
+                    println!("Unrecognized signal: {}", name);
+                    return;
+                }
+            }
+        };
+        let keywords: Vec<&str> = keywords.iter().map(|keyword| keyword.as_str()).collect();
+        for &signal in &signals {
+            if let Err(err) = self.signals.update(signal, &keywords) {
+                println!("{}", err);
+                return;
+            }
+        }
+        self.update_inferior_settings();
+        self.signals.print(&signals);
+    }
+
+    fn print_signals(&self, name: Option<String>) {
+        let signals: Vec<Signal> = match name {
+            Some(name) => match signals::parse_signal(&name) {
+                Some(signal) => vec![signal],
+                None => {
+                    println!("Unrecognized signal: {}", name);
+                    return;
+                }
+            },
+            None => Signal::iterator().collect(),
+        };
+        self.signals.print(&signals);
+    }
+
+    /// Implements `signal <signal>`: continues the inferior, delivering the given signal instead
+    /// of the one it stopped with. `signal 0` continues without a signal.
+    fn continue_with_signal(&mut self, name: &str) {
+        let signal = if name == "0" {
+            None
+        } else {
+            match signals::parse_signal(name) {
+                Some(signal) => Some(signal),
+                None => {
+                    println!("Unrecognized signal: {}", name);
+                    return;
+                }
+            }
+        };
+        let inferior = match self.inferior {
+            Some(ref mut inferior) => inferior,
+            None => {
+                println!("The program is not being run.");
+                return;
+            }
+        };
+        inferior.set_pending_signal(signal);
+        match signal {
+            Some(signal) => println!("Continuing with signal {}.", signal),
+            None => println!("Continuing with no signal."),
+        }
+        self.continue_inferior();
+    }
+
+    fn print_inferiors(&self) {
+        let mut inferiors: Vec<(usize, &Inferior)> = self
+            .other_inferiors
+            .iter()
+            .map(|(num, inferior)| (*num, inferior))
+            .collect();
+        if let Some(ref inferior) = self.inferior {
+            inferiors.push((self.inferior_num, inferior));
+        }
+        if inferiors.is_empty() {
+            println!("No inferiors.");
+            return;
+        }
+        inferiors.sort_by_key(|(num, _)| *num);
+        println!("  {:<5}{:<18}Executable", "Num", "Description");
+        for (num, inferior) in inferiors {
+            let exe = fs::read_link(format!("/proc/{}/exe", inferior.pid()))
+                .map(|path| path.to_string_lossy().to_string())
+                .unwrap_or_default();
+            println!(
+                "{} {:<5}{:<18}{}",
+                if self.inferior.is_some() && num == self.inferior_num {
+                    "*"
+                } else {
+                    " "
+                },
+                num,
+                format!("process {}", inferior.pid()),
+                exe
+            );
+        }
+    }
+
+    /// Makes another traced process the current inferior. The current one stays stopped.
+    fn switch_inferior(&mut self, num: usize) {
+        if let Some(inferior) = self.inferior.as_ref().filter(|_| num == self.inferior_num) {
+            println!("[Current inferior is {} (process {})]", num, inferior.pid());
+            return;
+        }
+        let idx = match self
+            .other_inferiors
+            .iter()
+            .position(|(other_num, _)| *other_num == num)
+        {
+            Some(idx) => idx,
+            None => {
+                println!("Inferior ID {} not known.", num);
+                return;
+            }
+        };
+        // Watchpoints are specific to a process
+        self.delete_watchpoints();
+        let (_, inferior) = self.other_inferiors.remove(idx);
+        if let Some(current) = self.inferior.replace(inferior) {
+            self.other_inferiors.push((self.inferior_num, current));
+        }
+        self.inferior_num = num;
+        let inferior = self.inferior.as_ref().unwrap();
+        println!(
+            "[Switching to inferior {} (process {})]",
+            num,
+            inferior.pid()
+        );
+        let rip = inferior.rip();
+        self.print_stop_location(rip);
+    }
+
+    /// Returns the addresses of all breakpoints, to be installed in a new inferior.
+    fn breakpoint_addrs(&self) -> Vec<usize> {
+        self.breakpoints.iter().filter_map(|bp| bp.addr).collect()
+    }
+
+    /// Decides whether to stop at the breakpoints at rip, which the inferior just trapped at (see
+    /// the breakpoint_hit function).
+    fn breakpoint_hit(&mut self, rip: usize) -> bool {
+        match self.inferior {
+            Some(ref inferior) => breakpoint_hit(
+                &mut self.breakpoints,
+                &mut self.pending_commands,
+                &self.debug_data,
+                inferior,
+                rip,
+            ),
+            None => true,
+        }
+    }
+
+    /// Makes the next count hits of breakpoint or watchpoint num resume without stopping.
+    fn ignore(&mut self, num: usize, count: usize) {
+        let ignore_count = if let Some(bp) = self.breakpoints.iter_mut().find(|bp| bp.num == num) {
+            &mut bp.ignore_count
+        } else if let Some(watchpoint) = self
+            .watchpoints
+            .iter_mut()
+            .find(|watchpoint| watchpoint.num == num)
+        {
+            &mut watchpoint.ignore_count
+        } else {
+            println!("No breakpoint number {}.", num);
+            return;
+        };
+        *ignore_count = count;
+        match count {
+            0 => println!("Will stop next time breakpoint {} is reached.", num),
+            1 => println!("Will ignore next crossing of breakpoint {}.", num),
+            _ => println!(
+                "Will ignore next {} crossings of breakpoint {}.",
+                count, num
+            ),
+        }
+    }
+
+    /// Lists breakpoints and watchpoints in the order they were created.
+    fn print_breakpoints(&self) {
+        if self.breakpoints.is_empty() && self.watchpoints.is_empty() {
+            println!("No breakpoints or watchpoints.");
+            return;
+        }
+        let mut rows = Vec::new();
+        for bp in &self.breakpoints {
+            let (addr, what) = match bp.addr {
+                Some(addr) => (
+                    format!("{:#018x}", addr),
+                    match (
+                        self.debug_data.get_function_from_addr(addr),
+                        self.debug_data.get_line_from_addr(addr),
+                    ) {
+                        (Some(func), Some(line)) => format!("in {} at {}", func, line),
+                        (Some(func), None) => format!("in {}", func),
+                        _ => String::new(),
+                    },
+                ),
+                None => ("<PENDING>".to_string(), bp.location.clone()),
+            };
+            rows.push(BreakpointRow {
+                num: bp.num,
+                kind: "breakpoint",
+                addr,
+                what,
+                condition: bp
+                    .condition
+                    .as_ref()
+                    .map(|(condition, _)| condition.as_str()),
+                hit_count: bp.hit_count,
+                ignore_count: bp.ignore_count,
+            });
+        }
+        for watchpoint in &self.watchpoints {
+            rows.push(BreakpointRow {
+                num: watchpoint.num,
+                kind: match watchpoint.kind {
+                    WatchKind::Write => "hw watchpoint",
+                    WatchKind::ReadWrite => "read watchpoint",
+                },
+                addr: String::new(),
+                what: watchpoint.expression.clone(),
+                condition: None,
+                hit_count: watchpoint.hit_count,
+                ignore_count: watchpoint.ignore_count,
+            });
+        }
+        rows.sort_by_key(|row| row.num);
+
+        println!("{:<7} {:<15} {:<18} What", "Num", "Type", "Address");
+        for row in rows {
+            println!(
+                "{:<7} {:<15} {:<18} {}",
+                row.num, row.kind, row.addr, row.what
+            );
+            if let Some(condition) = row.condition {
+                println!("\tstop only if {}", condition);
+            }
+            match row.hit_count {
+                0 => {}
+                1 => println!("\tbreakpoint already hit 1 time"),
+                _ => println!("\tbreakpoint already hit {} times", row.hit_count),
+            }
+            if row.ignore_count > 0 {
+                println!(
+                    "\tWill ignore next {} crossings of breakpoint.",
+                    row.ignore_count
+                );
+            }
+            if let Some(bp) = self.breakpoints.iter().find(|bp| bp.num == row.num) {
+                for line in &bp.commands {
+                    println!("\t  {}", line);
+                }
+            }
+        }
+    }
+
+    /// Sets a hardware watchpoint on the value of an expression, which must live in memory.
+    fn set_watchpoint(&mut self, input: &str, kind: WatchKind) {
+        let inferior = self.inferior.as_mut().unwrap();
+        let evaluator = Evaluator::new(&self.debug_data, inferior);
+        let value = match expression::parse(input).and_then(|expr| evaluator.evaluate(&expr)) {
+            Ok(value) => value,
+            Err(err) => {
+                println!("{}", err);
+                return;
+            }
+        };
+        let addr = match value.address {
+            Some(addr) if !value.bytes.is_empty() => addr,
+            _ => {
+                println!("Cannot watch constant value `{}'.", input);
+                return;
+            }
+        };
+
+        let mut slots = Vec::new();
+        for (chunk_addr, len) in watch_chunks(addr, value.bytes.len()) {
+            let error = match inferior.set_watchpoint(chunk_addr, len, kind) {
+                Ok(Some(slot)) => {
+                    slots.push(slot);
+                    continue;
+                }
+                Ok(None) => format!(
+                    "Not enough hardware debug registers to watch {} ({} bytes).",
+                    input,
+                    value.bytes.len()
+                ),
+                Err(err) => format!("Failed to set watchpoint: {}", err),
+            };
+            println!("{}", error);
+            for &slot in &slots {
+                let _ = inferior.remove_watchpoint(slot);
+            }
+            return;
+        }
+
+        // Values between rsp (allowing for the red zone) and the CFA live in the current frame.
+        // A temporary breakpoint at the return address tells us when the frame goes away.
+        let mut scope = None;
+        if let (Ok(regs), Ok(cfa)) = (inferior.get_registers(), inferior.get_cfa(&self.debug_data))
+        {
+            if addr + STACK_RED_ZONE >= regs.rsp as usize && addr < cfa {
+                let return_addr = inferior
+                    .read_memory(cfa - 8, 8)
+                    .map(|bytes| read_unsigned(&bytes) as usize);
+                if let Ok(return_addr) = return_addr {
+                    if inferior.set_breakpoint(return_addr).is_ok() {
+                        scope = Some((return_addr, cfa));
+                    }
+                }
+            }
+        }
+
+        let num = self.next_breakpoint_num;
+        self.next_breakpoint_num += 1;
+        match kind {
+            WatchKind::Write => println!("Hardware watchpoint {}: {}", num, input),
+            WatchKind::ReadWrite => println!("Hardware read watchpoint {}: {}", num, input),
+        }
+        self.watchpoints.push(Watchpoint {
+            num,
+            expression: input.to_string(),
+            entity_type: value.entity_type,
+            addr,
+            kind,
+            slots,
+            old_value: value.bytes,
+            hit_count: 0,
+            ignore_count: 0,
+            scope,
+        });
+    }
+
+    /// Works out whether watchpoints made the inferior stop, returning None if not. Otherwise
+    /// returns a report for each watchpoint that should be announced: write watchpoints whose
+    /// value changed, read watchpoints whose value didn't (reads can't be told apart from writes
+    /// in hardware), and watchpoints that were deleted because their frame returned.
+    fn check_watchpoints(&mut self) -> Option<Vec<String>> {
+        let inferior = self.inferior.as_mut()?;
+        let hits = inferior.take_watchpoint_hits().ok()?;
+        let regs = inferior.get_registers().ok()?;
+        let (rip, rsp) = (regs.rip as usize, regs.rsp as usize);
+        // Whether the stop was caused by us rather than a user breakpoint
+        let mut watch_stop = !hits.is_empty();
+        let mut reports = Vec::new();
+
+        let inferior = &*inferior;
+        let debug_data = &self.debug_data;
+        let read_memory = |addr, len| inferior.read_memory(addr, len);
+        for watchpoint in self.watchpoints.iter_mut() {
+            if !watchpoint.slots.iter().any(|slot| hits.contains(slot)) {
+                continue;
+            }
+            let new_value = match inferior.read_memory(watchpoint.addr, watchpoint.old_value.len())
+            {
+                Ok(new_value) => new_value,
+                Err(_) => continue,
+            };
+            let format = |bytes: &[u8]| {
+                format_value(debug_data, &watchpoint.entity_type, bytes, &read_memory)
+            };
+            let report = match watchpoint.kind {
+                WatchKind::Write if new_value != watchpoint.old_value => format!(
+                    "\nHardware watchpoint {}: {}\n\nOld value = {}\nNew value = {}",
+                    watchpoint.num,
+                    watchpoint.expression,
+                    format(&watchpoint.old_value),
+                    format(&new_value)
+                ),
+                WatchKind::ReadWrite if new_value == watchpoint.old_value => format!(
+                    "\nHardware read watchpoint {}: {}\n\nValue = {}",
+                    watchpoint.num,
+                    watchpoint.expression,
+                    format(&new_value)
+                ),
+                _ => {
+                    watchpoint.old_value = new_value;
+                    continue;
+                }
+            };
+            watchpoint.hit_count += 1;
+            if watchpoint.ignore_count > 0 {
+                watchpoint.ignore_count -= 1;
+            } else {
+                reports.push(report);
+            }
+            watchpoint.old_value = new_value;
+        }
+
+        // Returning to the frame's caller pops the return address, leaving rsp at the CFA. (A
+        // lower rsp means a deeper recursive call returned to the same address.)
+        let scope_ends_here = |watchpoint: &Watchpoint| {
+            watchpoint
+                .scope
+                .is_some_and(|(return_addr, _)| return_addr == rip)
+        };
+        let user_breakpoint = self.breakpoints.iter().any(|bp| bp.addr == Some(rip));
+        if self.watchpoints.iter().any(scope_ends_here) && !user_breakpoint {
+            watch_stop = true;
+        }
+        let (deleted, kept): (Vec<Watchpoint>, Vec<Watchpoint>) =
+            self.watchpoints.drain(..).partition(|watchpoint| {
+                scope_ends_here(watchpoint) && rsp >= watchpoint.scope.unwrap().1
+            });
+        self.watchpoints = kept;
+        let inferior = self.inferior.as_mut()?;
+        for watchpoint in &deleted {
+            for &slot in &watchpoint.slots {
+                let _ = inferior.remove_watchpoint(slot);
+            }
+            reports.push(format!(
+                "\nWatchpoint {} deleted because the program has left the block in\nwhich its \
+                 expression is valid.",
+                watchpoint.num
+            ));
+        }
+        if !deleted.is_empty() && !user_breakpoint && !self.watchpoints.iter().any(scope_ends_here)
+        {
+            let _ = inferior.remove_breakpoint(rip);
+        }
+
+        if watch_stop {
+            Some(reports)
+        } else {
+            None
+        }
+    }
+
+    /// Prints the general-purpose registers, or just the named ones, as hex and in their natural
+    /// format: decimal, or the symbol for code and decoded flags for eflags.
+    fn print_registers(&self, names: &[String]) {
+        let target = match self.target() {
+            Some(target) => target,
+            None => {
+                println!("The program has no registers now.");
+                return;
+            }
+        };
+        let regs = match target.get_registers() {
+            Ok(regs) => regs,
+            Err(err) => {
+                println!("Failed to read registers: {}", err);
+                return;
+            }
+        };
+        let names: Vec<&str> = if names.is_empty() {
+            registers::REGISTER_NAMES.to_vec()
+        } else {
+            names
+                .iter()
+                .map(|name| name.trim_start_matches('$'))
+                .collect()
+        };
+        for name in names {
+            let value = match registers::register_by_name(&regs, name) {
+                Some(value) => value,
+                None => {
+                    println!("Invalid register `{}'", name);
+                    return;
+                }
+            };
+            let natural = match name {
+                "rip" | "pc" => format!("{:#x}{}", value, self.symbolize(value as usize)),
+                "rsp" | "sp" | "rbp" | "fp" | "fs_base" | "gs_base" => format!("{:#x}", value),
+                "eflags" => registers::format_eflags(value),
+                _ => (value as i64).to_string(),
+            };
+            println!("{:<15}{:<19}{}", name, format!("{:#x}", value), natural);
+        }
+    }
+
+    /// Prints the x87 stack and control registers, followed by the SSE registers.
+    fn print_float_registers(&self) {
+        let target = match self.target() {
+            Some(target) => target,
+            None => {
+                println!("The program has no registers now.");
+                return;
+            }
+        };
+        let fpregs = match target.get_fpregs() {
+            Ok(fpregs) => fpregs,
+            Err(err) => {
+                println!("Failed to read floating point registers: {}", err);
+                return;
+            }
+        };
+        for i in 0..8 {
+            let bytes = registers::st_bytes(&fpregs, i);
+            let raw: String = bytes.iter().rev().map(|b| format!("{:02x}", b)).collect();
+            println!(
+                "{:<15}{:<19}(raw 0x{})",
+                format!("st{}", i),
+                format_float(x87_extended_to_f64(&bytes)),
+                raw
+            );
+        }
+        let control: &[(&str, u64)] = &[
+            ("fctrl", fpregs.cwd as u64),
+            ("fstat", fpregs.swd as u64),
+            ("ftag", fpregs.ftw as u64),
+            ("fop", fpregs.fop as u64),
+            ("fip", fpregs.rip),
+            ("fdp", fpregs.rdp),
+            ("mxcsr", fpregs.mxcsr as u64),
+        ];
+        for (name, value) in control {
+            println!("{:<15}{:#x}", name, value);
+        }
+        for i in 0..16 {
+            let bytes = registers::xmm_bytes(&fpregs, i);
+            let floats: Vec<String> = bytes
+                .chunks(4)
+                .map(|chunk| format_float(f32::from_bits(read_unsigned(chunk) as u32)))
+                .collect();
+            let doubles: Vec<String> = bytes
+                .chunks(8)
+                .map(|chunk| format_float(f64::from_bits(read_unsigned(chunk))))
+                .collect();
+            let raw: String = bytes.iter().rev().map(|b| format!("{:02x}", b)).collect();
+            println!(
+                "{:<15}{{v4_float = {{{}}}, v2_double = {{{}}}, uint128 = 0x{}}}",
+                format!("xmm{}", i),
+                floats.join(", "),
+                doubles.join(", "),
+                raw
+            );
+        }
+    }
+
+    /// Implements `set [var] <lvalue> = <expression>`. The lvalue is either a register such as
+    /// `$rip`, or an expression denoting memory such as `x`, `p->next` or `*0x404018`. Also
+    /// handles the settings `follow-fork-mode parent|child` and `detach-on-fork on|off`.
+    fn set(&mut self, input: &str) {
+        let input = input.trim_start();
+        let words: Vec<&str> = input.split_whitespace().collect();
+        match words[..] {
+            ["follow-fork-mode", mode] => {
+                match mode {
+                    "parent" => self.follow_fork_child = false,
+                    "child" => self.follow_fork_child = true,
+                    _ => println!("Undefined item: \"{}\".", mode),
+                }
+                self.update_inferior_settings();
+                return;
+            }
+            ["detach-on-fork", setting] => {
+                match setting {
+                    "on" => self.detach_on_fork = true,
+                    "off" => self.detach_on_fork = false,
+                    _ => println!("\"on\" or \"off\" expected."),
+                }
+                self.update_inferior_settings();
+                return;
+            }
+            _ => {}
+        }
+        let input = input
+            .strip_prefix("var ")
+            .or_else(|| input.strip_prefix("variable "))
+            .unwrap_or(input);
+        let (lhs, rhs) = match split_assignment(input) {
+            Some(parts) => parts,
+            None => {
+                println!("Expected an assignment of the form `set var <variable> = <value>'.");
+                return;
+            }
+        };
+        if self.inferior.is_none() {
+            println!("There has no child.");
+            return;
+        }
+        let result = match lhs.strip_prefix('$') {
+            Some(register) => self.set_register(register, rhs),
+            None => self.set_variable(lhs, rhs),
+        };
+        if let Err(err) = result {
+            println!("{}", err);
+        }
+    }
+
+    fn set_register(&self, name: &str, rhs: &str) -> Result<(), String> {
+        let inferior = self.inferior.as_ref().unwrap();
+        let evaluator = Evaluator::new(&self.debug_data, inferior);
+        let value = expression::parse(rhs).and_then(|expr| evaluator.evaluate(&expr))?;
+        let value = value_as_integer(&self.debug_data, &value)
+            .ok_or_else(|| "Registers can only be set to integer values.".to_string())?;
+        let mut regs = inferior
+            .get_registers()
+            .map_err(|err| format!("Failed to read registers: {}", err))?;
+        match registers::register_mut(&mut regs, name) {
+            Some(register) => *register = value as u64,
+            None => return Err(format!("Invalid register `{}'", name)),
+        }
+        inferior
+            .set_registers(regs)
+            .map_err(|err| format!("Failed to set ${}: {}", name, err))
+    }
+
+    /// Converts the value of rhs to the type of lhs and writes it to lhs's memory.
+    fn set_variable(&mut self, lhs: &str, rhs: &str) -> Result<(), String> {
+        let (addr, bytes) = {
+            let inferior = self.inferior.as_ref().unwrap();
+            let evaluator = Evaluator::new(&self.debug_data, inferior);
+            evaluator.assignment(&expression::parse(lhs)?, &expression::parse(rhs)?)?
+        };
+        self.inferior
+            .as_mut()
+            .unwrap()
+            .write_memory(addr, &bytes)
+            .map_err(|_| format!("Cannot access memory at address {:#x}", addr))
+    }
+
+    /// Prints ten lines of source. With no location, continues after the previous listing, or
+    /// lists around where the inferior is stopped (or main if it isn't running). The current
+    /// line is marked with `=>` and lines with breakpoints with `B`.
+    fn list(&mut self, location: Option<String>) {
+        let (file, center) = match location {
+            Some(location) => match self.resolve_list_location(&location) {
+                Ok(position) => position,
+                Err(err) => {
+                    println!("{}", err);
+                    return;
+                }
+            },
+            None => match self.list_position.take() {
+                // Continuing: start right after the previous listing
+                Some((file, next)) => (file, next + LIST_LINES / 2),
+                None => {
+                    let addr = match self.target() {
+                        Some(target) => Some(target.rip()),
+                        None => self.debug_data.get_addr_for_function(None, "main"),
+                    };
+                    match addr.and_then(|addr| self.debug_data.get_line_from_addr(addr)) {
+                        Some(line) => (line.file, line.number),
+                        None => {
+                            println!("No source information for the current location.");
+                            return;
+                        }
+                    }
+                }
+            },
+        };
+        let first = center.saturating_sub(LIST_LINES / 2).max(1);
+
+        let current_line = self
+            .target()
+            .and_then(|target| self.debug_data.get_line_from_addr(target.rip()))
+            .filter(|line| line.file == file)
+            .map(|line| line.number);
+        let breakpoint_lines: Vec<usize> = self
+            .breakpoints
+            .iter()
+            .filter_map(|bp| self.debug_data.get_line_from_addr(bp.addr?))
+            .filter(|line| line.file == file)
+            .map(|line| line.number)
+            .collect();
+        let lines = match self.sources.get_lines(&file) {
+            Some(lines) => lines,
+            None => {
+                println!(
+                    "{}: No such file or directory (searched {}).",
+                    file,
+                    self.sources.search_path()
+                );
+                return;
+            }
+        };
+        if first > lines.len() {
+            println!(
+                "Line number {} out of range; \"{}\" has {} lines.",
+                first,
+                file,
+                lines.len()
+            );
+            return;
+        }
+
+        let last = (first + LIST_LINES - 1).min(lines.len());
+        for number in first..=last {
+            println!(
+                "{:2}{:2}{:<6}{}",
+                if Some(number) == current_line {
+                    "=>"
+                } else {
+                    ""
+                },
+                if breakpoint_lines.contains(&number) {
+                    "B"
+                } else {
+                    ""
+                },
+                number,
+                lines[number - 1]
+            );
+        }
+        self.list_position = Some((file, last + 1));
+    }
+
+    /// Resolves the argument of `list` to a file name and line number. Accepts `<file>:<line>`,
+    /// `<line>` (in the file being listed or stopped in) or a function name.
+    fn resolve_list_location(&self, location: &str) -> Result<(String, usize), String> {
+        if let Some(idx) = location.rfind(':') {
+            let number = location[idx + 1..]
+                .parse::<usize>()
+                .map_err(|_| format!("Invalid line number \"{}\".", &location[idx + 1..]))?;
+            let file = self
+                .full_file_name(Some(&location[..idx]))
+                .ok_or_else(|| format!("No source file named {}.", &location[..idx]))?;
+            return Ok((file, number));
+        }
+        if let Ok(number) = location.parse::<usize>() {
+            let file = match self.list_position {
+                Some((ref file, _)) => Some(file.clone()),
+                None => self
+                    .target()
+                    .and_then(|target| self.debug_data.get_line_from_addr(target.rip()))
+                    .map(|line| line.file),
+            };
+            let file = file
+                .or_else(|| self.full_file_name(None))
+                .ok_or_else(|| "No symbol table is loaded.".to_string())?;
+            return Ok((file, number));
+        }
+        self.debug_data
+            .get_addr_for_function(None, location)
+            .and_then(|addr| self.debug_data.get_line_from_addr(addr))
+            .map(|line| (line.file, line.number))
+            .ok_or_else(|| format!("Function \"{}\" not defined.", location))
+    }
+
+    /// Returns the name of a source file as the line table reports it (which includes the
+    /// compilation directory), given a file name as accepted by DwarfData::get_file_name.
+    fn full_file_name(&self, file: Option<&str>) -> Option<String> {
+        let name = self.debug_data.get_file_name(file)?;
+        Some(
+            self.debug_data
+                .get_addr_for_line(Some(name), 0)
+                .and_then(|addr| self.debug_data.get_line_from_addr(addr))
+                .map(|line| line.file)
+                .unwrap_or_else(|| name.to_string()),
+        )
+    }
+
+    /// Prints the instructions of a function (by default, the one containing rip), preceded by
+    /// the source line they were compiled from.
+    fn disassemble(&self, function: Option<String>) {
+        let rip = self.target().map(|target| target.rip());
+        let (name, func_addr, len) = match function {
+            Some(name) => match self
+                .debug_data
+                .get_addr_for_function(None, &name)
+                .and_then(|addr| self.debug_data.get_function_bounds(addr))
+            {
+                Some(bounds) => bounds,
+                None => {
+                    println!("No symbol \"{}\" in current context.", name);
+                    return;
+                }
+            },
+            None => match rip {
+                Some(rip) => match self.debug_data.get_function_bounds(rip) {
+                    Some(bounds) => bounds,
+                    None => {
+                        println!("No function contains program counter for selected frame.");
+                        return;
+                    }
+                },
+                None => {
+                    println!("No frame selected.");
+                    return;
+                }
+            },
+        };
+        let code = match self.read_code(func_addr, len) {
+            Ok(code) => code,
+            Err(err) => {
+                println!("{}", err);
+                return;
+            }
+        };
+
+        println!("Dump of assembler code for function {}:", name);
+        let mut last_line_number = None;
+        for instruction in disassembler::disassemble(&code, func_addr) {
+            if self.debug_data.is_line_start(instruction.address) {
+                if let Some(line) = self.debug_data.get_line_from_addr(instruction.address) {
+                    if last_line_number != Some(line.number) {
+                        println!("{}", line);
+                        last_line_number = Some(line.number);
+                    }
+                }
+            }
+            println!(
+                "{} {:#018x} <+{}>:\t{}",
+                if Some(instruction.address) == rip {
+                    "=>"
+                } else {
+                    "  "
+                },
+                instruction.address,
+                instruction.address - func_addr,
+                instruction.text
+            );
+        }
+        println!("End of assembler dump.");
+    }
+
+    /// Implements `x/<count><format><unit> <expression>`, which shows memory at the address an
+    /// expression evaluates to. As in gdb, the format and unit default to the ones used last.
+    fn examine(&mut self, format: &str, input: &str) {
+        if self.target().is_none() {
+            println!("There has no child.");
+            return;
+        }
+        let (count, letter, unit) = match parse_examine_format(format, self.examine_format) {
+            Ok(format) => format,
+            Err(err) => {
+                println!("{}", err);
+                return;
+            }
+        };
+        self.examine_format = (letter, unit);
+        let target = self.target().unwrap();
+        let addr = match (input, self.examine_next) {
+            ("", Some(addr)) => addr,
+            ("", None) => {
+                println!("Argument required (starting display address).");
+                return;
+            }
+            _ => match self.examine_address(target, input) {
+                Ok(addr) => addr,
+                Err(err) => {
+                    println!("{}", err);
+                    return;
+                }
+            },
+        };
+        let next = match letter {
+            'i' => self.examine_instructions(target, addr, count),
+            's' => self.examine_strings(target, addr, count),
+            _ => self.examine_units(target, addr, count, letter, unit),
+        };
+        self.examine_next = next.or(self.examine_next);
+    }
+
+    /// Works out the address to examine. Functions stand for their address, as do arrays and
+    /// structs (which don't fit in an address); anything else is evaluated and its value used.
+    fn examine_address(&self, target: &dyn Target, input: &str) -> Result<usize, String> {
+        if self.debug_data.get_variable(target.rip(), input).is_none() {
+            if let Some(addr) = self.debug_data.get_addr_for_function(None, input) {
+                return Ok(addr);
+            }
+        }
+        let evaluator = Evaluator::new(&self.debug_data, target);
+        let value = expression::parse(input).and_then(|expr| evaluator.evaluate(&expr))?;
+        match (value_as_integer(&self.debug_data, &value), value.address) {
+            (Some(addr), _) => Ok(addr as usize),
+            (None, Some(addr)) => Ok(addr),
+            (None, None) => Err(format!("Invalid address `{}'.", input)),
+        }
+    }
+
+    /// Prints count units of unit bytes each, several to a line. Returns the address after the
+    /// last unit, or None if the count is too large.
+    fn examine_units(
+        &self,
+        target: &dyn Target,
+        addr: usize,
+        count: usize,
+        letter: char,
+        unit: usize,
+    ) -> Option<usize> {
+        let per_line = match (letter, unit) {
+            ('c', _) | (_, 1) | (_, 2) => 8,
+            (_, 4) => 4,
+            _ => 2,
+        };
+        let len = match count.checked_mul(unit) {
+            Some(len) => len,
+            None => {
+                println!("Too many units to examine: {}.", count);
+                return None;
+            }
+        };
+        let bytes = target.read_memory_prefix(addr, len);
+        let mut line = String::new();
+        for i in 0..count {
+            let unit_addr = addr + i * unit;
+            if i % per_line == 0 {
+                if i > 0 {
+                    println!("{}", line);
+                }
+                line = format!("{:#x}{}:", unit_addr, self.symbolize(unit_addr));
+            }
+            if bytes.len() < (i + 1) * unit {
+                println!("{}\tCannot access memory at address {:#x}", line, unit_addr);
+                return Some(unit_addr);
+            }
+            line.push('\t');
+            line.push_str(&format_unit(letter, &bytes[i * unit..(i + 1) * unit]));
+        }
+        println!("{}", line);
+        Some(addr + len)
+    }
+
+    /// Prints count NUL-terminated strings, one after the other in memory. Returns the address
+    /// after the last one.
+    fn examine_strings(&self, target: &dyn Target, mut addr: usize, count: usize) -> Option<usize> {
+        for _ in 0..count {
+            let bytes = target.read_memory_prefix(addr, MAX_ELEMENTS + 1);
+            let (string, next_addr) = match bytes.iter().position(|&b| b == 0) {
+                Some(len) => (quote_c_string(&bytes[..len]), addr + len + 1),
+                None if bytes.len() > MAX_ELEMENTS => (
+                    format!("{}...", quote_c_string(&bytes[..MAX_ELEMENTS])),
+                    addr + MAX_ELEMENTS,
+                ),
+                None => {
+                    println!(
+                        "{:#x}{}:\t<error: Cannot access memory at address {:#x}>",
+                        addr,
+                        self.symbolize(addr),
+                        addr + bytes.len()
+                    );
+                    return Some(addr);
+                }
+            };
+            println!("{:#x}{}:\t{}", addr, self.symbolize(addr), string);
+            addr = next_addr;
+        }
+        Some(addr)
+    }
+
+    /// Disassembles count instructions, marking the one at rip. Returns the address after the
+    /// last one, or None if the count is too large.
+    fn examine_instructions(
+        &self,
+        target: &dyn Target,
+        addr: usize,
+        count: usize,
+    ) -> Option<usize> {
+        let len = match count.checked_mul(disassembler::MAX_INSTRUCTION_LEN) {
+            Some(len) => len,
+            None => {
+                println!("Too many instructions to examine: {}.", count);
+                return None;
+            }
+        };
+        let code = target.read_memory_prefix(addr, len);
+        let instructions = disassembler::disassemble(&code, addr);
+        for instruction in instructions.iter().take(count) {
+            println!(
+                "{} {:#x}{}:\t{}",
+                if instruction.address == target.rip() {
+                    "=>"
+                } else {
+                    "  "
+                },
+                instruction.address,
+                self.symbolize(instruction.address),
+                instruction.text
+            );
+        }
+        if instructions.len() < count {
+            println!("Cannot access memory at address {:#x}", addr + code.len());
+        }
+        // The code read holds more than count instructions unless memory ran out first
+        Some(
+            instructions
+                .get(count)
+                .map_or(addr + code.len(), |instruction| instruction.address),
+        )
+    }
+
+    /// Returns " <symbol+offset>" for an address in a function or global variable, or an empty
+    /// string if it isn't in one.
+    fn symbolize(&self, addr: usize) -> String {
+        match self.debug_data.get_symbol_for_addr(addr) {
+            Some((name, 0)) => format!(" <{}>", name),
+            Some((name, offset)) => format!(" <{}+{}>", name, offset),
+            None => String::new(),
+        }
+    }
+
+    /// Reads code from the inferior if it is running (with breakpoint instructions replaced by
+    /// the original bytes) or from the core dump, or otherwise from the executable.
+    fn read_code(&self, addr: usize, len: usize) -> Result<Vec<u8>, String> {
+        match self.target() {
+            Some(target) => target
+                .read_memory(addr, len)
+                .map_err(|_| format!("Cannot access memory at address {:#x}", addr)),
+            None => self
+                .debug_data
+                .read_text(addr, len)
+                .map(|code| code.to_vec())
+                .ok_or_else(|| format!("Cannot access memory at address {:#x}", addr)),
+        }
+    }
+
+    /// Prints the source location of rip, including the address if rip is in the middle of a
+    /// line.
+    fn print_stop_location(&mut self, rip: usize) {
+        self.list_position = None;
+        match self.debug_data.get_line_from_addr(rip) {
+            Some(line) if self.debug_data.is_line_start(rip) => println!("Stopped at {}", line),
+            Some(line) => println!("Stopped at {:#x} ({})", rip, line),
+            None => println!("Stopped at {:#x}{}", rip, self.symbolize(rip)),
+        }
+    }
+
+    /// Resolves a breakpoint location to an address. Accepts `*0x<address>`, `<file>:<line>`,
+    /// `<line>` (in the first compilation unit), or a function name, which may be a Rust or C++
+    /// path like `module::function`. Function breakpoints are placed after the prologue so that
+    /// the frame pointer is already set up when they are hit.
+    fn parse_location(&self, location: &str) -> Option<usize> {
+        if let Some(addr) = location.strip_prefix('*') {
+            return parse_address(addr);
+        }
+        if !is_function_location(location) {
+            if let Some(idx) = location.rfind(':') {
+                let line_number = location[idx + 1..].parse::<usize>().ok()?;
+                return self
+                    .debug_data
+                    .get_addr_for_line(Some(&location[..idx]), line_number);
+            }
+        }
+        if let Ok(line_number) = location.parse::<usize>() {
+            return self.debug_data.get_addr_for_line(None, line_number);
+        }
+        let func_addr = self.debug_data.get_addr_for_function(None, location)?;
+        Some(
+            self.debug_data
+                .get_addr_after_prologue(func_addr)
+                .unwrap_or(func_addr),
+        )
+    }
+
+    /// Implements `source <file>`: runs the commands in a file, one per line, before going back
+    /// to wherever commands were coming from.
+    fn source(&mut self, path: &str) {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(err) => {
+                match err.raw_os_error() {
+                    Some(code) => println!("{}: {}.", path, Errno::from_i32(code).desc()),
+                    None => println!("{}: {}.", path, err),
+                }
+                return;
+            }
+        };
+        for line in contents.lines().rev() {
+            self.pending_commands.push_front(line.to_string());
+        }
+    }
+
+    /// Implements `define <name>`: reads the lines up to `end` and makes them a new command. In
+    /// the lines, `$arg0`, `$arg1`, ... stand for the arguments it is invoked with and `$argc`
+    /// for their number.
+    fn define(&mut self, name: String) {
+        if debugger_command::is_builtin(&name) {
+            println!("Cannot redefine built-in command \"{}\".", name);
+            self.read_block();
+            return;
+        }
+        if self.at_prompt() {
+            println!(
+                "Type commands for definition of \"{}\".\nEnd with a line saying just \"end\".",
+                name
+            );
+        }
+        let body = self.read_block();
+        self.user_commands.insert(name, body);
+    }
+
+    /// Implements `commands [<num>]`: reads the lines up to `end`, which will run whenever the
+    /// breakpoint (by default the last one set) stops the inferior. No lines remove the commands.
+    fn set_breakpoint_commands(&mut self, num: Option<usize>) {
+        let num = match num.or_else(|| self.breakpoints.iter().map(|bp| bp.num).max()) {
+            Some(num) => num,
+            None => {
+                println!("No breakpoints specified.");
+                self.read_block();
+                return;
+            }
+        };
+        if !self.breakpoints.iter().any(|bp| bp.num == num) {
+            println!("No breakpoint number {}.", num);
+            self.read_block();
+            return;
+        }
+        if self.at_prompt() {
+            println!(
+                "Type commands for breakpoint {}, one per line.\nEnd with a line saying just \"end\".",
+                num
+            );
+        }
+        let commands = self.read_block();
+        self.breakpoints
+            .iter_mut()
+            .find(|bp| bp.num == num)
+            .unwrap()
+            .commands = commands;
+    }
+
+    /// Asks the user a yes or no question, defaulting to no. In batch mode, or when input is
+    /// piped in, there is no one to ask, so the answer is yes (rather than taking the next line
+    /// of input as the answer).
+    fn query(&mut self, question: &str) -> bool {
+        if self.batch || !unistd::isatty(libc::STDIN_FILENO).unwrap_or(false) {
+            println!(
+                "{} (y or [n]) [answered Y; input not from terminal]",
+                question
+            );
+            return true;
+        }
+        loop {
+            match self.readline.readline(&format!("{} (y or [n]) ", question)) {
+                Ok(answer) => match answer.trim() {
+                    "y" | "Y" | "yes" => return true,
+                    "" | "n" | "N" | "no" => return false,
+                    _ => println!("Please answer y or n."),
+                },
+                Err(_) => {
+                    println!("EOF [answered N; input not from terminal]");
+                    return false;
+                }
+            }
+        }
+    }
+
+    /// Whether the next line of input will be typed by the user, rather than come from a script.
+    fn at_prompt(&self) -> bool {
+        self.pending_commands.is_empty() && !self.batch
+    }
+
+    /// Reads the lines of a `define` or `commands` block, up to the `end` that closes it. Blocks
+    /// nested inside it are read as part of it.
+    fn read_block(&mut self) -> Vec<String> {
+        let mut lines = Vec::new();
+        let mut depth = 0;
+        while let Some(line) = self.next_line(">") {
+            let line = line.trim().to_string();
+            match line.split_whitespace().next() {
+                Some("end") if depth == 0 => break,
+                Some("end") => depth -= 1,
+                Some("define") | Some("commands") => depth += 1,
+                None => continue,
+                _ => {}
+            }
+            lines.push(line);
+        }
+        lines
+    }
+
+    /// Returns the next line of input: a pending command if there are any, or else one typed at
+    /// the given prompt. Returns None when there is no more input.
+    fn next_line(&mut self, prompt: &str) -> Option<String> {
+        if let Some(line) = self.pending_commands.pop_front() {
+            return Some(line);
+        }
+        if self.batch {
+            return None;
+        }
         loop {
             // Print prompt and get next line of user input
-            match self.readline.readline("(deet) ") {
+            match self.readline.readline(prompt) {
                 Err(ReadlineError::Interrupted) => {
                     // User pressed ctrl+c. We're going to ignore it
                     println!("Type \"quit\" to exit");
                 }
                 Err(ReadlineError::Eof) => {
                     // User pressed ctrl+d, which is the equivalent of "quit" for our purposes
-                    return DebuggerCommand::Quit;
+                    return None;
                 }
                 Err(err) => {
                     panic!("Unexpected I/O error: {:?}", err);
                 }
                 Ok(line) => {
-                    if line.trim().len() == 0 {
-                        continue;
-                    }
-                    self.readline.add_history_entry(line.as_str());
-                    if let Err(err) = self.readline.save_history(&self.history_path) {
-                        println!(
-                            "Warning: failed to save history file at {}: {}",
-                            self.history_path, err
-                        );
-                    }
-                    let tokens: Vec<&str> = line.split_whitespace().collect();
-                    if let Some(cmd) = DebuggerCommand::from_tokens(&tokens) {
-                        return cmd;
-                    } else {
-                        println!("Unrecognized command.");
+                    if !line.trim().is_empty() {
+                        self.readline.add_history_entry(line.as_str());
+                        if let Err(err) = self.readline.save_history(&self.history_path) {
+                            println!(
+                                "Warning: failed to save history file at {}: {}",
+                                self.history_path, err
+                            );
+                        }
                     }
+                    return Some(line);
+                }
+            }
+        }
+    }
+
+    /// Reads lines until one holds a valid command, using DebuggerCommand::from_tokens to do the
+    /// parsing. Blank lines and comments (starting with `#`) are skipped.
+    fn get_next_command(&mut self) -> DebuggerCommand {
+        loop {
+            // A user-defined command is done once all of its lines have been taken
+            while let Some(&rest) = self.user_call_stack.last() {
+                if rest < self.pending_commands.len() {
+                    break;
+                }
+                self.user_call_stack.pop();
+            }
+            let line = match self.next_line("(deet) ") {
+                Some(line) => line,
+                None => return DebuggerCommand::Quit,
+            };
+            let tokens: Vec<&str> = line.split_whitespace().collect();
+            if tokens.is_empty() || tokens[0].starts_with('#') {
+                continue;
+            }
+            if let Some(body) = self.user_commands.get(tokens[0]) {
+                if self.user_call_stack.len() >= MAX_USER_CALL_DEPTH {
+                    // Drop the rest of the outermost user-defined command
+                    let rest = self.user_call_stack[0];
+                    let len = self.pending_commands.len();
+                    self.pending_commands.drain(..len - rest);
+                    self.user_call_stack.clear();
+                    println!("Max user call depth exceeded -- command aborted.");
+                    continue;
+                }
+                self.user_call_stack.push(self.pending_commands.len());
+                let lines: Vec<String> = body
+                    .iter()
+                    .map(|line| substitute_args(line, &tokens[1..]))
+                    .collect();
+                for line in lines.into_iter().rev() {
+                    self.pending_commands.push_front(line);
                 }
+                continue;
+            }
+            if let Some(cmd) = DebuggerCommand::from_tokens(&tokens) {
+                return cmd;
+            } else {
+                println!("Unrecognized command.");
             }
         }
     }
 }
+
+// How deeply user-defined commands may call each other, as gdb's max-user-call-depth
+const MAX_USER_CALL_DEPTH: usize = 1024;
+
+// Number of source lines printed by `list`
+const LIST_LINES: usize = 10;
+
+// Bytes below rsp that a function may use without adjusting rsp (System V ABI)
+const STACK_RED_ZONE: usize = 128;
+
+/// Splits the range [addr, addr + len) into naturally aligned chunks of 1, 2, 4 or 8 bytes, which
+/// is what a single debug register can watch.
+fn watch_chunks(mut addr: usize, len: usize) -> Vec<(usize, usize)> {
+    let end = addr + len;
+    let mut chunks = Vec::new();
+    while addr < end {
+        let chunk_len = [8, 4, 2, 1]
+            .iter()
+            .cloned()
+            .find(|&chunk_len| addr.is_multiple_of(chunk_len) && addr + chunk_len <= end)
+            .unwrap();
+        chunks.push((addr, chunk_len));
+        addr += chunk_len;
+    }
+    chunks
+}
+
+/// Replaces `$argc` and `$arg<n>` in a line of a user-defined command with the number of
+/// arguments it was invoked with and the arguments themselves.
+fn substitute_args(line: &str, args: &[&str]) -> String {
+    let mut line = line.replace("$argc", &args.len().to_string());
+    // Highest first, so that $arg1 doesn't match the start of $arg10
+    for (i, arg) in args.iter().enumerate().rev() {
+        line = line.replace(&format!("$arg{}", i), arg);
+    }
+    line
+}
+
+/// Splits `lhs = rhs` at the assignment operator (but not at `==`), trimming both sides.
+fn split_assignment(input: &str) -> Option<(&str, &str)> {
+    let bytes = input.as_bytes();
+    let idx = (0..bytes.len()).find(|&i| {
+        bytes[i] == b'='
+            && bytes.get(i + 1) != Some(&b'=')
+            && (i == 0 || !b"=!<>".contains(&bytes[i - 1]))
+    })?;
+    let (lhs, rhs) = (input[..idx].trim(), input[idx + 1..].trim());
+    if lhs.is_empty() || rhs.is_empty() {
+        return None;
+    }
+    Some((lhs, rhs))
+}
+
+/// Parses the letters after `x/`: an optional count followed by a format letter and/or a unit
+/// size letter, in either order. Returns (count, format, unit size in bytes).
+fn parse_examine_format(
+    format: &str,
+    (mut letter, mut unit): (char, usize),
+) -> Result<(usize, char, usize), String> {
+    let count_len = format
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(format.len());
+    let count = if count_len == 0 {
+        1
+    } else {
+        format[..count_len]
+            .parse::<usize>()
+            .map_err(|_| format!("Invalid number \"{}\".", &format[..count_len]))?
+    };
+    let mut unit_given = false;
+    for c in format[count_len..].chars() {
+        match c {
+            'x' | 'd' | 'u' | 'o' | 't' | 'c' | 's' | 'i' => letter = c,
+            'b' => unit = 1,
+            'h' => unit = 2,
+            'w' => unit = 4,
+            'g' => unit = 8,
+            _ => return Err(format!("Undefined output format \"{}\".", c)),
+        }
+        unit_given |= "bhwg".contains(c);
+    }
+    // Characters and strings are made of bytes unless asked otherwise
+    if (letter == 'c' || letter == 's') && !unit_given {
+        unit = 1;
+    }
+    Ok((count, letter, unit))
+}
+
+/// Formats one unit of memory for `x`.
+fn format_unit(letter: char, bytes: &[u8]) -> String {
+    match letter {
+        'd' => read_signed(bytes).to_string(),
+        'u' => read_unsigned(bytes).to_string(),
+        'o' => format!("{:#o}", read_unsigned(bytes)),
+        't' => format!("{:0width$b}", read_unsigned(bytes), width = bytes.len() * 8),
+        'c' => format!("{} '{}'", bytes[0] as i8, escape_c_char(bytes[0])),
+        _ => format!(
+            "{:#0width$x}",
+            read_unsigned(bytes),
+            width = 2 + bytes.len() * 2
+        ),
+    }
+}
+
+/// Loads the debugging symbols of the given executable, printing an error if that fails.
+fn load_debug_data(target: &str) -> Option<DwarfData> {
+    match DwarfData::from_file(target) {
+        Ok(val) => {
+            if !val.has_debug_info() {
+                println!("(No debugging symbols found in {})", target);
+            }
+            Some(val)
+        }
+        Err(DwarfError::ErrorOpeningFile) => {
+            println!("Could not open file {}", target);
+            None
+        }
+        Err(DwarfError::DwarfFormatError(err)) => {
+            println!(
+                "Could not load debugging symbols from {}: {:?}",
+                target, err
+            );
+            None
+        }
+    }
+}
+
+/// Updates the hit counts of the breakpoints at rip, which the inferior just trapped at, and
+/// decides whether to stop there. Conditions are evaluated in the inferior's current frame; if a
+/// condition can't be evaluated, we stop so the user can fix it. When stopping, the breakpoints'
+/// commands are queued in pending_commands.
+fn breakpoint_hit(
+    breakpoints: &mut [Breakpoint],
+    pending_commands: &mut VecDeque<String>,
+    debug_data: &DwarfData,
+    inferior: &Inferior,
+    rip: usize,
+) -> bool {
+    let evaluator = Evaluator::new(debug_data, inferior);
+    let mut found = false;
+    let mut stop = false;
+    let mut commands = Vec::new();
+    for bp in breakpoints.iter_mut().filter(|bp| bp.addr == Some(rip)) {
+        found = true;
+        if let Some((ref condition, ref expr)) = bp.condition {
+            match evaluator
+                .evaluate(expr)
+                .and_then(|value| evaluator.is_true(&value))
+            {
+                Ok(true) => {}
+                Ok(false) => continue,
+                Err(err) => {
+                    println!(
+                        "Error in testing breakpoint condition {} ({}): {}",
+                        bp.num, condition, err
+                    );
+                    stop = true;
+                    continue;
+                }
+            }
+        }
+        bp.hit_count += 1;
+        if bp.ignore_count > 0 {
+            bp.ignore_count -= 1;
+        } else {
+            stop = true;
+            commands.extend(bp.commands.iter().cloned());
+        }
+    }
+    // The breakpoints' commands run next, before anything else queued
+    if stop {
+        for line in commands.into_iter().rev() {
+            pending_commands.push_front(line);
+        }
+    }
+    // Traps that aren't ours (e.g. an int3 compiled into the program) always stop
+    !found || stop
+}
+
+/// Returns true if a breakpoint location names a function (see Debugger::parse_location).
+fn is_function_location(location: &str) -> bool {
+    // Function names may contain "::" too, but are never followed by a line number
+    let last = location.rsplit(':').next().unwrap_or("");
+    !location.starts_with('*') && last.parse::<usize>().is_err()
+}
+
+/// Parses a hexadecimal address, with or without a leading "0x".
+fn parse_address(addr: &str) -> Option<usize> {
+    let addr_without_0x = if addr.to_lowercase().starts_with("0x") {
+        &addr[2..]
+    } else {
+        addr
+    };
+    usize::from_str_radix(addr_without_0x, 16).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn examine_format_defaults_to_the_last_one() {
+        assert_eq!(parse_examine_format("", ('d', 2)), Ok((1, 'd', 2)));
+        assert_eq!(parse_examine_format("8", ('x', 4)), Ok((8, 'x', 4)));
+    }
+
+    #[test]
+    fn examine_format_takes_letters_in_either_order() {
+        assert_eq!(parse_examine_format("4xg", ('d', 4)), Ok((4, 'x', 8)));
+        assert_eq!(parse_examine_format("4gx", ('d', 4)), Ok((4, 'x', 8)));
+        assert_eq!(parse_examine_format("b", ('u', 4)), Ok((1, 'u', 1)));
+    }
+
+    #[test]
+    fn examine_format_reads_strings_as_bytes() {
+        assert_eq!(parse_examine_format("s", ('x', 4)), Ok((1, 's', 1)));
+        assert_eq!(parse_examine_format("3c", ('x', 8)), Ok((3, 'c', 1)));
+        assert_eq!(parse_examine_format("sw", ('x', 8)), Ok((1, 's', 4)));
+    }
+
+    #[test]
+    fn examine_format_rejects_bad_input() {
+        assert_eq!(
+            parse_examine_format("2q", ('x', 4)),
+            Err("Undefined output format \"q\".".to_string())
+        );
+        assert_eq!(
+            parse_examine_format("99999999999999999999x", ('x', 4)),
+            Err("Invalid number \"99999999999999999999\".".to_string())
+        );
+    }
+
+    #[test]
+    fn split_assignment_trims_both_sides() {
+        assert_eq!(split_assignment("x = 5"), Some(("x", "5")));
+        assert_eq!(split_assignment(" p->next=0 "), Some(("p->next", "0")));
+    }
+
+    #[test]
+    fn split_assignment_skips_comparisons() {
+        assert_eq!(split_assignment("x == 5"), None);
+        assert_eq!(split_assignment("x != 5"), None);
+        assert_eq!(split_assignment("x <= 5"), None);
+        assert_eq!(split_assignment("x >= 5"), None);
+        assert_eq!(split_assignment("a[i] = b == c"), Some(("a[i]", "b == c")));
+    }
+
+    #[test]
+    fn split_assignment_needs_both_sides() {
+        assert_eq!(split_assignment("x"), None);
+        assert_eq!(split_assignment("= 5"), None);
+        assert_eq!(split_assignment("x ="), None);
+    }
+
+    #[test]
+    fn substitute_args_replaces_argc_and_args() {
+        assert_eq!(
+            substitute_args("print $arg0 + $arg1 * $argc", &["x", "y"]),
+            "print x + y * 2"
+        );
+        assert_eq!(substitute_args("print $argc", &[]), "print 0");
+    }
+
+    #[test]
+    fn substitute_args_leaves_missing_args() {
+        assert_eq!(substitute_args("print $arg1", &["x"]), "print $arg1");
+    }
+
+    #[test]
+    fn substitute_args_tells_arg1_from_arg10() {
+        let args = ["a", "b", "c", "d", "e", "f", "g", "h", "i", "j", "k"];
+        assert_eq!(substitute_args("$arg1 $arg10", &args), "b k");
+    }
+
+    #[test]
+    fn watch_chunks_uses_the_widest_aligned_chunks() {
+        assert_eq!(watch_chunks(0x1000, 8), vec![(0x1000, 8)]);
+        assert_eq!(watch_chunks(0x1004, 4), vec![(0x1004, 4)]);
+        assert_eq!(watch_chunks(0x1000, 16), vec![(0x1000, 8), (0x1008, 8)]);
+    }
+
+    #[test]
+    fn watch_chunks_splits_unaligned_addresses() {
+        assert_eq!(watch_chunks(0x1004, 8), vec![(0x1004, 4), (0x1008, 4)]);
+        assert_eq!(
+            watch_chunks(0x1001, 4),
+            vec![(0x1001, 1), (0x1002, 2), (0x1004, 1)]
+        );
+        assert_eq!(
+            watch_chunks(0x1003, 8),
+            vec![(0x1003, 1), (0x1004, 4), (0x1008, 2), (0x100a, 1)]
+        );
+    }
+
+    #[test]
+    fn watch_chunks_covers_odd_lengths() {
+        assert_eq!(watch_chunks(0x1000, 1), vec![(0x1000, 1)]);
+        assert_eq!(watch_chunks(0x1000, 3), vec![(0x1000, 2), (0x1002, 1)]);
+        assert_eq!(
+            watch_chunks(0x1000, 7),
+            vec![(0x1000, 4), (0x1004, 2), (0x1006, 1)]
+        );
+        assert_eq!(watch_chunks(0x1000, 0), vec![]);
+    }
+
+    #[test]
+    fn watch_chunks_may_need_more_than_the_debug_registers() {
+        // set_watchpoint reports these as needing too many registers
+        let chunks = watch_chunks(0x1001, 40);
+        assert_eq!(chunks.len(), 8);
+        assert_eq!(chunks.first(), Some(&(0x1001, 1)));
+        assert_eq!(chunks.last(), Some(&(0x1028, 1)));
+        assert_eq!(chunks.iter().map(|&(_, len)| len).sum::<usize>(), 40);
+        assert!(chunks.iter().all(|&(addr, len)| addr.is_multiple_of(len)));
+    }
+}
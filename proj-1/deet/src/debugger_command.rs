@@ -2,21 +2,368 @@ pub enum DebuggerCommand {
     Quit,
     Run(Vec<String>),
     Continue,
+    Break(String, Option<String>),
+    Backtrace,
+    StepInstruction,
+    Step,
+    Next,
+    Finish,
+    Print(String),
+    InfoLocals,
+    InfoBreakpoints,
+    InfoInferiors,
+    InfoSharedLibrary,
+    Inferior(usize),
+    InfoThreads,
+    Thread(Option<usize>),
+    /// A signal name (or `all`) and the keywords saying how to handle it
+    Handle(String, Vec<String>),
+    InfoSignals(Option<String>),
+    Signal(String),
+    Ignore(usize, usize),
+    Attach(String),
+    Detach,
+    Watch(String),
+    ReadWatch(String),
+    /// Names of the registers to show, or all of them if empty
+    InfoRegisters(Vec<String>),
+    InfoFloat,
+    /// `set [var] <lvalue> = <expression>`, or a setting
+    Set(String),
+    List(Option<String>),
+    Directory(Option<String>),
+    Disassemble(Option<String>),
+    /// Format letters (e.g. "4xw" or "i") and the expression giving the address, if any
+    Examine(String, String),
+    Source(String),
+    /// `define <name>`, followed by the lines of the definition up to `end`
+    Define(String),
+    /// `commands [<breakpoint>]`, followed by the commands to run when it is hit up to `end`
+    Commands(Option<usize>),
+    /// An `end` that doesn't close a `define` or `commands`
+    End,
 }
 
-impl DebuggerCommand {
-    pub fn from_tokens(tokens: &Vec<&str>) -> Option<DebuggerCommand> {
-        match tokens[0] {
-            "q" | "quit" => Some(DebuggerCommand::Quit),
-            "r" | "run" => {
-                let args = tokens[1..].to_vec();
-                Some(DebuggerCommand::Run(
-                    args.iter().map(|s| s.to_string()).collect(),
-                ))
+/// How a command is typed: the names it goes by, and how to build it from the words that follow
+/// the name (returning None if they don't fit the command).
+struct CommandSpec {
+    names: &'static [&'static str],
+    parse: fn(&[&str]) -> Option<DebuggerCommand>,
+}
+
+fn join(args: &[&str]) -> String {
+    args.join(" ")
+}
+
+fn to_strings(args: &[&str]) -> Vec<String> {
+    args.iter().map(|s| s.to_string()).collect()
+}
+
+/// The optional single argument of commands like `list` and `thread`
+fn optional_arg(args: &[&str]) -> Option<Option<String>> {
+    match args {
+        [] => Some(None),
+        [arg] => Some(Some(arg.to_string())),
+        _ => None,
+    }
+}
+
+const COMMANDS: &[CommandSpec] = &[
+    CommandSpec {
+        names: &["q", "quit"],
+        parse: |_| Some(DebuggerCommand::Quit),
+    },
+    CommandSpec {
+        names: &["r", "run"],
+        parse: |args| Some(DebuggerCommand::Run(to_strings(args))),
+    },
+    CommandSpec {
+        names: &["c", "cont", "continue"],
+        parse: |_| Some(DebuggerCommand::Continue),
+    },
+    CommandSpec {
+        names: &["b", "break"],
+        parse: |args| match args {
+            [location] => Some(DebuggerCommand::Break(location.to_string(), None)),
+            [location, "if", condition @ ..] if !condition.is_empty() => Some(
+                DebuggerCommand::Break(location.to_string(), Some(join(condition))),
+            ),
+            _ => None,
+        },
+    },
+    CommandSpec {
+        names: &["ignore"],
+        parse: |args| match args {
+            [num, count] => Some(DebuggerCommand::Ignore(
+                num.parse().ok()?,
+                count.parse().ok()?,
+            )),
+            _ => None,
+        },
+    },
+    CommandSpec {
+        names: &["bt", "back", "backtrace"],
+        parse: |_| Some(DebuggerCommand::Backtrace),
+    },
+    CommandSpec {
+        names: &["si", "stepi"],
+        parse: |_| Some(DebuggerCommand::StepInstruction),
+    },
+    CommandSpec {
+        names: &["s", "step"],
+        parse: |_| Some(DebuggerCommand::Step),
+    },
+    CommandSpec {
+        names: &["n", "next"],
+        parse: |_| Some(DebuggerCommand::Next),
+    },
+    CommandSpec {
+        names: &["fin", "finish"],
+        parse: |_| Some(DebuggerCommand::Finish),
+    },
+    CommandSpec {
+        names: &["p", "print"],
+        parse: |args| match args {
+            [] => None,
+            _ => Some(DebuggerCommand::Print(join(args))),
+        },
+    },
+    CommandSpec {
+        names: &["attach"],
+        parse: |args| match args {
+            [pid] => Some(DebuggerCommand::Attach(pid.to_string())),
+            _ => None,
+        },
+    },
+    CommandSpec {
+        names: &["detach"],
+        parse: |_| Some(DebuggerCommand::Detach),
+    },
+    CommandSpec {
+        names: &["watch"],
+        parse: |args| match args {
+            [] => None,
+            _ => Some(DebuggerCommand::Watch(join(args))),
+        },
+    },
+    CommandSpec {
+        names: &["rwatch"],
+        parse: |args| match args {
+            [] => None,
+            _ => Some(DebuggerCommand::ReadWatch(join(args))),
+        },
+    },
+    CommandSpec {
+        names: &["l", "list"],
+        parse: |args| Some(DebuggerCommand::List(optional_arg(args)?)),
+    },
+    CommandSpec {
+        names: &["dir", "directory"],
+        parse: |args| Some(DebuggerCommand::Directory(optional_arg(args)?)),
+    },
+    CommandSpec {
+        names: &["disas", "disassemble"],
+        parse: |args| Some(DebuggerCommand::Disassemble(optional_arg(args)?)),
+    },
+    CommandSpec {
+        names: &["x"],
+        parse: |args| Some(DebuggerCommand::Examine(String::new(), join(args))),
+    },
+    CommandSpec {
+        names: &["inferior"],
+        parse: |args| match args {
+            [num] => Some(DebuggerCommand::Inferior(num.parse().ok()?)),
+            _ => None,
+        },
+    },
+    CommandSpec {
+        names: &["thread"],
+        parse: |args| match args {
+            [] => Some(DebuggerCommand::Thread(None)),
+            [num] => Some(DebuggerCommand::Thread(Some(num.parse().ok()?))),
+            _ => None,
+        },
+    },
+    CommandSpec {
+        names: &["handle"],
+        parse: |args| match args {
+            [signal, keywords @ ..] => Some(DebuggerCommand::Handle(
+                signal.to_string(),
+                to_strings(keywords),
+            )),
+            _ => None,
+        },
+    },
+    CommandSpec {
+        names: &["signal"],
+        parse: |args| match args {
+            [signal] => Some(DebuggerCommand::Signal(signal.to_string())),
+            _ => None,
+        },
+    },
+    CommandSpec {
+        names: &["source"],
+        parse: |args| match args {
+            [path] => Some(DebuggerCommand::Source(path.to_string())),
+            _ => None,
+        },
+    },
+    CommandSpec {
+        names: &["define"],
+        parse: |args| match args {
+            [name] => Some(DebuggerCommand::Define(name.to_string())),
+            _ => None,
+        },
+    },
+    CommandSpec {
+        names: &["commands"],
+        parse: |args| match args {
+            [] => Some(DebuggerCommand::Commands(None)),
+            [num] => Some(DebuggerCommand::Commands(Some(num.parse().ok()?))),
+            _ => None,
+        },
+    },
+    CommandSpec {
+        names: &["end"],
+        parse: |_| Some(DebuggerCommand::End),
+    },
+    CommandSpec {
+        names: &["set"],
+        parse: |args| match args {
+            [] => None,
+            _ => Some(DebuggerCommand::Set(join(args))),
+        },
+    },
+    CommandSpec {
+        names: &["info"],
+        parse: |args| match args {
+            [subcommand, args @ ..] => (find_command(INFO_COMMANDS, subcommand)?.parse)(args),
+            _ => None,
+        },
+    },
+];
+
+/// The subcommands of `info`
+const INFO_COMMANDS: &[CommandSpec] = &[
+    CommandSpec {
+        names: &["r", "reg", "registers"],
+        parse: |args| Some(DebuggerCommand::InfoRegisters(to_strings(args))),
+    },
+    CommandSpec {
+        names: &["signals"],
+        parse: |args| Some(DebuggerCommand::InfoSignals(optional_arg(args)?)),
+    },
+    CommandSpec {
+        names: &["locals"],
+        parse: |args| {
+            if args.is_empty() {
+                Some(DebuggerCommand::InfoLocals)
+            } else {
+                None
+            }
+        },
+    },
+    CommandSpec {
+        names: &["b", "break", "breakpoints"],
+        parse: |args| {
+            if args.is_empty() {
+                Some(DebuggerCommand::InfoBreakpoints)
+            } else {
+                None
+            }
+        },
+    },
+    CommandSpec {
+        names: &["float"],
+        parse: |args| {
+            if args.is_empty() {
+                Some(DebuggerCommand::InfoFloat)
+            } else {
+                None
+            }
+        },
+    },
+    CommandSpec {
+        names: &["inferiors"],
+        parse: |args| {
+            if args.is_empty() {
+                Some(DebuggerCommand::InfoInferiors)
+            } else {
+                None
+            }
+        },
+    },
+    CommandSpec {
+        names: &["shared", "sharedlibrary", "dll"],
+        parse: |args| {
+            if args.is_empty() {
+                Some(DebuggerCommand::InfoSharedLibrary)
+            } else {
+                None
+            }
+        },
+    },
+    CommandSpec {
+        names: &["threads"],
+        parse: |args| {
+            if args.is_empty() {
+                Some(DebuggerCommand::InfoThreads)
+            } else {
+                None
             }
-            "c" | "cont" | "continue" => Some(DebuggerCommand::Continue),
-            // Default case:
+        },
+    },
+];
+
+fn find_command(table: &'static [CommandSpec], name: &str) -> Option<&'static CommandSpec> {
+    table.iter().find(|spec| spec.names.contains(&name))
+}
+
+/// Returns whether name is one of the built-in commands (which can't be redefined).
+pub fn is_builtin(name: &str) -> bool {
+    find_command(COMMANDS, name).is_some()
+}
+
+impl DebuggerCommand {
+    pub fn from_tokens(tokens: &[&str]) -> Option<DebuggerCommand> {
+        // The format letters of `x` are attached to its name, as in `x/4xw`. The address may be
+        // left out to continue where the last `x` left off.
+        if let Some(format) = tokens[0].strip_prefix("x/") {
+            return Some(DebuggerCommand::Examine(
+                format.to_string(),
+                join(&tokens[1..]),
+            ));
+        }
+        (find_command(COMMANDS, tokens[0])?.parse)(&tokens[1..])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn examine(tokens: &[&str]) -> Option<(String, String)> {
+        match DebuggerCommand::from_tokens(tokens) {
+            Some(DebuggerCommand::Examine(format, address)) => Some((format, address)),
             _ => None,
         }
     }
+
+    #[test]
+    fn examine_takes_format_and_address() {
+        assert_eq!(
+            examine(&["x/4xw", "&arr", "[1]"]),
+            Some(("4xw".to_string(), "&arr [1]".to_string()))
+        );
+        assert_eq!(
+            examine(&["x", "$rsp"]),
+            Some((String::new(), "$rsp".to_string()))
+        );
+    }
+
+    #[test]
+    fn examine_address_is_optional() {
+        assert_eq!(examine(&["x/4x"]), Some(("4x".to_string(), String::new())));
+        assert_eq!(examine(&["x"]), Some((String::new(), String::new())));
+    }
 }
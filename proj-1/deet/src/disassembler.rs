@@ -0,0 +1,40 @@
+//! Decoding of x86-64 machine code into AT&T syntax (like gdb and objdump), for `disassemble` and
+//! `x/i`.
+
+use iced_x86::{Decoder, DecoderError, DecoderOptions, Formatter, GasFormatter};
+
+// No x86 instruction is longer than this
+pub const MAX_INSTRUCTION_LEN: usize = 15;
+
+pub struct Instruction {
+    pub address: usize,
+    pub text: String,
+}
+
+/// Decodes the instructions in bytes, which were read from address. An instruction that is cut
+/// off at the end of bytes is dropped; any other invalid encoding is shown as "(bad)".
+pub fn disassemble(bytes: &[u8], address: usize) -> Vec<Instruction> {
+    let mut decoder = Decoder::with_ip(64, bytes, address as u64, DecoderOptions::NONE);
+    let mut formatter = GasFormatter::new();
+    // Pad mnemonics so that operands line up, as objdump does
+    formatter.options_mut().set_first_operand_char_index(7);
+
+    let mut instructions = Vec::new();
+    while decoder.can_decode() {
+        let instruction = decoder.decode();
+        if decoder.last_error() == DecoderError::NoMoreBytes {
+            break;
+        }
+        let mut text = String::new();
+        if instruction.is_invalid() {
+            text.push_str("(bad)");
+        } else {
+            formatter.format(&instruction, &mut text);
+        }
+        instructions.push(Instruction {
+            address: instruction.ip() as usize,
+            text,
+        });
+    }
+    instructions
+}
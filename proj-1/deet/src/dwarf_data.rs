@@ -1,6 +1,9 @@
 use crate::gimli_wrapper;
+use crate::shared_library::SharedLibrary;
+use crate::unwind::CallFrameInfo;
 use addr2line::Context;
-use object::Object;
+use object::{Object, ObjectSection, ObjectSegment, SymbolKind};
+use std::collections::HashMap;
 use std::convert::TryInto;
 use std::{fmt, fs};
 
@@ -12,9 +15,35 @@ pub enum Error {
 
 pub struct DwarfData {
     files: Vec<File>,
+    types: HashMap<usize, Type>,
+    /// Functions and objects from the ELF symbol tables (.symtab and .dynsym), in address order.
+    /// They name code that has no debugging information, such as most of libc.
+    symbols: Vec<Symbol>,
+    /// Contents of the .text section and the address it is loaded at, so that code can be
+    /// disassembled before the program is running
+    text: Vec<u8>,
+    text_address: usize,
+    call_frame_info: CallFrameInfo,
     addr2line: Context<addr2line::gimli::EndianRcSlice<addr2line::gimli::RunTimeEndian>>,
+    /// How far the file has been moved from the addresses it was linked at. Everything above
+    /// holds addresses as loaded, except addr2line, which is given unbiased ones.
+    bias: usize,
+    /// The lowest address of the file's loadable segments, as linked
+    base_address: usize,
+    entry_point: usize,
+    /// Where the dynamic section is, for executables that use shared libraries
+    dynamic_address: Option<usize>,
+    /// The shared libraries the program has loaded. Lookups search them after the program
+    /// itself.
+    libraries: Vec<SharedLibrary>,
+    /// Number of libraries loaded so far, used to keep their type offsets apart
+    libraries_loaded: usize,
 }
 
+/// Each library's type offsets are moved this far past the previous library's, so that types
+/// from all of them can be looked up by offset
+const LIBRARY_TYPE_OFFSET_STRIDE: usize = 1 << 40;
+
 impl fmt::Debug for DwarfData {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "DwarfData {{files: {:?}}}", self.files)
@@ -31,31 +60,249 @@ impl DwarfData {
     pub fn from_file(path: &str) -> Result<DwarfData, Error> {
         let file = fs::File::open(path).or(Err(Error::ErrorOpeningFile))?;
         let mmap = unsafe { memmap::Mmap::map(&file).or(Err(Error::ErrorOpeningFile))? };
-        let object = object::File::parse(&*mmap)
-            .or_else(|e| Err(gimli_wrapper::Error::ObjectError(e.to_string())))?;
+        let object =
+            object::File::parse(&mmap).map_err(|e| gimli_wrapper::Error::Object(e.to_string()))?;
         let endian = if object.is_little_endian() {
             gimli::RunTimeEndian::Little
         } else {
             gimli::RunTimeEndian::Big
         };
+        let (files, types) = gimli_wrapper::load_file(&object, endian)?;
+        let mut symbols: Vec<Symbol> = object
+            .symbols()
+            .chain(object.dynamic_symbols())
+            .filter_map(|(_, symbol)| {
+                let is_function = match symbol.kind() {
+                    SymbolKind::Text => true,
+                    SymbolKind::Data => false,
+                    _ => return None,
+                };
+                if symbol.is_undefined() || symbol.address() == 0 {
+                    return None;
+                }
+                Some(Symbol {
+                    name: demangle(symbol.name()?),
+                    address: symbol.address() as usize,
+                    size: symbol.size() as usize,
+                    is_function,
+                })
+            })
+            .collect();
+        // .symtab repeats the symbols in .dynsym
+        symbols.sort_by(|a, b| (a.address, &a.name).cmp(&(b.address, &b.name)));
+        symbols.dedup_by(|a, b| a.address == b.address && a.name == b.name);
+        let text_address = object
+            .section_by_name(".text")
+            .map(|section| section.address() as usize)
+            .unwrap_or(0);
+        let text = object
+            .section_data_by_name(".text")
+            .map(|data| data.into_owned())
+            .unwrap_or_default();
+        let base_address = object
+            .segments()
+            .map(|segment| segment.address() as usize)
+            .min()
+            .unwrap_or(0);
         Ok(DwarfData {
-            files: gimli_wrapper::load_file(&object, endian)?,
-            addr2line: Context::new(&object).or_else(|e| Err(gimli_wrapper::Error::from(e)))?,
+            files,
+            types,
+            symbols,
+            text,
+            text_address,
+            call_frame_info: CallFrameInfo::load(&object),
+            addr2line: Context::new(&object).map_err(gimli_wrapper::Error::from)?,
+            bias: 0,
+            // Segments are mapped whole pages at a time
+            base_address: base_address & !0xfff,
+            entry_point: object.entry() as usize,
+            dynamic_address: object
+                .section_by_name(".dynamic")
+                .map(|section| section.address() as usize),
+            libraries: Vec::new(),
+            libraries_loaded: 0,
         })
     }
 
+    /// Moves every address by bias, for a file that is loaded somewhere other than the
+    /// addresses it was linked at. (Biases add up, and may wrap around to move addresses down.)
+    pub fn relocate(&mut self, bias: usize) {
+        for file in &mut self.files {
+            for func in &mut file.functions {
+                func.address = func.address.wrapping_add(bias);
+                for var in &mut func.variables {
+                    var.location.relocate(bias);
+                }
+            }
+            for var in &mut file.global_variables {
+                var.location.relocate(bias);
+            }
+            for line in &mut file.lines {
+                line.address = line.address.wrapping_add(bias);
+            }
+        }
+        for symbol in &mut self.symbols {
+            symbol.address = symbol.address.wrapping_add(bias);
+        }
+        self.text_address = self.text_address.wrapping_add(bias);
+        self.entry_point = self.entry_point.wrapping_add(bias);
+        self.dynamic_address = self.dynamic_address.map(|addr| addr.wrapping_add(bias));
+        self.call_frame_info.relocate(bias);
+        self.bias = self.bias.wrapping_add(bias);
+    }
+
+    /// Adds base to the offsets that types are known by.
+    fn shift_type_offsets(&mut self, base: usize) {
+        self.types = self
+            .types
+            .drain()
+            .map(|(offset, mut dtype)| {
+                dtype.shift_offsets(base);
+                (offset + base, dtype)
+            })
+            .collect();
+        for file in &mut self.files {
+            let locals = file
+                .functions
+                .iter_mut()
+                .flat_map(|func| func.variables.iter_mut());
+            for var in file.global_variables.iter_mut().chain(locals) {
+                var.entity_type.shift_offsets(base);
+            }
+        }
+    }
+
+    /// Replaces the list of loaded shared libraries with the ones now mapped into the inferior
+    /// (see shared_library::mapped_libraries), loading the data of any new ones. Returns true if
+    /// the list changed.
+    pub fn update_libraries(&mut self, mut mapped: Vec<SharedLibrary>) -> bool {
+        let mut old = std::mem::take(&mut self.libraries);
+        let mut changed = false;
+        for library in &mut mapped {
+            let position = old
+                .iter()
+                .position(|lib| lib.path == library.path && lib.start == library.start);
+            if let Some(position) = position {
+                library.debug_data = old.remove(position).debug_data;
+                continue;
+            }
+            changed = true;
+            library.debug_data = DwarfData::from_file(&library.path).ok().map(|mut data| {
+                self.libraries_loaded += 1;
+                data.shift_type_offsets(self.libraries_loaded * LIBRARY_TYPE_OFFSET_STRIDE);
+                data.relocate(library.start.wrapping_sub(data.base_address));
+                data
+            });
+        }
+        self.libraries = mapped;
+        changed || !old.is_empty()
+    }
+
+    /// Forgets the shared libraries, when the process that loaded them is gone.
+    pub fn clear_libraries(&mut self) {
+        self.libraries.clear();
+    }
+
+    pub fn libraries(&self) -> &[SharedLibrary] {
+        &self.libraries
+    }
+
+    /// Moves the file so that it is bias away from the addresses it was linked at, e.g. once the
+    /// load bias of a position-independent executable is known.
+    pub fn set_bias(&mut self, bias: usize) {
+        self.relocate(bias.wrapping_sub(self.bias));
+    }
+
+    pub fn entry_point(&self) -> usize {
+        self.entry_point
+    }
+
+    /// The entry point as linked, before relocation
+    pub fn linked_entry_point(&self) -> usize {
+        self.entry_point.wrapping_sub(self.bias)
+    }
+
+    pub fn base_address(&self) -> usize {
+        self.base_address
+    }
+
+    pub fn dynamic_address(&self) -> Option<usize> {
+        self.dynamic_address
+    }
+
+    /// The .text section's address range, once loaded
+    pub fn text_range(&self) -> (usize, usize) {
+        (self.text_address, self.text_address + self.text.len())
+    }
+
+    /// Returns true if the file has any DWARF debugging information.
+    pub fn has_debug_info(&self) -> bool {
+        !self.files.is_empty()
+    }
+
+    /// The program's source files, followed by those of the shared libraries
+    fn all_files(&self) -> impl Iterator<Item = &File> {
+        let library_files = self
+            .libraries
+            .iter()
+            .filter_map(|lib| lib.debug_data.as_ref())
+            .flat_map(|data| data.files.iter());
+        self.files.iter().chain(library_files)
+    }
+
+    /// The program's ELF symbols, followed by those of the shared libraries
+    fn all_symbols(&self) -> impl Iterator<Item = &Symbol> {
+        let library_symbols = self
+            .libraries
+            .iter()
+            .filter_map(|lib| lib.debug_data.as_ref())
+            .flat_map(|data| data.symbols.iter());
+        self.symbols.iter().chain(library_symbols)
+    }
+
+    /// Returns the ELF symbol whose function (or object, unless functions_only is set) contains
+    /// addr.
+    fn get_elf_symbol(&self, addr: usize, functions_only: bool) -> Option<&Symbol> {
+        self.object_for(addr)
+            .symbols
+            .iter()
+            .rev()
+            .skip_while(|symbol| symbol.address > addr)
+            .filter(|symbol| symbol.is_function || !functions_only)
+            .find(|symbol| addr < symbol.address + symbol.size.max(1))
+    }
+
+    /// Returns the data of the shared library that addr is in, or else the program's own.
+    fn object_for(&self, addr: usize) -> &DwarfData {
+        self.libraries
+            .iter()
+            .filter(|lib| lib.contains(addr))
+            .find_map(|lib| lib.debug_data.as_ref())
+            .unwrap_or(self)
+    }
+
     #[allow(dead_code)]
     fn get_target_file(&self, file: &str) -> Option<&File> {
-        self.files.iter().find(|f| {
+        self.all_files().find(|f| {
             f.name == file || (!file.contains("/") && f.name.ends_with(&format!("/{}", file)))
         })
     }
 
+    /// Returns the full name of the source file that file refers to (a path, or just the file's
+    /// name), or of the first compilation unit if file is None.
+    pub fn get_file_name(&self, file: Option<&str>) -> Option<&str> {
+        let target_file = match file {
+            Some(filename) => self.get_target_file(filename)?,
+            None => self.files.first()?,
+        };
+        Some(&target_file.name)
+    }
+
     #[allow(dead_code)]
     pub fn get_addr_for_line(&self, file: Option<&str>, line_number: usize) -> Option<usize> {
         let target_file = match file {
             Some(filename) => self.get_target_file(filename)?,
-            None => self.files.get(0)?,
+            None => self.files.first()?,
         };
         Some(
             target_file
@@ -73,25 +320,159 @@ impl DwarfData {
                 self.get_target_file(filename)?
                     .functions
                     .iter()
-                    .find(|func| func.name == func_name)?
+                    .find(|func| func.name == func_name && !func.is_declaration())?
                     .address,
             ),
             None => {
-                for file in &self.files {
-                    if let Some(func) = file.functions.iter().find(|func| func.name == func_name) {
+                for file in self.all_files() {
+                    if let Some(func) = file
+                        .functions
+                        .iter()
+                        .find(|func| func.name == func_name && !func.is_declaration())
+                    {
                         return Some(func.address);
                     }
                 }
-                None
+                self.all_symbols()
+                    .find(|symbol| symbol.is_function && symbol.name == func_name)
+                    .map(|symbol| symbol.address)
+            }
+        }
+    }
+
+    /// Returns the address of the first line-table row after the entry point of the function at
+    /// func_addr. That is where the function body begins once the prologue has set up the stack
+    /// frame, which is where gdb places breakpoints on functions.
+    pub fn get_addr_after_prologue(&self, func_addr: usize) -> Option<usize> {
+        for file in self.all_files() {
+            if let Some(func) = file.functions.iter().find(|func| func.address == func_addr) {
+                return file
+                    .lines
+                    .iter()
+                    .map(|line| line.address)
+                    .filter(|&addr| addr > func_addr && addr < func_addr + func.text_length)
+                    .min();
+            }
+        }
+        None
+    }
+
+    /// Returns true if addr is the first instruction of a row in the line table.
+    pub fn is_line_start(&self, addr: usize) -> bool {
+        self.all_files()
+            .any(|file| file.lines.iter().any(|line| line.address == addr))
+    }
+
+    /// Returns true if addr is the entry point of a function.
+    pub fn is_function_entry(&self, addr: usize) -> bool {
+        self.all_files()
+            .any(|file| file.functions.iter().any(|func| func.address == addr))
+    }
+
+    /// Returns the function whose code contains curr_addr.
+    pub fn get_function_containing(&self, curr_addr: usize) -> Option<&Function> {
+        self.all_files().find_map(|file| {
+            file.functions.iter().find(|func| {
+                curr_addr >= func.address && curr_addr < func.address + func.text_length
+            })
+        })
+    }
+
+    /// Returns the variable called name that is visible at curr_addr: a local variable or
+    /// parameter of the function containing curr_addr, or otherwise a global variable.
+    pub fn get_variable(&self, curr_addr: usize, name: &str) -> Option<&Variable> {
+        if let Some(func) = self.get_function_containing(curr_addr) {
+            // Search backwards so that variables in inner blocks shadow outer ones
+            if let Some(var) = func.variables.iter().rev().find(|var| var.name == name) {
+                return Some(var);
+            }
+        }
+        self.all_files()
+            .find_map(|file| file.global_variables.iter().find(|var| var.name == name))
+    }
+
+    /// Returns the name of the function or global variable that addr lies in, along with the
+    /// offset of addr from its start. Falls back to the ELF symbols for code and data that have
+    /// no debugging information.
+    pub fn get_symbol_for_addr(&self, addr: usize) -> Option<(&str, usize)> {
+        if let Some(func) = self.get_function_containing(addr) {
+            return Some((&func.name, addr - func.address));
+        }
+        let global = self.all_files().find_map(|file| {
+            file.global_variables.iter().find_map(|var| {
+                let start = match var.location {
+                    Location::Expression(ref expr) => expr.static_address()?,
+                    Location::List(_) => return None,
+                };
+                if addr >= start && addr < start + var.entity_type.size.max(1) {
+                    Some((var.name.as_str(), addr - start))
+                } else {
+                    None
+                }
+            })
+        });
+        global.or_else(|| {
+            self.get_elf_symbol(addr, false)
+                .map(|symbol| (symbol.name.as_str(), addr - symbol.address))
+        })
+    }
+
+    /// Returns the name, address and length of the function whose code contains addr, from
+    /// the debugging information or else the ELF symbols.
+    pub fn get_function_bounds(&self, addr: usize) -> Option<(&str, usize, usize)> {
+        match self.get_function_containing(addr) {
+            Some(func) => Some((&func.name, func.address, func.text_length)),
+            None => self
+                .get_elf_symbol(addr, true)
+                .map(|symbol| (symbol.name.as_str(), symbol.address, symbol.size)),
+        }
+    }
+
+    /// Returns the type at the given offset in .debug_info, which is how types refer to each
+    /// other.
+    pub fn get_type(&self, offset: usize) -> Option<&Type> {
+        self.types.get(&offset).or_else(|| {
+            self.libraries
+                .iter()
+                .filter_map(|lib| lib.debug_data.as_ref())
+                .find_map(|data| data.types.get(&offset))
+        })
+    }
+
+    /// Strips typedefs and const/volatile qualifiers off a type.
+    pub fn resolve_type<'a>(&'a self, mut dtype: &'a Type) -> &'a Type {
+        loop {
+            match dtype.kind {
+                TypeKind::Typedef(Some(target))
+                | TypeKind::Const(Some(target))
+                | TypeKind::Volatile(Some(target)) => match self.get_type(target) {
+                    Some(target_type) => dtype = target_type,
+                    None => return dtype,
+                },
+                _ => return dtype,
             }
         }
     }
 
+    /// Returns len bytes of code at addr from the executable file (or a shared library), if
+    /// they are all in .text.
+    pub fn read_text(&self, addr: usize, len: usize) -> Option<&[u8]> {
+        let data = self.object_for(addr);
+        let start = addr.checked_sub(data.text_address)?;
+        data.text.get(start..start.checked_add(len)?)
+    }
+
+    /// Returns the CFI for the code at addr.
+    pub fn call_frame_info(&self, addr: usize) -> &CallFrameInfo {
+        &self.object_for(addr).call_frame_info
+    }
+
     #[allow(dead_code)]
     pub fn get_line_from_addr(&self, curr_addr: usize) -> Option<Line> {
-        let location = self
+        let data = self.object_for(curr_addr);
+        let location = data
             .addr2line
-            .find_location(curr_addr.try_into().unwrap())
+            .find_location(curr_addr.wrapping_sub(data.bias).try_into().unwrap())
             .ok()??;
         Some(Line {
             file: location.file?.to_string(),
@@ -100,15 +481,25 @@ impl DwarfData {
         })
     }
 
+    /// Returns the name of the function containing curr_addr, falling back to the ELF symbols if
+    /// there is no debugging information for it.
     #[allow(dead_code)]
     pub fn get_function_from_addr(&self, curr_addr: usize) -> Option<String> {
-        let frame = self
+        self.get_dwarf_function_name(curr_addr).or_else(|| {
+            self.get_elf_symbol(curr_addr, true)
+                .map(|symbol| symbol.name.clone())
+        })
+    }
+
+    fn get_dwarf_function_name(&self, curr_addr: usize) -> Option<String> {
+        let data = self.object_for(curr_addr);
+        let frame = data
             .addr2line
-            .find_frames(curr_addr.try_into().unwrap())
+            .find_frames(curr_addr.wrapping_sub(data.bias).try_into().unwrap())
             .ok()?
             .next()
             .ok()??;
-        Some(frame.function?.raw_name().ok()?.to_string())
+        Some(demangle(&frame.function?.raw_name().ok()?))
     }
 
     #[allow(dead_code)]
@@ -152,28 +543,212 @@ impl DwarfData {
 pub struct Type {
     pub name: String,
     pub size: usize,
+    pub kind: TypeKind,
+    /// The types a generic type was instantiated with (e.g. T in Rust's Vec<T>), in order
+    pub type_params: Vec<usize>,
+    /// The offset the type is known by (see DwarfData::get_type), or None for types the debugger
+    /// makes up itself
+    pub offset: Option<usize>,
 }
 
 impl Type {
     pub fn new(name: String, size: usize) -> Self {
         Type {
-            name: name,
-            size: size,
+            name,
+            size,
+            kind: TypeKind::Base,
+            type_params: Vec::new(),
+            offset: None,
+        }
+    }
+
+    /// Adds base to the offsets of the types this one refers to.
+    fn shift_offsets(&mut self, base: usize) {
+        self.kind.shift_offsets(base);
+        for offset in &mut self.type_params {
+            *offset += base;
+        }
+        if let Some(offset) = &mut self.offset {
+            *offset += base;
+        }
+    }
+}
+
+/// The shape of a type. Other types are referenced by their offset in .debug_info (see
+/// DwarfData::get_type), so that self-referential structs can be represented. A referenced type
+/// of None means void.
+#[derive(Debug, Clone, Default)]
+pub enum TypeKind {
+    #[default]
+    Base,
+    Pointer(Option<usize>),
+    Const(Option<usize>),
+    Volatile(Option<usize>),
+    Typedef(Option<usize>),
+    Struct(Vec<Member>),
+    Union(Vec<Member>),
+    /// Element type and the number of elements in each dimension (None if unknown, e.g. for
+    /// flexible array members)
+    Array(usize, Vec<Option<usize>>),
+    /// Enumerator names and values
+    Enum(Vec<(String, i64)>),
+    /// Return type and parameter types
+    Function(Option<usize>, Vec<usize>),
+    /// A Rust enum: the member holding the discriminant (None if there is only one variant), and
+    /// the variants it selects between
+    Variants(Option<Member>, Vec<Variant>),
+}
+
+impl TypeKind {
+    /// Adds base to the offsets of the types this one refers to.
+    fn shift_offsets(&mut self, base: usize) {
+        match self {
+            TypeKind::Pointer(target)
+            | TypeKind::Const(target)
+            | TypeKind::Volatile(target)
+            | TypeKind::Typedef(target) => {
+                if let Some(offset) = target {
+                    *offset += base;
+                }
+            }
+            TypeKind::Struct(members) | TypeKind::Union(members) => {
+                for member in members {
+                    member.member_type += base;
+                }
+            }
+            TypeKind::Array(element_type, _) => *element_type += base,
+            TypeKind::Function(return_type, parameters) => {
+                if let Some(offset) = return_type {
+                    *offset += base;
+                }
+                for offset in parameters {
+                    *offset += base;
+                }
+            }
+            TypeKind::Variants(discriminant, variants) => {
+                let variant_members = variants.iter_mut().flat_map(|v| v.members.iter_mut());
+                for member in discriminant.iter_mut().chain(variant_members) {
+                    member.member_type += base;
+                }
+            }
+            TypeKind::Base | TypeKind::Enum(_) => {}
         }
     }
 }
 
+#[derive(Debug, Clone)]
+pub struct Member {
+    pub name: String,
+    pub member_type: usize,
+    pub offset: usize, // Byte offset from the start of the struct
+}
+
+/// One variant of a Rust enum (see TypeKind::Variants)
+#[derive(Debug, Clone)]
+pub struct Variant {
+    /// The discriminant value that selects this variant, or None for the variant used when no
+    /// other one matches
+    pub discriminant: Option<u64>,
+    /// Its data, normally a single member named after the variant whose type holds its fields
+    pub members: Vec<Member>,
+}
+
+/// A DWARF location expression. The bytecode is kept as-is and run by location::evaluate when
+/// the variable is read, since most expressions depend on the inferior's registers.
+#[derive(Debug, Clone)]
+pub struct LocationExpression {
+    pub bytecode: Vec<u8>,
+    pub encoding: gimli::Encoding,
+}
+
+impl LocationExpression {
+    /// Returns the address if the expression is just DW_OP_addr, as it is for global and static
+    /// variables.
+    pub fn static_address(&self) -> Option<usize> {
+        let size = self.encoding.address_size as usize;
+        if self.bytecode.len() != 1 + size || self.bytecode[0] != gimli::DW_OP_addr.0 {
+            return None;
+        }
+        let mut buf = [0u8; 8];
+        buf[..size].copy_from_slice(&self.bytecode[1..]);
+        Some(u64::from_le_bytes(buf) as usize)
+    }
+
+    /// Moves the addresses of DW_OP_addr operations by bias.
+    fn relocate(&mut self, bias: usize) {
+        let size = self.encoding.address_size as usize;
+        let mut patches = Vec::new();
+        let mut reader = gimli::EndianSlice::new(&self.bytecode, gimli::LittleEndian);
+        while !reader.is_empty() {
+            let offset = self.bytecode.len() - reader.len();
+            match gimli::Operation::parse(&mut reader, self.encoding) {
+                Ok(gimli::Operation::Address { address }) => patches.push((offset + 1, address)),
+                Ok(_) => {}
+                Err(_) => break,
+            }
+        }
+        for (offset, address) in patches {
+            let address = (address as usize).wrapping_add(bias) as u64;
+            self.bytecode[offset..offset + size].copy_from_slice(&address.to_le_bytes()[..size]);
+        }
+    }
+}
+
+impl fmt::Display for LocationExpression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:02x?}", self.bytecode)
+    }
+}
+
 #[derive(Clone)]
 pub enum Location {
-    Address(usize),
-    FramePointerOffset(isize),
+    /// An expression that is valid wherever the variable is in scope
+    Expression(LocationExpression),
+    /// A location list: expressions that are valid within [start, end) address ranges. Outside
+    /// of those ranges the variable has been optimized out.
+    List(Vec<(usize, usize, LocationExpression)>),
+}
+
+impl Location {
+    fn relocate(&mut self, bias: usize) {
+        match self {
+            Location::Expression(expr) => expr.relocate(bias),
+            Location::List(entries) => {
+                for (start, end, expr) in entries {
+                    *start = start.wrapping_add(bias);
+                    *end = end.wrapping_add(bias);
+                    expr.relocate(bias);
+                }
+            }
+        }
+    }
+
+    /// Returns the expression giving the variable's location when rip is at addr.
+    pub fn expression_at(&self, addr: usize) -> Option<&LocationExpression> {
+        match self {
+            Location::Expression(expr) => Some(expr),
+            Location::List(entries) => entries
+                .iter()
+                .find(|(start, end, _)| *start <= addr && addr < *end)
+                .map(|(_, _, expr)| expr),
+        }
+    }
 }
 
 impl fmt::Display for Location {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match *self {
-            Location::Address(addr) => write!(f, "Address({:#x})", addr),
-            Location::FramePointerOffset(offset) => write!(f, "FramePointerOffset({})", offset),
+        match self {
+            Location::Expression(expr) => write!(f, "Expression({})", expr),
+            Location::List(entries) => {
+                write!(f, "List(")?;
+                for (i, (start, end, expr)) in entries.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "[{:#x}, {:#x}): {}", start, end, expr)?;
+                }
+                write!(f, ")")
+            }
         }
     }
 }
@@ -199,9 +774,18 @@ pub struct Function {
     pub address: usize,
     pub text_length: usize,
     pub line_number: usize, // Line number in source file
+    pub frame_base: Option<LocationExpression>,
     pub variables: Vec<Variable>,
 }
 
+impl Function {
+    /// Returns true for a declaration of a function defined elsewhere (e.g. in a shared
+    /// library), which has no code.
+    pub fn is_declaration(&self) -> bool {
+        self.text_length == 0
+    }
+}
+
 #[derive(Debug, Default, Clone)]
 pub struct File {
     pub name: String,
@@ -210,6 +794,39 @@ pub struct File {
     pub lines: Vec<Line>,
 }
 
+/// Turns a mangled Rust (legacy or v0) or C++ symbol name back into the path it was written as,
+/// e.g. _ZN6rtypes8describe17hfd5f6a409438ddceE into rtypes::describe. C++ names leave out the
+/// parameter and return types, so that they can be used as breakpoint locations. Other names,
+/// like those of C functions, are returned as they are.
+fn demangle(name: &str) -> String {
+    if let Ok(demangled) = rustc_demangle::try_demangle(name) {
+        // The alternate format leaves out the hash that ends legacy Rust symbols
+        return format!("{:#}", demangled);
+    }
+    if name.starts_with("_Z") {
+        let options = cpp_demangle::DemangleOptions::new()
+            .no_params()
+            .no_return_type();
+        let demangled = cpp_demangle::Symbol::new(name)
+            .ok()
+            .and_then(|symbol| symbol.demangle(&options).ok());
+        if let Some(demangled) = demangled {
+            return demangled;
+        }
+    }
+    name.to_string()
+}
+
+/// A function or object from an ELF symbol table (with its name demangled)
+#[derive(Debug, Clone)]
+pub struct Symbol {
+    pub name: String,
+    pub address: usize,
+    /// Size in bytes, which may be 0 if unknown
+    pub size: usize,
+    pub is_function: bool,
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct Line {
     pub file: String,
@@ -223,4 +840,43 @@ impl fmt::Display for Line {
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn demangle_drops_the_hash_of_legacy_rust_symbols() {
+        assert_eq!(
+            demangle("_ZN6rtypes8describe17hfd5f6a409438ddceE"),
+            "rtypes::describe"
+        );
+        assert_eq!(
+            demangle("_ZN4core3ptr85drop_in_place$LT$std..rt..lang_start$LT$$LP$$RP$$GT$..$u7b$$u7b$closure$u7d$$u7d$$GT$17h0b5a1f3b8f5fd0c0E"),
+            "core::ptr::drop_in_place<std::rt::lang_start<()>::{{closure}}>"
+        );
+    }
+
+    #[test]
+    fn demangle_reads_rust_v0_symbols() {
+        assert_eq!(demangle("_RNvCs1234_6rtypes8describe"), "rtypes::describe");
+        assert_eq!(
+            demangle("_RNvMCs1234_6rtypesNtB2_5Point4norm"),
+            "<rtypes::Point>::norm"
+        );
+    }
+
+    #[test]
+    fn demangle_leaves_out_cpp_parameters_and_return_types() {
+        assert_eq!(demangle("_Z3addii"), "add");
+        assert_eq!(demangle("_ZN5shape4areaEv"), "shape::area");
+        assert_eq!(demangle("_ZNK5shape5scaleEd"), "shape::scale");
+        assert_eq!(demangle("_ZN2ns3maxIiEET_S1_S1_"), "ns::max<int>");
+    }
 
+    #[test]
+    fn demangle_leaves_other_names_alone() {
+        assert_eq!(demangle("main"), "main");
+        assert_eq!(demangle("_start"), "_start");
+        assert_eq!(demangle("_Znot_mangled"), "_Znot_mangled");
+    }
+}
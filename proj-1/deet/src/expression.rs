@@ -0,0 +1,796 @@
+//! Parsing and evaluation of the C-like expressions accepted by `print` and breakpoint
+//! conditions, e.g. `*p`, `p->x`, `pt.y`, `arr[2]`, `&n` or `x > 3 && !done`.
+
+use crate::dwarf_data::{DwarfData, Type, TypeKind, Variable};
+use crate::location::{self, Frame, Place};
+use crate::registers;
+use crate::target::Target;
+use crate::value::{encode_value, read_unsigned, value_as_float, value_as_integer, Value};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expression {
+    Variable(String),
+    Integer(i64),
+    Float(f64),
+    Negate(Box<Expression>),
+    Not(Box<Expression>),
+    Deref(Box<Expression>),
+    AddressOf(Box<Expression>),
+    /// `a.b` or `a->b` (pointers to structs are dereferenced automatically)
+    Member(Box<Expression>, String),
+    Index(Box<Expression>, Box<Expression>),
+    Binary(BinaryOp, Box<Expression>, Box<Expression>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinaryOp {
+    Or,
+    And,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+}
+
+/// Returns the operator for a punctuation token along with its precedence (higher binds
+/// tighter), following C.
+fn binary_op(punct: &str) -> Option<(BinaryOp, u8)> {
+    Some(match punct {
+        "||" => (BinaryOp::Or, 1),
+        "&&" => (BinaryOp::And, 2),
+        "==" => (BinaryOp::Eq, 3),
+        "!=" => (BinaryOp::Ne, 3),
+        "<" => (BinaryOp::Lt, 4),
+        "<=" => (BinaryOp::Le, 4),
+        ">" => (BinaryOp::Gt, 4),
+        ">=" => (BinaryOp::Ge, 4),
+        "+" => (BinaryOp::Add, 5),
+        "-" => (BinaryOp::Sub, 5),
+        "*" => (BinaryOp::Mul, 6),
+        "/" => (BinaryOp::Div, 6),
+        "%" => (BinaryOp::Rem, 6),
+        _ => return None,
+    })
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Integer(i64),
+    Float(f64),
+    Punct(&'static str),
+}
+
+// Longer tokens come first so that e.g. `->` isn't read as `-`
+const PUNCTUATION: &[&str] = &[
+    "->", "==", "!=", "<=", ">=", "&&", "||", "*", "&", "-", "+", "/", "%", "<", ">", "!", "(",
+    ")", "[", "]", ".",
+];
+
+fn tokenize(input: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = input.trim_start();
+    while !rest.is_empty() {
+        let c = rest.chars().next().unwrap();
+        if c.is_ascii_alphabetic() || c == '_' || c == '$' {
+            let len = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '$'))
+                .unwrap_or(rest.len());
+            tokens.push(Token::Ident(rest[..len].to_string()));
+            rest = &rest[len..];
+        } else if c.is_ascii_digit() {
+            let len = rest
+                .find(|c: char| !c.is_ascii_alphanumeric())
+                .unwrap_or(rest.len());
+            let mut literal = &rest[..len];
+            if rest[len..].starts_with('.') {
+                let fraction_len = rest[len + 1..]
+                    .find(|c: char| !c.is_ascii_digit())
+                    .unwrap_or_else(|| rest.len() - len - 1);
+                literal = &rest[..len + 1 + fraction_len];
+                match literal.parse::<f64>() {
+                    Ok(value) => tokens.push(Token::Float(value)),
+                    Err(_) => return Err(format!("Invalid number \"{}\".", literal)),
+                }
+            } else {
+                let value = if literal.starts_with("0x") || literal.starts_with("0X") {
+                    i64::from_str_radix(&literal[2..], 16)
+                } else {
+                    literal.parse::<i64>()
+                };
+                match value {
+                    Ok(value) => tokens.push(Token::Integer(value)),
+                    Err(_) => return Err(format!("Invalid number \"{}\".", literal)),
+                }
+            }
+            rest = &rest[literal.len()..];
+        } else {
+            match PUNCTUATION.iter().find(|punct| rest.starts_with(*punct)) {
+                Some(punct) => {
+                    tokens.push(Token::Punct(punct));
+                    rest = &rest[punct.len()..];
+                }
+                None => return Err(format!("Invalid character '{}' in expression.", c)),
+            }
+        }
+        rest = rest.trim_start();
+    }
+    Ok(tokens)
+}
+
+/// Parses an expression, returning a message describing the problem if it is malformed.
+pub fn parse(input: &str) -> Result<Expression, String> {
+    let tokens = tokenize(input)?;
+    let mut parser = Parser { tokens, pos: 0 };
+    let expression = parser.parse_binary(0)?;
+    if parser.pos != parser.tokens.len() {
+        return Err(parser.syntax_error());
+    }
+    Ok(expression)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn eat(&mut self, punct: &str) -> bool {
+        if self.peek() == Some(&Token::Punct(punct_str(punct))) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn remaining_input(&self) -> String {
+        let rest: Vec<String> = self.tokens[self.pos.min(self.tokens.len())..]
+            .iter()
+            .map(|token| match token {
+                Token::Ident(name) => name.clone(),
+                Token::Integer(value) => value.to_string(),
+                Token::Float(value) => value.to_string(),
+                Token::Punct(punct) => punct.to_string(),
+            })
+            .collect();
+        rest.concat()
+    }
+
+    fn syntax_error(&self) -> String {
+        format!(
+            "A syntax error in expression, near `{}'.",
+            self.remaining_input()
+        )
+    }
+
+    /// Parses a sequence of binary operations whose operators have at least the given
+    /// precedence (precedence climbing).
+    fn parse_binary(&mut self, min_precedence: u8) -> Result<Expression, String> {
+        let mut lhs = self.parse_unary()?;
+        while let Some(Token::Punct(punct)) = self.peek() {
+            let (op, precedence) = match binary_op(punct) {
+                Some((op, precedence)) if precedence >= min_precedence => (op, precedence),
+                _ => break,
+            };
+            self.pos += 1;
+            let rhs = self.parse_binary(precedence + 1)?;
+            lhs = Expression::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expression, String> {
+        if self.eat("!") {
+            Ok(Expression::Not(Box::new(self.parse_unary()?)))
+        } else if self.eat("*") {
+            Ok(Expression::Deref(Box::new(self.parse_unary()?)))
+        } else if self.eat("&") {
+            Ok(Expression::AddressOf(Box::new(self.parse_unary()?)))
+        } else if self.eat("-") {
+            Ok(Expression::Negate(Box::new(self.parse_unary()?)))
+        } else {
+            self.parse_postfix()
+        }
+    }
+
+    fn parse_postfix(&mut self) -> Result<Expression, String> {
+        let mut expression = self.parse_primary()?;
+        loop {
+            if self.eat(".") || self.eat("->") {
+                match self.next() {
+                    Some(Token::Ident(field)) => {
+                        expression = Expression::Member(Box::new(expression), field)
+                    }
+                    _ => {
+                        self.pos -= 1;
+                        return Err(self.syntax_error());
+                    }
+                }
+            } else if self.eat("[") {
+                let index = self.parse_binary(0)?;
+                if !self.eat("]") {
+                    return Err(self.syntax_error());
+                }
+                expression = Expression::Index(Box::new(expression), Box::new(index));
+            } else {
+                return Ok(expression);
+            }
+        }
+    }
+
+    fn parse_primary(&mut self) -> Result<Expression, String> {
+        match self.next() {
+            Some(Token::Ident(name)) => Ok(Expression::Variable(name)),
+            Some(Token::Integer(value)) => Ok(Expression::Integer(value)),
+            Some(Token::Float(value)) => Ok(Expression::Float(value)),
+            Some(Token::Punct("(")) => {
+                let expression = self.parse_binary(0)?;
+                if !self.eat(")") {
+                    return Err(self.syntax_error());
+                }
+                Ok(expression)
+            }
+            _ => {
+                self.pos -= 1;
+                Err(self.syntax_error())
+            }
+        }
+    }
+}
+
+/// Maps a punctuation string onto the static string stored in tokens.
+fn punct_str(punct: &str) -> &'static str {
+    PUNCTUATION
+        .iter()
+        .find(|candidate| **candidate == punct)
+        .unwrap()
+}
+
+/// Evaluates expressions against the current state of a stopped inferior (or a core dump).
+pub struct Evaluator<'a> {
+    debug_data: &'a DwarfData,
+    inferior: &'a dyn Target,
+}
+
+impl<'a> Evaluator<'a> {
+    pub fn new(debug_data: &'a DwarfData, inferior: &'a dyn Target) -> Evaluator<'a> {
+        Evaluator {
+            debug_data,
+            inferior,
+        }
+    }
+
+    pub fn evaluate(&self, expression: &Expression) -> Result<Value, String> {
+        match expression {
+            Expression::Variable(name) if name.starts_with('$') => self.read_register(&name[1..]),
+            Expression::Variable(name) => {
+                match self.debug_data.get_variable(self.inferior.rip(), name) {
+                    Some(var) => self.read_variable(var),
+                    None => Err(format!("No symbol \"{}\" in current context.", name)),
+                }
+            }
+            Expression::Integer(value) => Ok(integer_value(*value)),
+            Expression::Float(value) => Ok(float_value(*value)),
+            Expression::Negate(inner) => {
+                let value = self.evaluate(inner)?;
+                match self.as_number(&value)? {
+                    Number::Integer(integer) => Ok(integer_value(integer.wrapping_neg())),
+                    Number::Float(float) => Ok(float_value(-float)),
+                }
+            }
+            Expression::Not(inner) => {
+                let value = self.evaluate(inner)?;
+                Ok(bool_value(!self.is_true(&value)?))
+            }
+            Expression::Binary(BinaryOp::And, lhs, rhs) => {
+                let lhs = self.evaluate(lhs)?;
+                Ok(bool_value(
+                    self.is_true(&lhs)? && self.is_true(&self.evaluate(rhs)?)?,
+                ))
+            }
+            Expression::Binary(BinaryOp::Or, lhs, rhs) => {
+                let lhs = self.evaluate(lhs)?;
+                Ok(bool_value(
+                    self.is_true(&lhs)? || self.is_true(&self.evaluate(rhs)?)?,
+                ))
+            }
+            Expression::Binary(op, lhs, rhs) => {
+                let lhs = self.evaluate(lhs)?;
+                let rhs = self.evaluate(rhs)?;
+                self.binary(*op, &lhs, &rhs)
+            }
+            Expression::Deref(inner) => {
+                let value = self.evaluate(inner)?;
+                self.deref(&value)
+            }
+            Expression::AddressOf(inner) => {
+                let value = self.evaluate(inner)?;
+                let addr = match value.address {
+                    Some(addr) => addr,
+                    None => {
+                        return Err(
+                            "Attempt to take address of value not located in memory.".to_string()
+                        )
+                    }
+                };
+                Ok(Value {
+                    entity_type: Type {
+                        name: format!("{} *", value.entity_type.name),
+                        size: 8,
+                        kind: TypeKind::Pointer(value.entity_type.offset),
+                        type_params: Vec::new(),
+                        offset: None,
+                    },
+                    bytes: (addr as u64).to_le_bytes().to_vec(),
+                    address: None,
+                })
+            }
+            Expression::Member(inner, field) => {
+                let mut value = self.evaluate(inner)?;
+                if let TypeKind::Pointer(_) = self.debug_data.resolve_type(&value.entity_type).kind
+                {
+                    value = self.deref(&value)?;
+                }
+                self.member(&value, field)
+            }
+            Expression::Index(inner, index) => {
+                let value = self.evaluate(inner)?;
+                let index_value = self.evaluate(index)?;
+                let index = match value_as_integer(self.debug_data, &index_value) {
+                    Some(index) => index,
+                    None => return Err("Array index is not an integer.".to_string()),
+                };
+                self.index(&value, index)
+            }
+        }
+    }
+
+    /// Evaluates both sides of an assignment `lhs = rhs`. Returns the address lhs lives at and
+    /// the value of rhs converted to lhs's type, ready to be written there.
+    pub fn assignment(
+        &self,
+        lhs: &Expression,
+        rhs: &Expression,
+    ) -> Result<(usize, Vec<u8>), String> {
+        let target = self.evaluate(lhs)?;
+        let addr = target
+            .address
+            .ok_or_else(|| "Left operand of assignment is not in memory.".to_string())?;
+        let value = self.evaluate(rhs)?;
+        Ok((
+            addr,
+            encode_value(self.debug_data, &target.entity_type, &value)?,
+        ))
+    }
+
+    /// Returns whether a value is non-zero, as in a C condition.
+    pub fn is_true(&self, value: &Value) -> Result<bool, String> {
+        Ok(match self.as_number(value)? {
+            Number::Integer(integer) => integer != 0,
+            Number::Float(float) => float != 0.0,
+        })
+    }
+
+    fn as_number(&self, value: &Value) -> Result<Number, String> {
+        if let Some(integer) = value_as_integer(self.debug_data, value) {
+            Ok(Number::Integer(integer))
+        } else if let Some(float) = value_as_float(self.debug_data, value) {
+            Ok(Number::Float(float))
+        } else {
+            Err("Argument to arithmetic operation not a number or boolean.".to_string())
+        }
+    }
+
+    fn binary(&self, op: BinaryOp, lhs: &Value, rhs: &Value) -> Result<Value, String> {
+        match (self.as_number(lhs)?, self.as_number(rhs)?) {
+            (Number::Integer(a), Number::Integer(b)) => Ok(match op {
+                BinaryOp::Add => integer_value(a.wrapping_add(b)),
+                BinaryOp::Sub => integer_value(a.wrapping_sub(b)),
+                BinaryOp::Mul => integer_value(a.wrapping_mul(b)),
+                BinaryOp::Div | BinaryOp::Rem if b == 0 => {
+                    return Err("Division by zero".to_string())
+                }
+                BinaryOp::Div => integer_value(a.wrapping_div(b)),
+                BinaryOp::Rem => integer_value(a.wrapping_rem(b)),
+                _ => bool_value(compare(op, a, b)),
+            }),
+            (a, b) => {
+                let (a, b) = (a.to_f64(), b.to_f64());
+                Ok(match op {
+                    BinaryOp::Add => float_value(a + b),
+                    BinaryOp::Sub => float_value(a - b),
+                    BinaryOp::Mul => float_value(a * b),
+                    BinaryOp::Div => float_value(a / b),
+                    BinaryOp::Rem => {
+                        return Err("Integer only operation %.".to_string());
+                    }
+                    _ => bool_value(compare(op, a, b)),
+                })
+            }
+        }
+    }
+
+    /// Reads the current value of a variable from the inferior. If the variable has been
+    /// optimized out at the current address, the value has no bytes.
+    pub fn read_variable(&self, var: &Variable) -> Result<Value, String> {
+        let rip = self.inferior.rip();
+        let expr = match var.location.expression_at(rip) {
+            Some(expr) => expr,
+            None => {
+                return Ok(Value {
+                    entity_type: var.entity_type.clone(),
+                    bytes: Vec::new(),
+                    address: None,
+                })
+            }
+        };
+
+        let to_string = |err: nix::Error| err.to_string();
+        let regs = self.inferior.get_registers().map_err(to_string)?;
+        let fpregs = self.inferior.get_fpregs().ok();
+        let read_memory = |addr, len| self.inferior.read_memory(addr, len);
+        let mut frame = Frame {
+            regs: &regs,
+            fpregs: fpregs.as_ref(),
+            cfa: self.inferior.get_cfa(self.debug_data).map_err(to_string)?,
+            frame_base: None,
+            read_memory: &read_memory,
+        };
+        // The frame base is usually DW_OP_call_frame_cfa, or a register for clang
+        let frame_base = self
+            .debug_data
+            .get_function_containing(rip)
+            .and_then(|func| func.frame_base.as_ref());
+        if let Some(frame_base) = frame_base {
+            frame.frame_base = match location::evaluate(frame_base, &frame)? {
+                Place::Memory(addr) => Some(addr),
+                Place::Register(register) => {
+                    Some(location::register_value(&regs, register)? as usize)
+                }
+                _ => None,
+            };
+        }
+
+        let place = location::evaluate(expr, &frame)?;
+        Ok(Value {
+            bytes: location::read_place(&place, var.entity_type.size, &frame)?,
+            entity_type: var.entity_type.clone(),
+            address: location::place_address(&place),
+        })
+    }
+
+    /// Reads a register named as in `$rax` or `$pc`. The stack and code registers are given
+    /// pointer types so that they can be dereferenced.
+    fn read_register(&self, name: &str) -> Result<Value, String> {
+        let regs = self
+            .inferior
+            .get_registers()
+            .map_err(|err| err.to_string())?;
+        let value = registers::register_by_name(&regs, name)
+            .ok_or_else(|| format!("Invalid register `{}'", name))?;
+        let type_name = match name {
+            "rip" | "pc" => "void (*)()",
+            "rsp" | "sp" | "rbp" | "fp" => "void *",
+            _ => "long",
+        };
+        let mut entity_type = Type::new(type_name.to_string(), 8);
+        if type_name != "long" {
+            entity_type.kind = TypeKind::Pointer(None);
+        }
+        Ok(Value {
+            entity_type,
+            bytes: value.to_le_bytes().to_vec(),
+            address: None,
+        })
+    }
+
+    fn read_value(&self, entity_type: Type, addr: usize) -> Result<Value, String> {
+        let bytes = self
+            .inferior
+            .read_memory(addr, entity_type.size)
+            .map_err(|_| format!("Cannot access memory at address {:#x}", addr))?;
+        Ok(Value {
+            entity_type,
+            bytes,
+            address: Some(addr),
+        })
+    }
+
+    fn deref(&self, value: &Value) -> Result<Value, String> {
+        let target = match self.debug_data.resolve_type(&value.entity_type).kind {
+            TypeKind::Pointer(Some(target)) => target,
+            TypeKind::Pointer(None) => {
+                return Err("Attempt to take contents of a non-pointer value.".to_string())
+            }
+            TypeKind::Array(element_type, _) => {
+                // An array decays to a pointer to its first element
+                let addr = value
+                    .address
+                    .ok_or_else(|| "Array is not located in memory.".to_string())?;
+                return self.read_value(self.lookup_type(element_type)?, addr);
+            }
+            TypeKind::Base => {
+                // Like gdb, treat `*<integer>` (e.g. `*0x404018`) as an int at that address
+                return match value_as_integer(self.debug_data, value) {
+                    Some(addr) => self.read_value(Type::new("int".to_string(), 4), addr as usize),
+                    None => Err("Attempt to take contents of a non-pointer value.".to_string()),
+                };
+            }
+            _ => return Err("Attempt to take contents of a non-pointer value.".to_string()),
+        };
+        let addr = read_unsigned(&value.bytes) as usize;
+        self.read_value(self.lookup_type(target)?, addr)
+    }
+
+    fn member(&self, value: &Value, field: &str) -> Result<Value, String> {
+        let members = match self.debug_data.resolve_type(&value.entity_type).kind {
+            TypeKind::Struct(ref members) | TypeKind::Union(ref members) => members,
+            _ => {
+                return Err(
+                    "Attempt to extract a component of a value that is not a structure."
+                        .to_string(),
+                )
+            }
+        };
+        let member = members
+            .iter()
+            .find(|member| member.name == field)
+            .ok_or_else(|| format!("There is no member named {}.", field))?;
+        let member_type = self.lookup_type(member.member_type)?;
+        let end = member.offset + member_type.size;
+        if end > value.bytes.len() {
+            return Err(format!("Member {} lies outside of its structure.", field));
+        }
+        Ok(Value {
+            bytes: value.bytes[member.offset..end].to_vec(),
+            entity_type: member_type,
+            address: value.address.map(|addr| addr + member.offset),
+        })
+    }
+
+    fn index(&self, value: &Value, index: i64) -> Result<Value, String> {
+        let (base_addr, element_type) = match self.debug_data.resolve_type(&value.entity_type).kind
+        {
+            TypeKind::Array(element_type, ref counts) => {
+                let addr = value
+                    .address
+                    .ok_or_else(|| "Array is not located in memory.".to_string())?;
+                let element_type = if counts.len() > 1 {
+                    // Indexing a multi-dimensional array yields a row
+                    let rows = counts[0].unwrap_or(1).max(1);
+                    Type {
+                        name: String::new(),
+                        size: value.entity_type.size / rows,
+                        kind: TypeKind::Array(element_type, counts[1..].to_vec()),
+                        type_params: Vec::new(),
+                        offset: None,
+                    }
+                } else {
+                    self.lookup_type(element_type)?
+                };
+                (addr, element_type)
+            }
+            TypeKind::Pointer(Some(target)) => (
+                read_unsigned(&value.bytes) as usize,
+                self.lookup_type(target)?,
+            ),
+            _ => return Err("cannot subscript something of this type".to_string()),
+        };
+        let addr = (base_addr as i64).wrapping_add(index.wrapping_mul(element_type.size as i64));
+        self.read_value(element_type, addr as usize)
+    }
+
+    fn lookup_type(&self, offset: usize) -> Result<Type, String> {
+        self.debug_data
+            .get_type(offset)
+            .cloned()
+            .ok_or_else(|| "Incomplete type information.".to_string())
+    }
+}
+
+enum Number {
+    Integer(i64),
+    Float(f64),
+}
+
+impl Number {
+    fn to_f64(&self) -> f64 {
+        match *self {
+            Number::Integer(integer) => integer as f64,
+            Number::Float(float) => float,
+        }
+    }
+}
+
+fn compare<T: PartialOrd>(op: BinaryOp, a: T, b: T) -> bool {
+    match op {
+        BinaryOp::Eq => a == b,
+        BinaryOp::Ne => a != b,
+        BinaryOp::Lt => a < b,
+        BinaryOp::Le => a <= b,
+        BinaryOp::Gt => a > b,
+        BinaryOp::Ge => a >= b,
+        _ => unreachable!(),
+    }
+}
+
+/// Comparisons and logical operators produce an int, as in C.
+fn bool_value(value: bool) -> Value {
+    Value {
+        entity_type: Type::new("int".to_string(), 4),
+        bytes: (value as i32).to_le_bytes().to_vec(),
+        address: None,
+    }
+}
+
+fn float_value(value: f64) -> Value {
+    Value {
+        entity_type: Type::new("double".to_string(), 8),
+        bytes: value.to_bits().to_le_bytes().to_vec(),
+        address: None,
+    }
+}
+
+fn integer_value(value: i64) -> Value {
+    Value {
+        entity_type: Type::new("long".to_string(), 8),
+        bytes: value.to_le_bytes().to_vec(),
+        address: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn var(name: &str) -> Box<Expression> {
+        Box::new(Expression::Variable(name.to_string()))
+    }
+
+    fn int(value: i64) -> Box<Expression> {
+        Box::new(Expression::Integer(value))
+    }
+
+    fn binary(op: BinaryOp, lhs: Box<Expression>, rhs: Box<Expression>) -> Box<Expression> {
+        Box::new(Expression::Binary(op, lhs, rhs))
+    }
+
+    #[test]
+    fn parse_follows_c_precedence() {
+        assert_eq!(
+            parse("a + b * 2"),
+            Ok(*binary(
+                BinaryOp::Add,
+                var("a"),
+                binary(BinaryOp::Mul, var("b"), int(2))
+            ))
+        );
+        assert_eq!(
+            parse("x > 3 && !done || y == 1"),
+            Ok(*binary(
+                BinaryOp::Or,
+                binary(
+                    BinaryOp::And,
+                    binary(BinaryOp::Gt, var("x"), int(3)),
+                    Box::new(Expression::Not(var("done")))
+                ),
+                binary(BinaryOp::Eq, var("y"), int(1))
+            ))
+        );
+        assert_eq!(
+            parse("(a + b) * 2"),
+            Ok(*binary(
+                BinaryOp::Mul,
+                binary(BinaryOp::Add, var("a"), var("b")),
+                int(2)
+            ))
+        );
+    }
+
+    #[test]
+    fn parse_binary_operators_are_left_associative() {
+        assert_eq!(
+            parse("10 - 4 - 3"),
+            Ok(*binary(
+                BinaryOp::Sub,
+                binary(BinaryOp::Sub, int(10), int(4)),
+                int(3)
+            ))
+        );
+    }
+
+    #[test]
+    fn parse_postfix_binds_tighter_than_prefix() {
+        assert_eq!(
+            parse("*p->next"),
+            Ok(Expression::Deref(Box::new(Expression::Member(
+                var("p"),
+                "next".to_string()
+            ))))
+        );
+        assert_eq!(
+            parse("&arr[2]"),
+            Ok(Expression::AddressOf(Box::new(Expression::Index(
+                var("arr"),
+                int(2)
+            ))))
+        );
+        assert_eq!(
+            parse("-pt.y * 2"),
+            Ok(*binary(
+                BinaryOp::Mul,
+                Box::new(Expression::Negate(Box::new(Expression::Member(
+                    var("pt"),
+                    "y".to_string()
+                )))),
+                int(2)
+            ))
+        );
+    }
+
+    #[test]
+    fn parse_reads_numbers_and_registers() {
+        assert_eq!(parse("0x1f"), Ok(Expression::Integer(31)));
+        assert_eq!(parse("2.5"), Ok(Expression::Float(2.5)));
+        assert_eq!(parse("$rip"), Ok(*var("$rip")));
+        assert_eq!(
+            parse("m[i][j + 1]"),
+            Ok(Expression::Index(
+                Box::new(Expression::Index(var("m"), var("i"))),
+                binary(BinaryOp::Add, var("j"), int(1))
+            ))
+        );
+    }
+
+    #[test]
+    fn parse_reports_syntax_errors() {
+        assert_eq!(
+            parse("1 +"),
+            Err("A syntax error in expression, near `'.".to_string())
+        );
+        assert_eq!(
+            parse("a b"),
+            Err("A syntax error in expression, near `b'.".to_string())
+        );
+        assert_eq!(
+            parse("arr[1"),
+            Err("A syntax error in expression, near `'.".to_string())
+        );
+        assert_eq!(
+            parse("p->3"),
+            Err("A syntax error in expression, near `3'.".to_string())
+        );
+        assert_eq!(
+            parse("(a + b"),
+            Err("A syntax error in expression, near `'.".to_string())
+        );
+    }
+
+    #[test]
+    fn parse_reports_bad_tokens() {
+        assert_eq!(
+            parse("a @ b"),
+            Err("Invalid character '@' in expression.".to_string())
+        );
+        assert_eq!(parse("12ab"), Err("Invalid number \"12ab\".".to_string()));
+        assert_eq!(parse("0xzz"), Err("Invalid number \"0xzz\".".to_string()));
+    }
+}
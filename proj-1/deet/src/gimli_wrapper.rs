@@ -5,18 +5,22 @@
 //!
 //! This code is a huge mess. Please don't read it unless you're trying to do an extension :)
 
-use gimli;
 use gimli::{UnitOffset, UnitSectionOffset};
 use object::Object;
 use std::borrow;
 //use std::io::{BufWriter, Write};
-use crate::dwarf_data::{File, Function, Line, Location, Type, Variable};
+use crate::dwarf_data::{
+    File, Function, Line, Location, LocationExpression, Member, Type, TypeKind, Variable, Variant,
+};
 use std::collections::HashMap;
 use std::convert::TryInto;
 use std::fmt::Write;
 use std::{io, path};
 
-pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<Vec<File>, Error> {
+pub fn load_file(
+    object: &object::File,
+    endian: gimli::RunTimeEndian,
+) -> Result<(Vec<File>, HashMap<usize, Type>), Error> {
     // Load a section and return as `Cow<[u8]>`.
     let load_section = |id: gimli::SectionId| -> Result<borrow::Cow<[u8]>, gimli::Error> {
         Ok(object
@@ -34,7 +38,7 @@ pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<
     let borrow_section: &dyn for<'a> Fn(
         &'a borrow::Cow<[u8]>,
     ) -> gimli::EndianSlice<'a, gimli::RunTimeEndian> =
-        &|section| gimli::EndianSlice::new(&*section, endian);
+        &|section| gimli::EndianSlice::new(section, endian);
 
     // Create `EndianSlice`s for all of the sections.
     let dwarf = dwarf_cow.borrow(&borrow_section);
@@ -49,16 +53,26 @@ pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<
     while let Some(header) = iter.next()? {
         let unit = dwarf.unit(header)?;
 
+        // Record the types first, since variables may refer to types that are declared later
+        // in the unit.
+        load_types(&unit, &dwarf, &mut offset_to_type)?;
+
         // Iterate over the Debugging Information Entries (DIEs) in the unit.
         let mut depth = 0;
+        // Depth of the function whose DIEs we are in, if any. Rust puts statics in namespaces,
+        // so anything outside a function is global, however deep it is.
+        let mut function_depth = None;
         let mut entries = unit.entries();
         while let Some((delta_depth, entry)) = entries.next_dfs()? {
             depth += delta_depth;
+            if function_depth.is_some_and(|function_depth| depth <= function_depth) {
+                function_depth = None;
+            }
             // Update the offset_to_type mapping for types
             // Update the variable list for formal params/variables
             match entry.tag() {
                 gimli::DW_TAG_compile_unit => {
-                    let name = if let Ok(Some(attr)) = entry.attr(gimli::DW_AT_name) {
+                    let mut name = if let Ok(Some(attr)) = entry.attr(gimli::DW_AT_name) {
                         if let Ok(DebugValue::Str(name)) = get_attr_value(&attr, &unit, &dwarf) {
                             name
                         } else {
@@ -67,6 +81,19 @@ pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<
                     } else {
                         "<unknown>".to_string()
                     };
+                    // rustc names units "<crate root>/@/<codegen unit>", with the crate root
+                    // relative to the compilation directory. Use the crate root's full path, which
+                    // is what the line table calls it.
+                    if let Some(idx) = name.find("/@/") {
+                        let mut root = path::PathBuf::new();
+                        if let Some(dir) =
+                            get_attr_string(entry, gimli::DW_AT_comp_dir, &unit, &dwarf)
+                        {
+                            root.push(dir);
+                        }
+                        root.push(&name[..idx]);
+                        name = root.to_string_lossy().to_string();
+                    }
                     compilation_units.push(File {
                         name,
                         global_variables: Vec::new(),
@@ -74,33 +101,6 @@ pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<
                         lines: Vec::new(),
                     });
                 }
-                gimli::DW_TAG_base_type => {
-                    let name = if let Ok(Some(attr)) = entry.attr(gimli::DW_AT_name) {
-                        if let Ok(DebugValue::Str(name)) = get_attr_value(&attr, &unit, &dwarf) {
-                            name
-                        } else {
-                            "<unknown>".to_string()
-                        }
-                    } else {
-                        "<unknown>".to_string()
-                    };
-                    let byte_size = if let Ok(Some(attr)) = entry.attr(gimli::DW_AT_byte_size) {
-                        if let Ok(DebugValue::Uint(byte_size)) =
-                            get_attr_value(&attr, &unit, &dwarf)
-                        {
-                            byte_size
-                        } else {
-                            // TODO: report error?
-                            0
-                        }
-                    } else {
-                        // TODO: report error?
-                        0
-                    };
-                    let type_offset = entry.offset().0;
-                    offset_to_type
-                        .insert(type_offset, Type::new(name, byte_size.try_into().unwrap()));
-                }
                 gimli::DW_TAG_subprogram => {
                     let mut func: Function = Default::default();
                     let mut attrs = entry.attrs();
@@ -129,10 +129,17 @@ pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<
                                     func.line_number = line_number.try_into().unwrap();
                                 }
                             }
+                            gimli::DW_AT_frame_base => {
+                                if let gimli::AttributeValue::Exprloc(ref data) = attr.value() {
+                                    func.frame_base =
+                                        get_location_expression(data, unit.encoding());
+                                }
+                            }
                             _ => {}
                         }
                     }
                     compilation_units.last_mut().unwrap().functions.push(func);
+                    function_depth = Some(depth);
                 }
                 gimli::DW_TAG_formal_parameter | gimli::DW_TAG_variable => {
                     let mut name = String::new();
@@ -151,13 +158,14 @@ pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<
                             }
                             gimli::DW_AT_type => {
                                 if let Ok(DebugValue::Size(offset)) = val {
-                                    if let Some(dtype) = offset_to_type.get(&offset).clone() {
-                                        entity_type = Some(dtype.clone());
-                                    }
+                                    entity_type = Some(match offset_to_type.get(&offset) {
+                                        Some(dtype) => dtype.clone(),
+                                        None => Type::new("<unknown type>".to_string(), 0),
+                                    });
                                 }
                             }
                             gimli::DW_AT_location => {
-                                if let Some(loc) = get_location(&attr, &unit) {
+                                if let Some(loc) = get_location(&attr, &unit, &dwarf) {
                                     location = Some(loc);
                                 }
                             }
@@ -169,20 +177,25 @@ pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<
                             _ => {}
                         }
                     }
-                    if entity_type.is_some() && location.is_some() {
+                    // Locals without a location have been optimized out entirely. (Globals
+                    // without one are just declarations.)
+                    if location.is_none() && function_depth.is_some() && !name.is_empty() {
+                        location = Some(Location::List(Vec::new()));
+                    }
+                    if let (Some(entity_type), Some(location)) = (entity_type, location) {
                         let var = Variable {
                             name,
-                            entity_type: entity_type.unwrap(),
-                            location: location.unwrap(),
+                            entity_type,
+                            location,
                             line_number: line_number.try_into().unwrap(),
                         };
-                        if depth == 1 {
+                        if function_depth.is_none() {
                             compilation_units
                                 .last_mut()
                                 .unwrap()
                                 .global_variables
                                 .push(var);
-                        } else if depth > 1 {
+                        } else {
                             compilation_units
                                 .last_mut()
                                 .unwrap()
@@ -240,47 +253,386 @@ pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<
             }
         }
     }
-    Ok(compilation_units)
+    Ok((compilation_units, offset_to_type))
+}
+
+/// Records every type declared in the unit in offset_to_type, keyed by its offset in
+/// .debug_info. Types refer to each other (e.g. a pointer to its pointee) by those offsets, which
+/// lets recursive types like linked list nodes be represented.
+fn load_types<R: Reader>(
+    unit: &gimli::Unit<R>,
+    dwarf: &gimli::Dwarf<R>,
+    offset_to_type: &mut HashMap<usize, Type>,
+) -> Result<(), Error> {
+    let mut unit_types: Vec<usize> = Vec::new();
+    // Enclosing struct/union/enum/array/subroutine types, as (depth, offset, tag), so that
+    // members, enumerators, subranges and parameters can be attached to them. The variant parts
+    // of Rust enums and their variants are pushed with the offset of the enum.
+    let mut type_stack: Vec<(isize, usize, gimli::DwTag)> = Vec::new();
+    let mut is_rust = false;
+    let mut depth = 0;
+    let mut entries = unit.entries();
+    while let Some((delta_depth, entry)) = entries.next_dfs()? {
+        depth += delta_depth;
+        while type_stack.last().is_some_and(|&(d, _, _)| d >= depth) {
+            type_stack.pop();
+        }
+        let (parent_offset, parent_tag) = match type_stack.last() {
+            Some(&(d, offset, tag)) if d == depth - 1 => (Some(offset), tag),
+            _ => (None, gimli::DW_TAG_null),
+        };
+        let parent = parent_offset.and_then(|offset| offset_to_type.get_mut(&offset));
+
+        let name = get_attr_string(entry, gimli::DW_AT_name, unit, dwarf);
+        let byte_size = get_attr_uint(entry, gimli::DW_AT_byte_size).unwrap_or(0) as usize;
+        let target = get_attr_type(entry, unit);
+        let kind = match entry.tag() {
+            gimli::DW_TAG_compile_unit => {
+                is_rust = get_attr_uint(entry, gimli::DW_AT_language)
+                    == Some(gimli::DW_LANG_Rust.0 as u64);
+                continue;
+            }
+            gimli::DW_TAG_base_type => TypeKind::Base,
+            gimli::DW_TAG_pointer_type => TypeKind::Pointer(target),
+            gimli::DW_TAG_const_type => TypeKind::Const(target),
+            gimli::DW_TAG_volatile_type => TypeKind::Volatile(target),
+            gimli::DW_TAG_typedef => TypeKind::Typedef(target),
+            gimli::DW_TAG_structure_type | gimli::DW_TAG_class_type => TypeKind::Struct(Vec::new()),
+            gimli::DW_TAG_union_type => TypeKind::Union(Vec::new()),
+            gimli::DW_TAG_enumeration_type => TypeKind::Enum(Vec::new()),
+            gimli::DW_TAG_array_type => match target {
+                Some(element_type) => TypeKind::Array(element_type, Vec::new()),
+                None => continue,
+            },
+            gimli::DW_TAG_subroutine_type => TypeKind::Function(target, Vec::new()),
+            gimli::DW_TAG_member => {
+                if let (Some(parent), Some(member_type)) = (parent, target) {
+                    let member = Member {
+                        name: name.unwrap_or_default(),
+                        member_type,
+                        offset: get_member_offset(entry, unit).unwrap_or(0),
+                    };
+                    match parent.kind {
+                        TypeKind::Struct(ref mut members) | TypeKind::Union(ref mut members) => {
+                            members.push(member)
+                        }
+                        TypeKind::Variants(ref mut discriminant, ref mut variants) => {
+                            if parent_tag == gimli::DW_TAG_variant {
+                                if let Some(variant) = variants.last_mut() {
+                                    variant.members.push(member);
+                                }
+                            } else {
+                                *discriminant = Some(member);
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+                continue;
+            }
+            // A Rust enum is a struct whose only child is a variant part, holding the
+            // discriminant member and a variant entry for each of the enum's variants
+            gimli::DW_TAG_variant_part => {
+                if let (Some(parent), Some(offset)) = (parent, parent_offset) {
+                    parent.kind = TypeKind::Variants(None, Vec::new());
+                    type_stack.push((depth, offset, entry.tag()));
+                }
+                continue;
+            }
+            gimli::DW_TAG_variant => {
+                if let Some(TypeKind::Variants(_, ref mut variants)) = parent.map(|p| &mut p.kind) {
+                    let discriminant =
+                        entry
+                            .attr(gimli::DW_AT_discr_value)?
+                            .and_then(|attr| match attr.value() {
+                                gimli::AttributeValue::Sdata(value) => Some(value as u64),
+                                _ => attr.udata_value(),
+                            });
+                    variants.push(Variant {
+                        discriminant,
+                        members: Vec::new(),
+                    });
+                    type_stack.push((depth, parent_offset.unwrap(), entry.tag()));
+                }
+                continue;
+            }
+            gimli::DW_TAG_template_type_parameter => {
+                if let (Some(parent), Some(param_type)) = (parent, target) {
+                    parent.type_params.push(param_type);
+                }
+                continue;
+            }
+            gimli::DW_TAG_subrange_type => {
+                if let Some(TypeKind::Array(_, ref mut counts)) = parent.map(|p| &mut p.kind) {
+                    let count = match get_attr_uint(entry, gimli::DW_AT_count) {
+                        Some(count) => Some(count as usize),
+                        None => get_attr_uint(entry, gimli::DW_AT_upper_bound)
+                            .map(|upper_bound| upper_bound as usize + 1),
+                    };
+                    counts.push(count);
+                }
+                continue;
+            }
+            gimli::DW_TAG_enumerator => {
+                if let Some(TypeKind::Enum(ref mut enumerators)) = parent.map(|p| &mut p.kind) {
+                    if let Some(attr) = entry.attr(gimli::DW_AT_const_value)? {
+                        let value = match attr.value() {
+                            gimli::AttributeValue::Sdata(value) => value,
+                            _ => attr.udata_value().unwrap_or(0) as i64,
+                        };
+                        enumerators.push((name.unwrap_or_default(), value));
+                    }
+                }
+                continue;
+            }
+            gimli::DW_TAG_formal_parameter => {
+                if let Some(TypeKind::Function(_, ref mut params)) = parent.map(|p| &mut p.kind) {
+                    if let Some(param_type) = target {
+                        params.push(param_type);
+                    }
+                }
+                continue;
+            }
+            _ => continue,
+        };
+
+        // Derived types (pointers, typedefs, ...) get their names once all types are known.
+        // Struct, union and enum names are prefixed like in C, which also tells apart anonymous
+        // ones. Rust types go by their names alone.
+        let name = match entry.tag() {
+            _ if is_rust => name.unwrap_or_default(),
+            gimli::DW_TAG_structure_type | gimli::DW_TAG_class_type => {
+                format!("struct {}", name.unwrap_or("{...}".to_string()))
+            }
+            gimli::DW_TAG_union_type => format!("union {}", name.unwrap_or("{...}".to_string())),
+            gimli::DW_TAG_enumeration_type => {
+                format!("enum {}", name.unwrap_or("{...}".to_string()))
+            }
+            _ => name.unwrap_or_default(),
+        };
+        let type_offset = get_global_offset(unit, entry.offset());
+        match kind {
+            TypeKind::Struct(_)
+            | TypeKind::Union(_)
+            | TypeKind::Enum(_)
+            | TypeKind::Array(..)
+            | TypeKind::Function(..) => type_stack.push((depth, type_offset, entry.tag())),
+            _ => {}
+        }
+        offset_to_type.insert(
+            type_offset,
+            Type {
+                name,
+                size: byte_size,
+                kind,
+                type_params: Vec::new(),
+                offset: Some(type_offset),
+            },
+        );
+        unit_types.push(type_offset);
+    }
+
+    // Now that every type in the unit is known, fill in the names of derived types and the sizes
+    // of types that don't have a DW_AT_byte_size of their own.
+    let resolved: Vec<(usize, String, usize)> = unit_types
+        .iter()
+        .map(|&offset| {
+            (
+                offset,
+                get_type_name(offset_to_type, Some(offset), 0),
+                get_type_size(offset_to_type, offset, 0),
+            )
+        })
+        .collect();
+    for (offset, name, size) in resolved {
+        let dtype = offset_to_type.get_mut(&offset).unwrap();
+        dtype.name = name;
+        dtype.size = size;
+    }
+    Ok(())
+}
+
+// Bounds the recursion in get_type_name/get_type_size in case of malformed DWARF.
+const MAX_TYPE_DEPTH: usize = 32;
+
+fn get_type_name(types: &HashMap<usize, Type>, offset: Option<usize>, depth: usize) -> String {
+    let dtype = match offset {
+        Some(offset) => match types.get(&offset) {
+            Some(dtype) => dtype,
+            None => return "<unknown type>".to_string(),
+        },
+        None => return "void".to_string(),
+    };
+    // Named types (including e.g. Rust's "&str", which is a named pointer type) keep their name
+    if !dtype.name.is_empty() || depth > MAX_TYPE_DEPTH {
+        return dtype.name.clone();
+    }
+    match dtype.kind {
+        TypeKind::Pointer(target) => match target.and_then(|target| types.get(&target)) {
+            Some(Type {
+                kind: TypeKind::Function(ret, ref params),
+                ..
+            }) => format!(
+                "{} (*)({})",
+                get_type_name(types, *ret, depth + 1),
+                get_param_names(types, params, depth)
+            ),
+            _ => format!("{} *", get_type_name(types, target, depth + 1)),
+        },
+        TypeKind::Const(target) => format!("const {}", get_type_name(types, target, depth + 1)),
+        TypeKind::Volatile(target) => {
+            format!("volatile {}", get_type_name(types, target, depth + 1))
+        }
+        TypeKind::Typedef(target) => get_type_name(types, target, depth + 1),
+        TypeKind::Array(element_type, ref counts) => {
+            let mut name = get_type_name(types, Some(element_type), depth + 1);
+            name.push(' ');
+            for count in counts {
+                match count {
+                    Some(count) => name.push_str(&format!("[{}]", count)),
+                    None => name.push_str("[]"),
+                }
+            }
+            name
+        }
+        TypeKind::Function(ret, ref params) => format!(
+            "{} ({})",
+            get_type_name(types, ret, depth + 1),
+            get_param_names(types, params, depth)
+        ),
+        TypeKind::Base
+        | TypeKind::Struct(_)
+        | TypeKind::Union(_)
+        | TypeKind::Enum(_)
+        | TypeKind::Variants(..) => dtype.name.clone(),
+    }
+}
+
+fn get_param_names(types: &HashMap<usize, Type>, params: &[usize], depth: usize) -> String {
+    params
+        .iter()
+        .map(|&param| get_type_name(types, Some(param), depth + 1))
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+fn get_type_size(types: &HashMap<usize, Type>, offset: usize, depth: usize) -> usize {
+    let dtype = match types.get(&offset) {
+        Some(dtype) => dtype,
+        None => return 0,
+    };
+    if dtype.size != 0 || depth > MAX_TYPE_DEPTH {
+        return dtype.size;
+    }
+    match dtype.kind {
+        TypeKind::Const(Some(target))
+        | TypeKind::Volatile(Some(target))
+        | TypeKind::Typedef(Some(target)) => get_type_size(types, target, depth + 1),
+        TypeKind::Pointer(_) => 8,
+        TypeKind::Array(element_type, ref counts) => counts.iter().fold(
+            get_type_size(types, element_type, depth + 1),
+            |size, count| size * count.unwrap_or(0),
+        ),
+        _ => 0,
+    }
+}
+
+/// Converts a unit-relative offset into an offset in .debug_info, which is how get_attr_value
+/// reports references to other DIEs.
+fn get_global_offset<R: Reader>(unit: &gimli::Unit<R>, offset: UnitOffset) -> usize {
+    match offset.to_unit_section_offset(unit) {
+        UnitSectionOffset::DebugInfoOffset(goff) => goff.0,
+        UnitSectionOffset::DebugTypesOffset(goff) => goff.0,
+    }
+}
+
+fn get_attr_string<R: Reader>(
+    entry: &gimli::DebuggingInformationEntry<R>,
+    name: gimli::DwAt,
+    unit: &gimli::Unit<R>,
+    dwarf: &gimli::Dwarf<R>,
+) -> Option<String> {
+    match get_attr_value(&entry.attr(name).ok()??, unit, dwarf) {
+        Ok(DebugValue::Str(value)) => Some(value),
+        _ => None,
+    }
+}
+
+fn get_attr_uint<R: Reader>(
+    entry: &gimli::DebuggingInformationEntry<R>,
+    name: gimli::DwAt,
+) -> Option<u64> {
+    entry.attr(name).ok()??.udata_value()
+}
+
+/// Returns the .debug_info offset of the type referenced by an entry's DW_AT_type.
+fn get_attr_type<R: Reader>(
+    entry: &gimli::DebuggingInformationEntry<R>,
+    unit: &gimli::Unit<R>,
+) -> Option<usize> {
+    match entry.attr(gimli::DW_AT_type).ok()??.value() {
+        gimli::AttributeValue::UnitRef(offset) => Some(get_global_offset(unit, offset)),
+        gimli::AttributeValue::DebugInfoRef(offset) => Some(offset.0),
+        _ => None,
+    }
+}
+
+/// Returns a struct member's DW_AT_data_member_location, which is either a constant or (in
+/// older DWARF versions) an expression of the form DW_OP_plus_uconst <offset>.
+fn get_member_offset<R: Reader>(
+    entry: &gimli::DebuggingInformationEntry<R>,
+    unit: &gimli::Unit<R>,
+) -> Option<usize> {
+    let attr = entry.attr(gimli::DW_AT_data_member_location).ok()??;
+    if let gimli::AttributeValue::Exprloc(ref data) = attr.value() {
+        let mut pc = data.0.clone();
+        if let Ok(gimli::Operation::PlusConstant { value }) =
+            gimli::Operation::parse(&mut pc, unit.encoding())
+        {
+            return Some(value as usize);
+        }
+        return None;
+    }
+    attr.udata_value().map(|offset| offset as usize)
 }
 
 #[derive(Debug, Clone)]
 pub enum DebugValue {
     Str(String),
     Uint(u64),
-    Int(i64),
     Size(usize),
     NoVal,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Error {
-    GimliError(gimli::Error),
-    Addr2lineError(addr2line::gimli::Error),
-    ObjectError(String),
-    IoError,
+    Gimli(gimli::Error),
+    Addr2line(addr2line::gimli::Error),
+    Object(String),
+    Io,
 }
 
 impl From<gimli::Error> for Error {
     fn from(err: gimli::Error) -> Self {
-        Error::GimliError(err)
+        Error::Gimli(err)
     }
 }
 
 impl From<addr2line::gimli::Error> for Error {
     fn from(err: addr2line::gimli::Error) -> Self {
-        Error::Addr2lineError(err)
+        Error::Addr2line(err)
     }
 }
 
 impl From<io::Error> for Error {
     fn from(_: io::Error) -> Self {
-        Error::IoError
+        Error::Io
     }
 }
 
 impl From<std::fmt::Error> for Error {
     fn from(_: std::fmt::Error) -> Self {
-        Error::IoError
+        Error::Io
     }
 }
 
@@ -291,25 +643,38 @@ impl<'input, Endian> Reader for gimli::EndianSlice<'input, Endian> where
 
 trait Reader: gimli::Reader<Offset = usize> + Send + Sync {}
 
-fn get_location<R: Reader>(attr: &gimli::Attribute<R>, unit: &gimli::Unit<R>) -> Option<Location> {
+fn get_location<R: Reader>(
+    attr: &gimli::Attribute<R>,
+    unit: &gimli::Unit<R>,
+    dwarf: &gimli::Dwarf<R>,
+) -> Option<Location> {
     if let gimli::AttributeValue::Exprloc(ref data) = attr.value() {
-        let encoding = unit.encoding();
-        let mut pc = data.0.clone();
-        if pc.len() > 0 {
-            if let Ok(op) = gimli::Operation::parse(&mut pc, encoding) {
-                match op {
-                    gimli::Operation::FrameOffset { offset } => {
-                        return Some(Location::FramePointerOffset(offset.try_into().unwrap()));
-                    }
-                    gimli::Operation::Address { address } => {
-                        return Some(Location::Address(address.try_into().unwrap()));
-                    }
-                    _ => {}
-                }
-            }
-        }
+        return Some(Location::Expression(get_location_expression(
+            data,
+            unit.encoding(),
+        )?));
     }
-    None
+    // Otherwise this should be a reference to a location list
+    let mut locations = dwarf.attr_locations(unit, attr.value()).ok()??;
+    let mut entries = Vec::new();
+    while let Ok(Some(entry)) = locations.next() {
+        entries.push((
+            entry.range.begin.try_into().unwrap(),
+            entry.range.end.try_into().unwrap(),
+            get_location_expression(&entry.data, unit.encoding())?,
+        ));
+    }
+    Some(Location::List(entries))
+}
+
+fn get_location_expression<R: Reader>(
+    data: &gimli::Expression<R>,
+    encoding: gimli::Encoding,
+) -> Option<LocationExpression> {
+    Some(LocationExpression {
+        bytecode: data.0.to_slice().ok()?.to_vec(),
+        encoding,
+    })
 }
 
 // based on dwarf_dump.rs
@@ -344,7 +709,6 @@ fn get_attr_value<R: Reader>(
                 Ok(DebugValue::Str(format!("<.debug_str+0x{:08x}>", offset.0)))
             }
         }
-        gimli::AttributeValue::Sdata(data) => Ok(DebugValue::Int(data)),
         gimli::AttributeValue::Addr(data) => Ok(DebugValue::Uint(data)),
         gimli::AttributeValue::Udata(data) => Ok(DebugValue::Uint(data)),
 
@@ -1,9 +1,21 @@
+use crate::dwarf_data::DwarfData;
+use crate::shared_library;
+use crate::signals::SignalTable;
+use crate::target::{align_addr_to_word, auxv_entry_point, Target};
+use nix::errno::Errno;
+use nix::libc::{self, user_fpregs_struct, user_regs_struct};
 use nix::sys::ptrace;
 use nix::sys::signal;
 use nix::sys::wait::{waitpid, WaitPidFlag, WaitStatus};
 use nix::unistd::Pid;
-use std::process::{Child, Command};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs;
+use std::mem::{size_of, MaybeUninit};
 use std::os::unix::process::CommandExt;
+use std::process::Command;
+use std::ptr;
+use std::rc::Rc;
 
 pub enum Status {
     /// Indicates inferior stopped. Contains the signal that stopped the process, as well as the
@@ -16,24 +28,115 @@ pub enum Status {
     /// Indicates the inferior exited due to a signal. Contains the signal that killed the
     /// process.
     Signaled(signal::Signal),
+
+    /// Indicates the inferior forked and we follow the child. Contains the pid of the child, which
+    /// can be picked up with Inferior::take_forks.
+    Forked(Pid),
+
+    /// Indicates the inferior replaced its program with execve. Contains the new instruction
+    /// pointer.
+    Execed(usize),
 }
 
 /// This function calls ptrace with PTRACE_TRACEME to enable debugging on a process. You should use
 /// pre_exec with Command to call this in the child process.
 fn child_traceme() -> Result<(), std::io::Error> {
-    ptrace::traceme().or(Err(std::io::Error::new(
-        std::io::ErrorKind::Other,
-        "ptrace TRACEME failed",
-    )))
+    ptrace::traceme().or(Err(std::io::Error::other("ptrace TRACEME failed")))
+}
+
+/// Which accesses trigger a hardware watchpoint. x86 can't trap on reads alone.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WatchKind {
+    Write,
+    ReadWrite,
+}
+
+// offsetof(struct user, u_debugreg) on x86-64
+const DEBUGREG_OFFSET: usize = 848;
+// Number of address debug registers (DR0-DR3)
+const NUM_WATCH_SLOTS: usize = 4;
+const DR6: usize = 6;
+const DR7: usize = 7;
+
+/// Returns the ids of the threads of a process, from /proc/<pid>/task.
+fn list_threads(pid: Pid) -> Result<Vec<Pid>, nix::Error> {
+    let entries =
+        fs::read_dir(format!("/proc/{}/task", pid)).map_err(|_| nix::Error::Sys(Errno::ESRCH))?;
+    let mut tids: Vec<i32> = entries
+        .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse().ok())
+        .collect();
+    tids.sort();
+    Ok(tids.into_iter().map(Pid::from_raw).collect())
 }
 
+/// Decides whether a breakpoint the inferior hit while stepping (at the given address) should
+/// stop it, e.g. by checking the breakpoint's condition. Used for the breakpoints in called
+/// functions that `next` and `finish` run into.
+pub type BreakpointFilter<'a> = &'a mut dyn FnMut(&Inferior, usize) -> bool;
+
 pub struct Inferior {
-    child: Child,
+    pid: Pid,
+    threads: Vec<Thread>,
+    /// The selected thread, whose registers are read and written, and which is stepped
+    current: Pid,
+    next_thread_num: usize,
+    /// Wait statuses collected for processes other than this one (e.g. the initial stop of a
+    /// child that we haven't heard the fork event for yet). Shared with the inferiors forked
+    /// from this one, so that each receives the statuses collected while waiting for another.
+    unclaimed: Rc<RefCell<Vec<(Pid, WaitStatus)>>>,
+    /// Whether we attached to an already-running process (rather than spawning it), in which
+    /// case it should be detached from rather than killed when we are done with it.
+    attached: bool,
+    /// Maps the address of every installed breakpoint to the original byte that the 0xcc
+    /// instruction replaced.
+    breakpoints: HashMap<usize, u8>,
+    /// The breakpoint the inferior stops at when its shared libraries may have changed (see
+    /// Debugger::update_shared_libraries)
+    library_breakpoint: Option<usize>,
+    /// Whether a fork should stop the inferior so that the debugger can switch to the child.
+    /// Otherwise the inferior keeps going after recording the child in forks.
+    follow_fork_child: bool,
+    /// Whether children that aren't followed are detached, rather than left stopped
+    detach_on_fork: bool,
+    /// Which signals stop the inferior and which are passed on to it
+    signals: SignalTable,
+    forks: Vec<Fork>,
+}
+
+/// A thread of the inferior. Threads are numbered from 1 in the order they were seen.
+pub struct Thread {
+    pub num: usize,
+    pub tid: Pid,
+    /// Whether the thread has been resumed and hasn't reported a stop since
+    running: bool,
+    /// Whether a SIGSTOP we sent to stop the thread is still to be collected
+    stop_pending: bool,
+    /// A signal to deliver when the thread is resumed: one it stopped with, or one that arrived
+    /// while we were stopping it
+    pending_signal: Option<signal::Signal>,
+}
+
+/// A child process forked by the inferior.
+pub struct Fork {
+    pub pid: Pid,
+    /// The child, or None if it was detached
+    pub child: Option<Inferior>,
+}
+
+// Events we want to hear about: new threads, new processes and new program images
+fn trace_options() -> ptrace::Options {
+    ptrace::Options::PTRACE_O_TRACECLONE
+        | ptrace::Options::PTRACE_O_TRACEFORK
+        | ptrace::Options::PTRACE_O_TRACEVFORK
+        | ptrace::Options::PTRACE_O_TRACEEXEC
 }
 
+// Every thread has its own debug registers, so watchpoints are programmed into all of them
+const WATCH_DEBUGREGS: [usize; 5] = [0, 1, 2, 3, DR7];
+
 impl Inferior {
-    /// Attempts to start a new inferior process. Returns Some(Inferior) if successful, or None if
-    /// an error is encountered.
+    /// Attempts to start a new inferior process, which is left stopped right after it execs the
+    /// program. Returns Some(Inferior) if successful, or None if an error is encountered.
     pub fn new(target: &str, args: &Vec<String>) -> Option<Inferior> {
         // create command
         let mut command = Command::new(target);
@@ -47,48 +150,1116 @@ impl Inferior {
         // spawn
         let child = command.spawn().ok()?;
 
-        let mut inferior = Inferior { child };
+        let inferior = Inferior::with_pid(Pid::from_raw(child.id() as i32), false);
         // block wait
-        match inferior.wait(None) {
-            Ok(Status::Stopped(signal::Signal::SIGTRAP, _)) => Some(inferior),
-            _ => None
+        match waitpid(inferior.pid(), None) {
+            Ok(WaitStatus::Stopped(_, signal::Signal::SIGTRAP)) => {}
+            _ => return None,
+        }
+        ptrace::setoptions(inferior.pid(), trace_options()).ok()?;
+        Some(inferior)
+    }
+
+    /// Attaches to an already-running process, stopping it.
+    pub fn attach(pid: Pid) -> Result<Inferior, nix::Error> {
+        let mut inferior = Inferior::with_pid(pid, true);
+        inferior.threads.clear();
+        inferior.next_thread_num = 1;
+        if let Err(err) = inferior.attach_threads() {
+            // Let go of the threads we did attach to
+            for thread in &inferior.threads {
+                let _ = ptrace::detach(thread.tid, None);
+            }
+            return Err(err);
+        }
+        Ok(inferior)
+    }
+
+    /// Attaches to every thread of the process. New ones may be created while we do so, so this
+    /// repeats until we have them all.
+    fn attach_threads(&mut self) -> Result<(), nix::Error> {
+        loop {
+            let tids = list_threads(self.pid)?;
+            let new_tids: Vec<Pid> = tids
+                .into_iter()
+                .filter(|tid| self.thread(*tid).is_none())
+                .collect();
+            if new_tids.is_empty() {
+                break;
+            }
+            for tid in new_tids {
+                match ptrace::attach(tid) {
+                    Ok(()) => {}
+                    // The thread exited in the meantime
+                    Err(nix::Error::Sys(Errno::ESRCH)) if tid != self.pid => continue,
+                    // E.g. EPERM, if we aren't allowed to trace the process
+                    Err(err) => return Err(err),
+                }
+                self.add_thread(tid);
+                // PTRACE_ATTACH sends a SIGSTOP. Other signals may arrive before it; they are
+                // delivered when the process is resumed.
+                loop {
+                    match waitpid(tid, Some(WaitPidFlag::__WALL))? {
+                        WaitStatus::Stopped(_, signal::Signal::SIGSTOP) => break,
+                        WaitStatus::Stopped(_, _) => continue,
+                        _ => return Err(nix::Error::Sys(Errno::ESRCH)),
+                    }
+                }
+                ptrace::setoptions(tid, trace_options())?;
+            }
+        }
+        if self.thread(self.pid).is_none() {
+            return Err(nix::Error::Sys(Errno::ESRCH));
         }
+        Ok(())
+    }
+
+    /// Returns how far the program has been moved from the addresses it was linked at, which is
+    /// nonzero for position-independent executables. The kernel tells us where it put the entry
+    /// point (AT_ENTRY in the auxiliary vector); failing that, we look at where the executable's
+    /// first page is mapped.
+    pub fn load_bias(&self, debug_data: &DwarfData) -> Option<usize> {
+        if let Ok(auxv) = fs::read(format!("/proc/{}/auxv", self.pid())) {
+            if let Some(entry_point) = auxv_entry_point(&auxv) {
+                return Some(entry_point.wrapping_sub(debug_data.linked_entry_point()));
+            }
+        }
+        let start = shared_library::executable_start(self.pid())?;
+        Some(start.wrapping_sub(debug_data.base_address()))
+    }
+
+    fn with_pid(pid: Pid, attached: bool) -> Inferior {
+        let mut inferior = Inferior {
+            pid,
+            threads: Vec::new(),
+            current: pid,
+            next_thread_num: 1,
+            unclaimed: Rc::new(RefCell::new(Vec::new())),
+            attached,
+            breakpoints: HashMap::new(),
+            library_breakpoint: None,
+            follow_fork_child: false,
+            detach_on_fork: true,
+            signals: SignalTable::new(),
+            forks: Vec::new(),
+        };
+        inferior.add_thread(pid);
+        inferior
+    }
+
+    /// Starts debugging a child that this inferior just forked (the kernel has already attached
+    /// to it).
+    fn fork_child(&mut self, pid: Pid) -> Result<Inferior, nix::Error> {
+        // The child starts out with a SIGSTOP, which may not have been collected yet
+        match self.wait_for(pid)? {
+            WaitStatus::Stopped(..) => {}
+            _ => return Err(nix::Error::Sys(Errno::ESRCH)),
+        }
+        let mut child = Inferior::with_pid(pid, self.attached);
+        child.unclaimed = Rc::clone(&self.unclaimed);
+        // The child has copies of our breakpoints, including temporary ones that we may remove
+        // later
+        child.breakpoints = self.breakpoints.clone();
+        child.library_breakpoint = self.library_breakpoint;
+        child.follow_fork_child = self.follow_fork_child;
+        child.detach_on_fork = self.detach_on_fork;
+        child.signals = self.signals.clone();
+        Ok(child)
+    }
+
+    /// Records a child forked by the thread tid. Unless we follow it, the child is detached or
+    /// left stopped. Returns true if the child is followed.
+    fn handle_fork(&mut self, tid: Pid, vfork: bool, may_follow: bool) -> Result<bool, nix::Error> {
+        let pid = Pid::from_raw(ptrace::getevent(tid)? as i32);
+        let mut child = self.fork_child(pid)?;
+        if (self.follow_fork_child && may_follow) || !self.detach_on_fork {
+            self.forks.push(Fork {
+                pid,
+                child: Some(child),
+            });
+            return Ok(self.follow_fork_child && may_follow);
+        }
+        // A vforked child shares our memory, so its breakpoints must stay
+        if vfork {
+            child.forget_breakpoints();
+        }
+        child.detach()?;
+        self.forks.push(Fork { pid, child: None });
+        Ok(false)
+    }
+
+    /// Sets what happens when the inferior forks: whether to stop and switch to the child, and
+    /// whether to detach from the process that isn't followed.
+    pub fn set_fork_mode(&mut self, follow_fork_child: bool, detach_on_fork: bool) {
+        self.follow_fork_child = follow_fork_child;
+        self.detach_on_fork = detach_on_fork;
+    }
+
+    pub fn set_signal_table(&mut self, signals: &SignalTable) {
+        self.signals = signals.clone();
+    }
+
+    /// Sets the signal that the selected thread receives when it is resumed, replacing the one
+    /// it stopped with (if that is passed on).
+    pub fn set_pending_signal(&mut self, signal: Option<signal::Signal>) {
+        let tid = self.tid();
+        self.thread_mut(tid).unwrap().pending_signal = signal;
+    }
+
+    /// Returns the children forked since the last call.
+    pub fn take_forks(&mut self) -> Vec<Fork> {
+        std::mem::take(&mut self.forks)
+    }
+
+    /// Forgets about installed breakpoints without removing them, for a vforked child whose
+    /// memory (and so its breakpoints) belongs to the parent.
+    fn forget_breakpoints(&mut self) {
+        self.breakpoints.clear();
+        self.library_breakpoint = None;
+    }
+
+    /// Removes all installed breakpoints and watchpoints and lets the process continue running
+    /// untraced.
+    pub fn detach(&mut self) -> Result<(), nix::Error> {
+        let addrs: Vec<usize> = self.breakpoints.keys().cloned().collect();
+        for addr in addrs {
+            self.remove_breakpoint(addr)?;
+        }
+        for thread in &self.threads {
+            // A watchpoint firing after we're gone would kill the process with SIGTRAP
+            self.poke_debugreg(thread.tid, DR7, 0)?;
+            ptrace::detach(thread.tid, thread.pending_signal)?;
+        }
+        Ok(())
     }
 
     /// Returns the pid of this inferior.
     pub fn pid(&self) -> Pid {
-        nix::unistd::Pid::from_raw(self.child.id() as i32)
+        self.pid
+    }
+
+    /// Returns the thread id of the selected thread.
+    pub fn tid(&self) -> Pid {
+        self.current
+    }
+
+    pub fn threads(&self) -> &[Thread] {
+        &self.threads
+    }
+
+    /// Returns the selected thread.
+    pub fn current_thread(&self) -> &Thread {
+        self.thread(self.current).unwrap()
     }
 
-    /// Calls waitpid on this inferior and returns a Status to indicate the state of the process
-    /// after the waitpid call.
-    pub fn wait(&self, options: Option<WaitPidFlag>) -> Result<Status, nix::Error> {
-        Ok(match waitpid(self.pid(), options)? {
-            WaitStatus::Exited(_pid, exit_code) => Status::Exited(exit_code),
-            WaitStatus::Signaled(_pid, signal, _core_dumped) => Status::Signaled(signal),
-            WaitStatus::Stopped(_pid, signal) => {
-                let regs = ptrace::getregs(self.pid())?;
-                Status::Stopped(signal, regs.rip as usize)
+    /// Selects the thread with the given number. Returns false if there is no such thread.
+    pub fn select_thread(&mut self, num: usize) -> bool {
+        match self.threads.iter().find(|thread| thread.num == num) {
+            Some(thread) => {
+                self.current = thread.tid;
+                true
             }
-            other => panic!("waitpid returned unexpected status: {:?}", other),
-        })
+            None => false,
+        }
     }
 
-    pub fn continu3(&self) -> Result<Status, nix::Error> {
-        // continue
-        ptrace::cont(self.pid(), None)?;
+    /// Returns the instruction pointer of the given thread, or 0 if it cannot be read.
+    pub fn thread_rip(&self, tid: Pid) -> usize {
+        ptrace::getregs(tid).map_or(0, |regs| regs.rip as usize)
+    }
 
-        // block wait
-        Ok(self.wait(None)?)
+    fn thread(&self, tid: Pid) -> Option<&Thread> {
+        self.threads.iter().find(|thread| thread.tid == tid)
     }
 
-    pub fn kill(&mut self) {
-        println!("Killing running inferior (pid {})", self.pid().as_raw());
+    fn thread_mut(&mut self, tid: Pid) -> Option<&mut Thread> {
+        self.threads.iter_mut().find(|thread| thread.tid == tid)
+    }
+
+    fn add_thread(&mut self, tid: Pid) {
+        self.threads.push(Thread {
+            num: self.next_thread_num,
+            tid,
+            running: false,
+            stop_pending: false,
+            pending_signal: None,
+        });
+        self.next_thread_num += 1;
+    }
+
+    /// Starts tracking a thread created by the thread parent. The new thread is left stopped.
+    fn add_new_thread(&mut self, parent: Pid) -> Result<(), nix::Error> {
+        let tid = Pid::from_raw(ptrace::getevent(parent)? as i32);
+        // The thread starts out with a SIGSTOP, which may not have been collected yet
+        self.wait_for(tid)?;
+        self.add_thread(tid);
+        for &reg in &WATCH_DEBUGREGS {
+            let value = self.peek_debugreg(parent, reg)?;
+            self.poke_debugreg(tid, reg, value)?;
+        }
+        println!("[New thread {} (LWP {})]", self.next_thread_num - 1, tid);
+        Ok(())
+    }
+
+    fn remove_thread(&mut self, tid: Pid) {
+        if let Some(idx) = self.threads.iter().position(|thread| thread.tid == tid) {
+            let thread = self.threads.remove(idx);
+            println!("[Thread {} (LWP {}) exited]", thread.num, tid);
+        }
+        if self.current == tid {
+            self.current = self.threads.first().map_or(self.pid, |thread| thread.tid);
+        }
+    }
+
+    /// Returns true if this inferior was attached to rather than started by us.
+    pub fn is_attached(&self) -> bool {
+        self.attached
+    }
+
+    /// Waits for any of our threads to change state, starting with the statuses another inferior
+    /// collected for us. Statuses of other processes are kept for wait_for and for the inferiors
+    /// they belong to.
+    fn wait_any(&mut self) -> Result<(Pid, WaitStatus), nix::Error> {
+        {
+            let mut unclaimed = self.unclaimed.borrow_mut();
+            if let Some(idx) = unclaimed
+                .iter()
+                .position(|(tid, _)| self.thread(*tid).is_some())
+            {
+                return Ok(unclaimed.remove(idx));
+            }
+        }
+        loop {
+            let status = waitpid(None, Some(WaitPidFlag::__WALL))?;
+            let tid = match status.pid() {
+                Some(tid) => tid,
+                None => continue,
+            };
+            if self.thread(tid).is_some() {
+                return Ok((tid, status));
+            }
+            self.unclaimed.borrow_mut().push((tid, status));
+        }
+    }
+
+    /// Waits for the given thread or process to change state.
+    fn wait_for(&mut self, tid: Pid) -> Result<WaitStatus, nix::Error> {
+        let mut unclaimed = self.unclaimed.borrow_mut();
+        if let Some(idx) = unclaimed.iter().position(|(pid, _)| *pid == tid) {
+            return Ok(unclaimed.remove(idx).1);
+        }
+        waitpid(tid, Some(WaitPidFlag::__WALL))
+    }
 
+    /// Installs a breakpoint at the given address by patching in an int3 (0xcc) instruction.
+    /// Installing the same breakpoint twice is a no-op.
+    pub fn set_breakpoint(&mut self, addr: usize) -> Result<(), nix::Error> {
+        if self.breakpoints.contains_key(&addr) {
+            return Ok(());
+        }
+        let orig_byte = self.write_byte(addr, 0xcc)?;
+        self.breakpoints.insert(addr, orig_byte);
+        Ok(())
+    }
+
+    /// Removes the breakpoint at the given address (if any), restoring the original byte.
+    pub fn remove_breakpoint(&mut self, addr: usize) -> Result<(), nix::Error> {
+        if let Some(orig_byte) = self.breakpoints.remove(&addr) {
+            self.write_byte(addr, orig_byte)?;
+        }
+        Ok(())
+    }
+
+    /// Sets the breakpoint that marks changes to the shared libraries, replacing the previous one
+    /// unless keep_old is set (because a user breakpoint is there too).
+    pub fn set_library_breakpoint(
+        &mut self,
+        addr: usize,
+        keep_old: bool,
+    ) -> Result<(), nix::Error> {
+        if let Some(old) = self.library_breakpoint.take() {
+            if !keep_old && old != addr {
+                self.remove_breakpoint(old)?;
+            }
+        }
+        self.set_breakpoint(addr)?;
+        self.library_breakpoint = Some(addr);
+        Ok(())
+    }
+
+    pub fn library_breakpoint(&self) -> Option<usize> {
+        self.library_breakpoint
+    }
+
+    /// Programs a free debug register to watch len bytes at addr, which must be 1, 2, 4 or 8
+    /// and aligned to addr. Returns the debug register number, or None if all are in use.
+    pub fn set_watchpoint(
+        &mut self,
+        addr: usize,
+        len: usize,
+        kind: WatchKind,
+    ) -> Result<Option<usize>, nix::Error> {
+        let dr7 = self.peek_debugreg(self.tid(), DR7)?;
+        let slot = match (0..NUM_WATCH_SLOTS).find(|slot| dr7 & (1 << (2 * slot)) == 0) {
+            Some(slot) => slot,
+            None => return Ok(None),
+        };
+        let rw_bits = match kind {
+            WatchKind::Write => 0b01,
+            WatchKind::ReadWrite => 0b11,
+        };
+        let len_bits = match len {
+            1 => 0b00,
+            2 => 0b01,
+            8 => 0b10,
+            _ => 0b11,
+        };
+        let control_shift = 16 + 4 * slot;
+        let dr7 = (dr7 & !(0b1111 << control_shift))
+            | ((rw_bits | len_bits << 2) << control_shift)
+            | (1 << (2 * slot));
+        for thread in &self.threads {
+            self.poke_debugreg(thread.tid, slot, addr as u64)?;
+            self.poke_debugreg(thread.tid, DR7, dr7)?;
+        }
+        Ok(Some(slot))
+    }
+
+    /// Disables the watchpoint in the given debug register.
+    pub fn remove_watchpoint(&mut self, slot: usize) -> Result<(), nix::Error> {
+        let dr7 = self.peek_debugreg(self.tid(), DR7)?;
+        for thread in &self.threads {
+            self.poke_debugreg(thread.tid, DR7, dr7 & !(1 << (2 * slot)))?;
+        }
+        Ok(())
+    }
+
+    /// Returns the debug registers whose watchpoints fired since the last call, according to
+    /// DR6. The processor never clears DR6 itself, so this does.
+    pub fn take_watchpoint_hits(&mut self) -> Result<Vec<usize>, nix::Error> {
+        let dr6 = self.peek_debugreg(self.tid(), DR6)?;
+        if dr6 & 0b1111 == 0 {
+            return Ok(Vec::new());
+        }
+        self.poke_debugreg(self.tid(), DR6, 0)?;
+        Ok((0..NUM_WATCH_SLOTS)
+            .filter(|slot| dr6 & (1 << slot) != 0)
+            .collect())
+    }
+
+    pub fn continu3(&mut self) -> Result<Status, nix::Error> {
+        // If a thread is sitting on a breakpoint, execute the original instruction first. Threads
+        // with a stop still pending were wound back onto a breakpoint they haven't reported yet,
+        // and should hit it again.
+        let selected = self.tid();
+        let tids: Vec<Pid> = self
+            .threads
+            .iter()
+            .filter(|thread| !thread.stop_pending)
+            .map(|thread| thread.tid)
+            .collect();
+        for tid in tids {
+            let rip = ptrace::getregs(tid)?.rip as usize;
+            if !self.breakpoints.contains_key(&rip) {
+                continue;
+            }
+            self.current = tid;
+            match self.step_instruction()? {
+                Status::Stopped(signal::Signal::SIGTRAP, _)
+                    if !self.watchpoint_hit_pending()? => {}
+                status => return Ok(status),
+            }
+        }
+        self.current = selected;
+
+        // continue and block wait
+        let status = self.resume(false)?;
+
+        // If we just executed one of our int3 instructions, rewind rip so that it points at the
+        // breakpoint address (and the original instruction runs on the next resume).
+        if let Status::Stopped(signal::Signal::SIGTRAP, rip) = status {
+            let bp_addr = rip - 1;
+            if self.breakpoints.contains_key(&bp_addr) && !self.watchpoint_hit_pending()? {
+                let mut regs = ptrace::getregs(self.tid())?;
+                regs.rip = bp_addr as u64;
+                ptrace::setregs(self.tid(), regs)?;
+                return Ok(Status::Stopped(signal::Signal::SIGTRAP, bp_addr));
+            }
+        }
+        Ok(status)
+    }
+
+    /// Executes a single instruction. If rip points at an installed breakpoint, the original byte
+    /// is restored for the duration of the step and the breakpoint is reinstalled afterwards.
+    pub fn step_instruction(&mut self) -> Result<Status, nix::Error> {
+        let rip = ptrace::getregs(self.tid())?.rip as usize;
+        let orig_byte = self.breakpoints.get(&rip).copied();
+        if let Some(orig_byte) = orig_byte {
+            self.write_byte(rip, orig_byte)?;
+        }
+        let status = self.resume(true)?;
+        if let (Some(_), Status::Stopped(..)) | (Some(_), Status::Forked(..)) = (orig_byte, &status)
+        {
+            self.write_byte(rip, 0xcc)?;
+        }
+        Ok(status)
+    }
+
+    /// Resumes the inferior and waits for it to stop. If step is set, only the selected thread
+    /// runs, for a single instruction. Otherwise all threads run, and once one of them stops, the
+    /// others are stopped too and it becomes the selected thread.
+    ///
+    /// Forked children are recorded, but the fork is only reported if we follow the child;
+    /// otherwise the child is detached (or left stopped) and the inferior is resumed again. After
+    /// an exec, the old program's breakpoints are gone (and the kernel has cleared the debug
+    /// registers), so they are forgotten.
+    fn resume(&mut self, mut step: bool) -> Result<Status, nix::Error> {
+        let stepping = self.tid();
+        if step {
+            self.resume_thread(stepping, true)?;
+        } else {
+            self.resume_all()?;
+        }
+        loop {
+            let (tid, status) = self.wait_any()?;
+            let step_this = step && tid == stepping;
+            let thread = self.thread_mut(tid).unwrap();
+            thread.running = false;
+            let stop_pending = thread.stop_pending;
+            match status {
+                WaitStatus::Exited(_, exit_code) if tid == self.pid => {
+                    return Ok(Status::Exited(exit_code))
+                }
+                WaitStatus::Signaled(_, signal, _) if tid == self.pid => {
+                    return Ok(Status::Signaled(signal))
+                }
+                WaitStatus::Exited(..) | WaitStatus::Signaled(..) => {
+                    self.remove_thread(tid);
+                    // The thread we were stepping is gone, so let the others run
+                    if step_this {
+                        step = false;
+                        self.resume_all()?;
+                    }
+                }
+                WaitStatus::Stopped(_, signal::Signal::SIGSTOP) if stop_pending => {
+                    self.thread_mut(tid).unwrap().stop_pending = false;
+                    self.resume_thread(tid, step_this)?;
+                }
+                WaitStatus::Stopped(_, signal) => {
+                    let handling = self.signals.get(signal);
+                    if handling.pass {
+                        self.thread_mut(tid).unwrap().pending_signal = Some(signal);
+                    }
+                    // We can't do without SIGTRAP, whatever the user said
+                    if !handling.stop && signal != signal::Signal::SIGTRAP {
+                        if handling.print {
+                            println!("Child received signal: {}", signal);
+                        }
+                        self.resume_thread(tid, step_this)?;
+                        continue;
+                    }
+                    self.current = tid;
+                    if !step {
+                        self.stop_others()?;
+                    }
+                    return Ok(Status::Stopped(signal, self.rip()));
+                }
+                WaitStatus::PtraceEvent(_, _, libc::PTRACE_EVENT_CLONE) => {
+                    self.add_new_thread(tid)?;
+                    if !step {
+                        let new_tid = self.threads.last().unwrap().tid;
+                        self.resume_thread(new_tid, false)?;
+                    }
+                    self.resume_thread(tid, step_this)?;
+                }
+                WaitStatus::PtraceEvent(_, _, event)
+                    if event == libc::PTRACE_EVENT_FORK || event == libc::PTRACE_EVENT_VFORK =>
+                {
+                    let vfork = event == libc::PTRACE_EVENT_VFORK;
+                    if self.handle_fork(tid, vfork, true)? {
+                        self.current = tid;
+                        if !step {
+                            self.stop_others()?;
+                        }
+                        let child = self.forks.last().unwrap().pid;
+                        return Ok(Status::Forked(child));
+                    }
+                    self.resume_thread(tid, step_this)?;
+                }
+                WaitStatus::PtraceEvent(_, _, libc::PTRACE_EVENT_EXEC) => {
+                    // All other threads are gone, and the thread that called exec now has the
+                    // process id
+                    self.threads.retain(|thread| thread.tid == tid);
+                    self.threads[0].tid = self.pid;
+                    self.current = self.pid;
+                    self.breakpoints.clear();
+                    self.library_breakpoint = None;
+                    return Ok(Status::Execed(self.rip()));
+                }
+                _ => self.resume_thread(tid, step_this)?,
+            }
+        }
+    }
+
+    /// Resumes a single thread, delivering its pending signal (if any).
+    fn resume_thread(&mut self, tid: Pid, step: bool) -> Result<(), nix::Error> {
+        let thread = self.thread_mut(tid).unwrap();
+        let signal = thread.pending_signal.take();
+        thread.running = true;
+        if step {
+            ptrace::step(tid, signal)
+        } else {
+            ptrace::cont(tid, signal)
+        }
+    }
+
+    fn resume_all(&mut self) -> Result<(), nix::Error> {
+        let tids: Vec<Pid> = self.threads.iter().map(|thread| thread.tid).collect();
+        for tid in tids {
+            self.resume_thread(tid, false)?;
+        }
+        Ok(())
+    }
+
+    /// Stops all running threads other than the selected one, so that the whole process is
+    /// stopped while the user looks at it.
+    fn stop_others(&mut self) -> Result<(), nix::Error> {
+        let tids: Vec<Pid> = self
+            .threads
+            .iter()
+            .filter(|thread| thread.running && thread.tid != self.current)
+            .map(|thread| thread.tid)
+            .collect();
+        for &tid in &tids {
+            // nix doesn't wrap tgkill
+            let res = unsafe {
+                libc::syscall(
+                    libc::SYS_tgkill,
+                    self.pid.as_raw(),
+                    tid.as_raw(),
+                    libc::SIGSTOP,
+                )
+            };
+            Errno::result(res)?;
+        }
+        for tid in tids {
+            self.collect_stop(tid)?;
+        }
+        Ok(())
+    }
+
+    /// Waits for a thread that was sent a SIGSTOP to stop. If it stops for another reason first,
+    /// the SIGSTOP is still on its way and is discarded when it arrives. A breakpoint it hit in
+    /// the meantime is unwound so that it is hit (and reported) again when the thread resumes.
+    fn collect_stop(&mut self, tid: Pid) -> Result<(), nix::Error> {
+        let status = self.wait_for(tid)?;
+        let stop_pending = match status {
+            WaitStatus::Exited(..) | WaitStatus::Signaled(..) => {
+                self.remove_thread(tid);
+                return Ok(());
+            }
+            WaitStatus::Stopped(_, signal::Signal::SIGSTOP) => false,
+            WaitStatus::Stopped(_, signal::Signal::SIGTRAP) => {
+                let mut regs = ptrace::getregs(tid)?;
+                if self.breakpoints.contains_key(&(regs.rip as usize - 1)) {
+                    regs.rip -= 1;
+                    ptrace::setregs(tid, regs)?;
+                }
+                true
+            }
+            WaitStatus::Stopped(_, signal) => {
+                if self.signals.get(signal).pass {
+                    self.thread_mut(tid).unwrap().pending_signal = Some(signal);
+                }
+                true
+            }
+            WaitStatus::PtraceEvent(_, _, libc::PTRACE_EVENT_CLONE) => {
+                self.add_new_thread(tid)?;
+                true
+            }
+            WaitStatus::PtraceEvent(_, _, event)
+                if event == libc::PTRACE_EVENT_FORK || event == libc::PTRACE_EVENT_VFORK =>
+            {
+                self.handle_fork(tid, event == libc::PTRACE_EVENT_VFORK, false)?;
+                true
+            }
+            _ => true,
+        };
+        let thread = self.thread_mut(tid).unwrap();
+        thread.running = false;
+        thread.stop_pending = stop_pending;
+        Ok(())
+    }
+
+    /// Steps until execution reaches the beginning of a different source line. If step_into is
+    /// set, calls into functions that have line information are entered; any other call runs
+    /// until it returns. Breakpoints hit along the way only stop the inferior if should_stop
+    /// says so (see BreakpointFilter).
+    pub fn step_line(
+        &mut self,
+        debug_data: &DwarfData,
+        step_into: bool,
+        should_stop: BreakpointFilter,
+    ) -> Result<Status, nix::Error> {
+        let start_line = debug_data.get_line_from_addr(ptrace::getregs(self.tid())?.rip as usize);
+        loop {
+            let prev_regs = ptrace::getregs(self.tid())?;
+            let mut status = self.step_instruction()?;
+            let mut rip = match status {
+                Status::Stopped(signal::Signal::SIGTRAP, _) if self.watchpoint_hit_pending()? => {
+                    return Ok(status)
+                }
+                Status::Stopped(signal::Signal::SIGTRAP, rip) => rip,
+                status => return Ok(status),
+            };
+
+            if let Some(return_addr) = self.get_call_return_addr(&prev_regs)? {
+                if !step_into || debug_data.get_line_from_addr(rip).is_none() {
+                    status =
+                        self.run_until_return(return_addr, prev_regs.rsp as usize, should_stop)?;
+                    match status {
+                        Status::Stopped(signal::Signal::SIGTRAP, addr) if addr == return_addr => {
+                            rip = addr;
+                        }
+                        status => return Ok(status),
+                    }
+                }
+            }
+
+            let line = match debug_data.get_line_from_addr(rip) {
+                Some(line) => line,
+                // We returned into code without line information (e.g. out of main into libc),
+                // so there is no source line left to stop at.
+                None => loop {
+                    match self.continu3()? {
+                        Status::Stopped(signal::Signal::SIGTRAP, rip)
+                            if self.breakpoints.contains_key(&rip) && !should_stop(self, rip) => {}
+                        status => return Ok(status),
+                    }
+                },
+            };
+            let same_line = start_line.as_ref().is_some_and(|start_line| {
+                start_line.file == line.file && start_line.number == line.number
+            });
+            // Stepping into a function stops after its prologue rather than at its entry point.
+            if !same_line && debug_data.is_line_start(rip) && !debug_data.is_function_entry(rip) {
+                return Ok(status);
+            }
+        }
+    }
+
+    /// Runs until the current function returns to its caller, passing the breakpoints that
+    /// should_stop says to.
+    pub fn finish(
+        &mut self,
+        debug_data: &DwarfData,
+        should_stop: BreakpointFilter,
+    ) -> Result<Status, nix::Error> {
+        // The return address is just below the CFA
+        let cfa = self.get_cfa(debug_data)?;
+        let return_addr = self.read_word(cfa - 8)?;
+        let status = self.run_until_return(return_addr, cfa, should_stop)?;
+        // Returning onto a breakpoint counts as hitting it. The temporary breakpoint is gone by
+        // now, so any breakpoint left there is the user's.
+        if let Status::Stopped(signal::Signal::SIGTRAP, rip) = status {
+            if rip == return_addr && self.breakpoints.contains_key(&rip) {
+                should_stop(self, rip);
+            }
+        }
+        Ok(status)
+    }
+
+    pub fn kill(&mut self) {
         // kill
-        let _ = self.child.kill();
+        let _ = signal::kill(self.pid(), signal::Signal::SIGKILL);
+        let pid = self.pid();
+        self.unclaimed
+            .borrow_mut()
+            .retain(|(tid, _)| self.thread(*tid).is_none());
 
-        // block wait
-        let _ = self.wait(None);
+        // Linux doesn't report the death of a traced thread group leader until every other
+        // thread has been reaped, including ones we haven't heard about yet. So reap whatever
+        // dies until the leader does, keeping what other inferiors report for them.
+        loop {
+            match waitpid(None, Some(WaitPidFlag::__WALL)) {
+                Ok(WaitStatus::Exited(tid, _)) | Ok(WaitStatus::Signaled(tid, ..))
+                    if tid == pid =>
+                {
+                    break
+                }
+                Ok(status) => match status.pid() {
+                    Some(tid) if self.thread(tid).is_none() => {
+                        self.unclaimed.borrow_mut().push((tid, status))
+                    }
+                    _ => {}
+                },
+                Err(nix::Error::Sys(Errno::EINTR)) => {}
+                // ECHILD: nothing left to wait for
+                Err(_) => break,
+            }
+        }
+    }
+
+    /// Writes bytes to memory at addr, preserving the rest of the words it partially covers.
+    /// Breakpoints in the range stay installed; the bytes they will restore are updated instead.
+    pub fn write_memory(&mut self, addr: usize, bytes: &[u8]) -> Result<(), nix::Error> {
+        let mut bytes = bytes.to_vec();
+        for (&bp_addr, orig_byte) in self.breakpoints.iter_mut() {
+            if bp_addr >= addr && bp_addr < addr + bytes.len() {
+                *orig_byte = bytes[bp_addr - addr];
+                bytes[bp_addr - addr] = 0xcc;
+            }
+        }
+        let mut word_addr = align_addr_to_word(addr);
+        while word_addr < addr + bytes.len() {
+            let mut word =
+                (ptrace::read(self.tid(), word_addr as ptrace::AddressType)? as u64).to_le_bytes();
+            for (i, byte) in word.iter_mut().enumerate() {
+                if word_addr + i >= addr && word_addr + i < addr + bytes.len() {
+                    *byte = bytes[word_addr + i - addr];
+                }
+            }
+            ptrace::write(
+                self.tid(),
+                word_addr as ptrace::AddressType,
+                u64::from_le_bytes(word) as *mut std::ffi::c_void,
+            )?;
+            word_addr += size_of::<usize>();
+        }
+        Ok(())
+    }
+
+    pub fn set_registers(&self, regs: user_regs_struct) -> Result<(), nix::Error> {
+        ptrace::setregs(self.tid(), regs)
+    }
+
+    /// If the instruction that was just executed (with registers prev_regs beforehand) was a
+    /// call, returns the return address it pushed.
+    fn get_call_return_addr(
+        &self,
+        prev_regs: &user_regs_struct,
+    ) -> Result<Option<usize>, nix::Error> {
+        let regs = ptrace::getregs(self.tid())?;
+        if regs.rsp != prev_regs.rsp.wrapping_sub(8) {
+            return Ok(None);
+        }
+        let pushed = ptrace::read(self.tid(), regs.rsp as ptrace::AddressType)? as u64;
+        // A call pushes the address of the following instruction (and x86-64 instructions are at
+        // most 15 bytes long), then jumps somewhere else.
+        if pushed > prev_regs.rip && pushed <= prev_regs.rip + 15 && regs.rip != pushed {
+            Ok(Some(pushed as usize))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Resumes the inferior until the selected thread reaches return_addr with rsp at (or above)
+    /// frame_rsp, using a temporary breakpoint. The rsp check skips returns to the same address
+    /// from deeper recursive calls. Anything else that stops the inferior first ends the wait
+    /// early, except for breakpoints that should_stop says to pass.
+    fn run_until_return(
+        &mut self,
+        return_addr: usize,
+        frame_rsp: usize,
+        should_stop: BreakpointFilter,
+    ) -> Result<Status, nix::Error> {
+        let temporary = !self.breakpoints.contains_key(&return_addr);
+        if temporary {
+            self.set_breakpoint(return_addr)?;
+        }
+        let thread = self.tid();
+        let status = loop {
+            let status = self.continu3()?;
+            match status {
+                // Another thread running the same code, or a deeper recursive call
+                Status::Stopped(signal::Signal::SIGTRAP, rip)
+                    if rip == return_addr
+                        && (self.tid() != thread
+                            || (ptrace::getregs(self.tid())?.rsp as usize) < frame_rsp) => {}
+                // The debugger picks up library changes once we are done
+                Status::Stopped(signal::Signal::SIGTRAP, rip)
+                    if Some(rip) == self.library_breakpoint => {}
+                Status::Stopped(signal::Signal::SIGTRAP, rip)
+                    if rip != return_addr
+                        && self.breakpoints.contains_key(&rip)
+                        && !should_stop(self, rip) => {}
+                status => break status,
+            }
+        };
+        if temporary {
+            match status {
+                Status::Stopped(..) | Status::Forked(..) => self.remove_breakpoint(return_addr)?,
+                _ => {
+                    self.breakpoints.remove(&return_addr);
+                }
+            }
+        }
+        Ok(status)
+    }
+
+    /// Returns true if DR6 says a watchpoint fired (and it hasn't been taken yet).
+    fn watchpoint_hit_pending(&self) -> Result<bool, nix::Error> {
+        Ok(self.peek_debugreg(self.tid(), DR6)? & 0b1111 != 0)
+    }
+
+    fn peek_debugreg(&self, tid: Pid, reg: usize) -> Result<u64, nix::Error> {
+        let offset = DEBUGREG_OFFSET + reg * size_of::<u64>();
+        // PTRACE_PEEKUSER returns the value itself, so errors can only be told apart by errno
+        let res = unsafe {
+            Errno::clear();
+            libc::ptrace(
+                libc::PTRACE_PEEKUSER,
+                tid.as_raw(),
+                offset as *mut libc::c_void,
+                ptr::null_mut::<libc::c_void>(),
+            )
+        };
+        if res == -1 && Errno::last() != Errno::UnknownErrno {
+            return Err(nix::Error::Sys(Errno::last()));
+        }
+        Ok(res as u64)
+    }
+
+    fn poke_debugreg(&self, tid: Pid, reg: usize, value: u64) -> Result<(), nix::Error> {
+        let offset = DEBUGREG_OFFSET + reg * size_of::<u64>();
+        let res = unsafe {
+            libc::ptrace(
+                libc::PTRACE_POKEUSER,
+                tid.as_raw(),
+                offset as *mut libc::c_void,
+                value as *mut libc::c_void,
+            )
+        };
+        Errno::result(res).map(drop)
+    }
+
+    /// Writes a single byte into the inferior's memory, returning the byte that was previously
+    /// stored at that address.
+    fn write_byte(&mut self, addr: usize, val: u8) -> Result<u8, nix::Error> {
+        let aligned_addr = align_addr_to_word(addr);
+        let byte_offset = addr - aligned_addr;
+        let word = ptrace::read(self.tid(), aligned_addr as ptrace::AddressType)? as u64;
+        let orig_byte = (word >> (8 * byte_offset)) & 0xff;
+        let masked_word = word & !(0xff << (8 * byte_offset));
+        let updated_word = masked_word | ((val as u64) << (8 * byte_offset));
+        ptrace::write(
+            self.tid(),
+            aligned_addr as ptrace::AddressType,
+            updated_word as *mut std::ffi::c_void,
+        )?;
+        Ok(orig_byte as u8)
+    }
+}
+
+impl Target for Inferior {
+    fn get_registers(&self) -> Result<user_regs_struct, nix::Error> {
+        ptrace::getregs(self.tid())
+    }
+
+    /// Returns the floating point and SSE registers.
+    fn get_fpregs(&self) -> Result<user_fpregs_struct, nix::Error> {
+        let mut fpregs = MaybeUninit::<user_fpregs_struct>::uninit();
+        // nix doesn't wrap PTRACE_GETFPREGS
+        let res = unsafe {
+            libc::ptrace(
+                libc::PTRACE_GETFPREGS,
+                self.tid().as_raw(),
+                ptr::null_mut::<libc::c_void>(),
+                fpregs.as_mut_ptr(),
+            )
+        };
+        Errno::result(res)?;
+        Ok(unsafe { fpregs.assume_init() })
+    }
+
+    /// Reads len bytes of the inferior's memory starting at addr. Breakpoints we installed are
+    /// hidden, i.e. the original bytes are returned in their place.
+    fn read_memory(&self, addr: usize, len: usize) -> Result<Vec<u8>, nix::Error> {
+        // Nothing to read, and addr may well be dangling (e.g. the buffer of an empty Vec)
+        if len == 0 {
+            return Ok(Vec::new());
+        }
+        let start = align_addr_to_word(addr);
+        let mut bytes = Vec::new();
+        let mut word_addr = start;
+        while word_addr < addr + len {
+            let word = ptrace::read(self.tid(), word_addr as ptrace::AddressType)? as u64;
+            bytes.extend_from_slice(&word.to_le_bytes());
+            word_addr += size_of::<usize>();
+        }
+        let mut bytes = bytes[addr - start..addr - start + len].to_vec();
+        for (&bp_addr, &orig_byte) in &self.breakpoints {
+            if bp_addr >= addr && bp_addr < addr + len {
+                bytes[bp_addr - addr] = orig_byte;
+            }
+        }
+        Ok(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Arc, Mutex, MutexGuard};
+    use std::thread;
+    use std::time::Duration;
+
+    // Main starts two threads that spin forever, then stops with a SIGTRAP
+    const SPIN_THREADS: &str = "
+        #include <pthread.h>
+        #include <signal.h>
+        void *spin(void *arg) { for (;;) {} }
+        int main() {
+            pthread_t t;
+            pthread_create(&t, 0, spin, 0);
+            pthread_create(&t, 0, spin, 0);
+            raise(SIGTRAP);
+            pthread_join(t, 0);
+        }
+    ";
+
+    // Like SPIN_THREADS, but main just waits for the threads
+    const IDLE_THREADS: &str = "
+        #include <pthread.h>
+        void *spin(void *arg) { for (;;) {} }
+        int main() {
+            pthread_t t;
+            pthread_create(&t, 0, spin, 0);
+            pthread_create(&t, 0, spin, 0);
+            pthread_join(t, 0);
+        }
+    ";
+
+    /// Tests that run processes take turns: waiting for any child, as Inferior::kill does, would
+    /// otherwise collect the statuses of another test's processes.
+    fn lock_processes() -> MutexGuard<'static, ()> {
+        static PROCESSES: Mutex<()> = Mutex::new(());
+        PROCESSES.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Compiles a C program into a temporary directory, returning the executable's path.
+    fn compile(name: &str, source: &str) -> String {
+        let dir: PathBuf = std::env::temp_dir().join(format!("deet-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let source_path = dir.join(format!("{}.c", name));
+        let executable = dir.join(name);
+        fs::write(&source_path, source).unwrap();
+        let status = Command::new("cc")
+            .arg("-pthread")
+            .arg("-o")
+            .arg(&executable)
+            .arg(&source_path)
+            .status()
+            .expect("failed to run cc");
+        assert!(status.success(), "failed to compile {}", name);
+        executable.to_str().unwrap().to_string()
+    }
+
+    /// Runs f, exiting the whole test process if it hangs.
+    fn with_timeout<F: FnOnce()>(f: F) {
+        let done = Arc::new(AtomicBool::new(false));
+        let watchdog_done = Arc::clone(&done);
+        thread::spawn(move || {
+            thread::sleep(Duration::from_secs(30));
+            if !watchdog_done.load(Ordering::SeqCst) {
+                eprintln!("test timed out");
+                std::process::exit(1);
+            }
+        });
+        f();
+        done.store(true, Ordering::SeqCst);
+    }
+
+    #[test]
+    fn kill_reaps_a_stopped_threaded_inferior() {
+        let _processes = lock_processes();
+        let path = compile("spin_threads", SPIN_THREADS);
+        let mut inferior = Inferior::new(&path, &Vec::new()).unwrap();
+        match inferior.continu3().unwrap() {
+            Status::Stopped(signal::Signal::SIGTRAP, _) => {}
+            _ => panic!("expected the inferior to stop with SIGTRAP"),
+        }
+        assert_eq!(inferior.threads().len(), 3);
+        let pid = inferior.pid();
+        with_timeout(|| inferior.kill());
+        assert_eq!(signal::kill(pid, None), Err(nix::Error::Sys(Errno::ESRCH)));
+    }
+
+    #[test]
+    // Inferior::kill reaps the child, which is ours as well as traced
+    #[allow(clippy::zombie_processes)]
+    fn attach_stops_every_thread() {
+        let _processes = lock_processes();
+        let path = compile("idle_threads", IDLE_THREADS);
+        let child = Command::new(&path).spawn().unwrap();
+        let pid = Pid::from_raw(child.id() as i32);
+        while list_threads(pid).unwrap().len() < 3 {
+            thread::sleep(Duration::from_millis(10));
+        }
+        let mut inferior = Inferior::attach(pid).unwrap();
+        assert_eq!(inferior.threads().len(), 3);
+        assert_eq!(inferior.current_thread().tid, pid);
+        with_timeout(|| inferior.kill());
+    }
+
+    #[test]
+    fn attach_reports_failure() {
+        let _processes = lock_processes();
+        let path = compile("spin_threads", SPIN_THREADS);
+        let mut traced = Inferior::new(&path, &Vec::new()).unwrap();
+        // A process can only have one tracer
+        with_timeout(|| {
+            assert_eq!(
+                Inferior::attach(traced.pid()).err(),
+                Some(nix::Error::Sys(Errno::EPERM))
+            );
+        });
+        traced.kill();
+        assert_eq!(
+            Inferior::attach(traced.pid()).err(),
+            Some(nix::Error::Sys(Errno::ESRCH))
+        );
+    }
+
+    #[test]
+    fn read_memory_of_nothing_needs_no_address() {
+        let _processes = lock_processes();
+        let path = compile("spin_threads", SPIN_THREADS);
+        let mut inferior = Inferior::new(&path, &Vec::new()).unwrap();
+        // Such as the dangling buffer pointer of an empty Vec
+        assert_eq!(inferior.read_memory(8, 0), Ok(Vec::new()));
+        assert_eq!(inferior.read_memory(8, 1), Err(nix::Error::Sys(Errno::EIO)));
+        inferior.kill();
+    }
+
+    #[test]
+    fn wait_for_takes_collected_status_first() {
+        let pid = Pid::from_raw(999_999);
+        let mut inferior = Inferior::with_pid(pid, false);
+        inferior
+            .unclaimed
+            .borrow_mut()
+            .push((pid, WaitStatus::Exited(pid, 3)));
+        assert_eq!(inferior.wait_for(pid), Ok(WaitStatus::Exited(pid, 3)));
+        assert!(inferior.unclaimed.borrow().is_empty());
+    }
+
+    #[test]
+    fn wait_any_leaves_other_inferiors_statuses() {
+        let (parent_pid, child_pid) = (Pid::from_raw(999_998), Pid::from_raw(999_999));
+        let mut parent = Inferior::with_pid(parent_pid, false);
+        let mut child = Inferior::with_pid(child_pid, false);
+        child.unclaimed = Rc::clone(&parent.unclaimed);
+        let child_status = WaitStatus::Exited(child_pid, 1);
+        let parent_status = WaitStatus::Stopped(parent_pid, signal::Signal::SIGSTOP);
+        parent
+            .unclaimed
+            .borrow_mut()
+            .extend(vec![(child_pid, child_status), (parent_pid, parent_status)]);
+        assert_eq!(parent.wait_any(), Ok((parent_pid, parent_status)));
+        assert_eq!(child.wait_any(), Ok((child_pid, child_status)));
+        assert!(parent.unclaimed.borrow().is_empty());
     }
 }
@@ -0,0 +1,543 @@
+//! Evaluation of DWARF location expressions, which describe where a variable lives (in memory,
+//! in a register, split across several of those, or nowhere at all) as a small stack machine
+//! program.
+
+use crate::dwarf_data::LocationExpression;
+use crate::registers;
+use crate::value::{read_unsigned, MemoryReader};
+use nix::libc::{user_fpregs_struct, user_regs_struct};
+
+// DWARF register numbers for x86-64 (System V ABI, figure 3.36)
+const DWARF_XMM0: u16 = 17;
+const DWARF_XMM15: u16 = 32;
+
+/// Where a value lives, as computed by a location expression.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Place {
+    Memory(usize),
+    /// DWARF register number
+    Register(u16),
+    /// The value doesn't live anywhere, but the expression computed it (DW_OP_stack_value or
+    /// DW_OP_implicit_value)
+    Value(Vec<u8>),
+    /// The value is split into pieces, given as (place, size in bytes)
+    Pieces(Vec<(Place, usize)>),
+    OptimizedOut,
+}
+
+/// The state of the stack frame an expression is evaluated in.
+pub struct Frame<'a> {
+    pub regs: &'a user_regs_struct,
+    pub fpregs: Option<&'a user_fpregs_struct>,
+    /// Canonical frame address (the value of rsp before the call into this frame)
+    pub cfa: usize,
+    /// The value of the function's DW_AT_frame_base, used by DW_OP_fbreg
+    pub frame_base: Option<usize>,
+    pub read_memory: MemoryReader<'a>,
+}
+
+/// Runs a location expression and returns the place it describes.
+pub fn evaluate(expr: &LocationExpression, frame: &Frame) -> Result<Place, String> {
+    let bytecode = gimli::EndianSlice::new(&expr.bytecode, gimli::LittleEndian);
+    let mut pc = bytecode;
+    let mut stack: Vec<u64> = Vec::new();
+    // Set by operations that describe the location of the current piece directly rather than
+    // leaving an address on the stack
+    let mut current: Option<Place> = None;
+    let mut pieces: Vec<(Place, usize)> = Vec::new();
+
+    while !pc.is_empty() {
+        let op = gimli::Operation::parse(&mut pc, expr.encoding)
+            .map_err(|err| format!("Invalid DWARF expression: {}", err))?;
+        match op {
+            gimli::Operation::Deref {
+                size, space: false, ..
+            } => {
+                let addr = pop(&mut stack)?;
+                let bytes = (frame.read_memory)(addr as usize, size as usize)
+                    .map_err(|_| format!("Cannot access memory at address {:#x}", addr))?;
+                stack.push(read_unsigned(&bytes));
+            }
+            gimli::Operation::Drop => {
+                pop(&mut stack)?;
+            }
+            gimli::Operation::Pick { index } => {
+                let index = index as usize;
+                if index >= stack.len() {
+                    return Err("DWARF expression stack underflow".to_string());
+                }
+                stack.push(stack[stack.len() - 1 - index]);
+            }
+            gimli::Operation::Swap => {
+                let a = pop(&mut stack)?;
+                let b = pop(&mut stack)?;
+                stack.push(a);
+                stack.push(b);
+            }
+            gimli::Operation::Rot => {
+                let a = pop(&mut stack)?;
+                let b = pop(&mut stack)?;
+                let c = pop(&mut stack)?;
+                stack.push(a);
+                stack.push(c);
+                stack.push(b);
+            }
+            gimli::Operation::Abs => {
+                let a = pop(&mut stack)?;
+                stack.push((a as i64).wrapping_abs() as u64);
+            }
+            gimli::Operation::Neg => {
+                let a = pop(&mut stack)?;
+                stack.push((a as i64).wrapping_neg() as u64);
+            }
+            gimli::Operation::Not => {
+                let a = pop(&mut stack)?;
+                stack.push(!a);
+            }
+            gimli::Operation::PlusConstant { value } => {
+                let a = pop(&mut stack)?;
+                stack.push(a.wrapping_add(value));
+            }
+            gimli::Operation::And
+            | gimli::Operation::Div
+            | gimli::Operation::Minus
+            | gimli::Operation::Mod
+            | gimli::Operation::Mul
+            | gimli::Operation::Or
+            | gimli::Operation::Plus
+            | gimli::Operation::Shl
+            | gimli::Operation::Shr
+            | gimli::Operation::Shra
+            | gimli::Operation::Xor
+            | gimli::Operation::Eq
+            | gimli::Operation::Ge
+            | gimli::Operation::Gt
+            | gimli::Operation::Le
+            | gimli::Operation::Lt
+            | gimli::Operation::Ne => {
+                let b = pop(&mut stack)?;
+                let a = pop(&mut stack)?;
+                stack.push(binary_op(&op, a, b)?);
+            }
+            gimli::Operation::Bra { target } => {
+                if pop(&mut stack)? != 0 {
+                    pc = branch(bytecode, pc, target)?;
+                }
+            }
+            gimli::Operation::Skip { target } => {
+                pc = branch(bytecode, pc, target)?;
+            }
+            gimli::Operation::UnsignedConstant { value } => stack.push(value),
+            gimli::Operation::SignedConstant { value } => stack.push(value as u64),
+            gimli::Operation::Address { address } => stack.push(address),
+            gimli::Operation::Register { register } => current = Some(Place::Register(register.0)),
+            gimli::Operation::RegisterOffset {
+                register, offset, ..
+            } => {
+                let value = register_value(frame.regs, register.0)?;
+                stack.push(value.wrapping_add(offset as u64));
+            }
+            gimli::Operation::FrameOffset { offset } => match frame.frame_base {
+                Some(frame_base) => stack.push((frame_base as u64).wrapping_add(offset as u64)),
+                None => return Err("Frame base is not available.".to_string()),
+            },
+            gimli::Operation::CallFrameCFA => stack.push(frame.cfa as u64),
+            gimli::Operation::Nop => {}
+            gimli::Operation::StackValue => {
+                current = Some(Place::Value(pop(&mut stack)?.to_le_bytes().to_vec()))
+            }
+            gimli::Operation::ImplicitValue { data } => {
+                current = Some(Place::Value(data.slice().to_vec()))
+            }
+            gimli::Operation::Piece {
+                size_in_bits,
+                bit_offset: None,
+            } if size_in_bits % 8 == 0 => {
+                let place = match current.take() {
+                    Some(place) => place,
+                    None => match stack.pop() {
+                        Some(addr) => Place::Memory(addr as usize),
+                        None => Place::OptimizedOut,
+                    },
+                };
+                pieces.push((place, (size_in_bits / 8) as usize));
+            }
+            // The value a register had on entry to the function can't be recovered in general
+            gimli::Operation::EntryValue { .. } => return Ok(Place::OptimizedOut),
+            _ => return Err(format!("Unsupported DWARF expression operation {:?}", op)),
+        }
+    }
+
+    if !pieces.is_empty() {
+        return Ok(Place::Pieces(pieces));
+    }
+    Ok(match current {
+        Some(place) => place,
+        None => match stack.pop() {
+            Some(addr) => Place::Memory(addr as usize),
+            None => Place::OptimizedOut,
+        },
+    })
+}
+
+/// Reads size bytes of the value at a place. Returns an empty vector if (any part of) the value
+/// has been optimized out.
+pub fn read_place(place: &Place, size: usize, frame: &Frame) -> Result<Vec<u8>, String> {
+    let mut bytes = match place {
+        Place::Memory(addr) => (frame.read_memory)(*addr, size)
+            .map_err(|_| format!("Cannot access memory at address {:#x}", addr))?,
+        Place::Register(register) => register_bytes(frame, *register)?,
+        Place::Value(bytes) => bytes.clone(),
+        Place::Pieces(pieces) => {
+            let mut bytes = Vec::new();
+            for (piece, piece_size) in pieces {
+                let piece_bytes = read_place(piece, *piece_size, frame)?;
+                if piece_bytes.is_empty() {
+                    return Ok(Vec::new());
+                }
+                bytes.extend_from_slice(&piece_bytes);
+            }
+            bytes
+        }
+        Place::OptimizedOut => return Ok(Vec::new()),
+    };
+    bytes.resize(size, 0);
+    Ok(bytes)
+}
+
+/// Returns the address of a place, if it is in memory.
+pub fn place_address(place: &Place) -> Option<usize> {
+    match place {
+        Place::Memory(addr) => Some(*addr),
+        _ => None,
+    }
+}
+
+/// Returns the value of a general-purpose register given its DWARF register number.
+pub fn register_value(regs: &user_regs_struct, register: u16) -> Result<u64, String> {
+    Ok(match register {
+        0 => regs.rax,
+        1 => regs.rdx,
+        2 => regs.rcx,
+        3 => regs.rbx,
+        4 => regs.rsi,
+        5 => regs.rdi,
+        6 => regs.rbp,
+        7 => regs.rsp,
+        8 => regs.r8,
+        9 => regs.r9,
+        10 => regs.r10,
+        11 => regs.r11,
+        12 => regs.r12,
+        13 => regs.r13,
+        14 => regs.r14,
+        15 => regs.r15,
+        16 => regs.rip,
+        _ => return Err(format!("Unsupported DWARF register {}", register)),
+    })
+}
+
+/// Returns a mutable reference to a general-purpose register given its DWARF register number.
+pub fn register_value_mut(regs: &mut user_regs_struct, register: u16) -> Result<&mut u64, String> {
+    Ok(match register {
+        0 => &mut regs.rax,
+        1 => &mut regs.rdx,
+        2 => &mut regs.rcx,
+        3 => &mut regs.rbx,
+        4 => &mut regs.rsi,
+        5 => &mut regs.rdi,
+        6 => &mut regs.rbp,
+        7 => &mut regs.rsp,
+        8 => &mut regs.r8,
+        9 => &mut regs.r9,
+        10 => &mut regs.r10,
+        11 => &mut regs.r11,
+        12 => &mut regs.r12,
+        13 => &mut regs.r13,
+        14 => &mut regs.r14,
+        15 => &mut regs.r15,
+        16 => &mut regs.rip,
+        _ => return Err(format!("Unsupported DWARF register {}", register)),
+    })
+}
+
+fn register_bytes(frame: &Frame, register: u16) -> Result<Vec<u8>, String> {
+    if (DWARF_XMM0..=DWARF_XMM15).contains(&register) {
+        let fpregs = frame
+            .fpregs
+            .ok_or_else(|| "Floating point registers are not available.".to_string())?;
+        return Ok(registers::xmm_bytes(
+            fpregs,
+            (register - DWARF_XMM0) as usize,
+        ));
+    }
+    Ok(register_value(frame.regs, register)?.to_le_bytes().to_vec())
+}
+
+fn pop(stack: &mut Vec<u64>) -> Result<u64, String> {
+    stack
+        .pop()
+        .ok_or_else(|| "DWARF expression stack underflow".to_string())
+}
+
+fn binary_op<R: gimli::Reader>(op: &gimli::Operation<R>, a: u64, b: u64) -> Result<u64, String> {
+    Ok(match op {
+        gimli::Operation::And => a & b,
+        gimli::Operation::Or => a | b,
+        gimli::Operation::Xor => a ^ b,
+        gimli::Operation::Plus => a.wrapping_add(b),
+        gimli::Operation::Minus => a.wrapping_sub(b),
+        gimli::Operation::Mul => a.wrapping_mul(b),
+        gimli::Operation::Div | gimli::Operation::Mod if b == 0 => {
+            return Err("Division by zero in DWARF expression".to_string())
+        }
+        gimli::Operation::Div => (a as i64).wrapping_div(b as i64) as u64,
+        gimli::Operation::Mod => a % b,
+        gimli::Operation::Shl => a.checked_shl(b as u32).unwrap_or(0),
+        gimli::Operation::Shr => a.checked_shr(b as u32).unwrap_or(0),
+        gimli::Operation::Shra => (a as i64).checked_shr(b as u32).unwrap_or(a as i64 >> 63) as u64,
+        gimli::Operation::Eq => (a == b) as u64,
+        gimli::Operation::Ne => (a != b) as u64,
+        gimli::Operation::Ge => (a as i64 >= b as i64) as u64,
+        gimli::Operation::Gt => (a as i64 > b as i64) as u64,
+        gimli::Operation::Le => (a as i64 <= b as i64) as u64,
+        gimli::Operation::Lt => ((a as i64) < b as i64) as u64,
+        _ => unreachable!(),
+    })
+}
+
+/// Returns the reader positioned at a branch target, which is relative to the end of the branch
+/// instruction (i.e. the current pc).
+fn branch<'a>(
+    bytecode: gimli::EndianSlice<'a, gimli::LittleEndian>,
+    pc: gimli::EndianSlice<'a, gimli::LittleEndian>,
+    target: i16,
+) -> Result<gimli::EndianSlice<'a, gimli::LittleEndian>, String> {
+    let offset = (bytecode.len() - pc.len()) as isize + target as isize;
+    if offset < 0 || offset as usize > bytecode.len() {
+        return Err("Invalid branch target in DWARF expression".to_string());
+    }
+    Ok(bytecode.range_from(offset as usize..))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nix::errno::Errno;
+
+    const RBP: u64 = 0x7ff0;
+    const CFA: usize = 0x8000;
+    const FRAME_BASE: usize = 0x7fe0;
+    // Memory holds 16 bytes at this address, and nothing else
+    const DATA: usize = 0x1000;
+
+    fn read_memory(addr: usize, size: usize) -> Result<Vec<u8>, nix::Error> {
+        let data: Vec<u8> = (0..16).collect();
+        if addr < DATA || addr + size > DATA + data.len() {
+            return Err(nix::Error::Sys(Errno::EIO));
+        }
+        Ok(data[addr - DATA..addr - DATA + size].to_vec())
+    }
+
+    fn regs() -> user_regs_struct {
+        let mut regs: user_regs_struct = unsafe { std::mem::zeroed() };
+        regs.rax = 0x1122_3344;
+        regs.rbp = RBP;
+        regs.rsp = DATA as u64;
+        regs
+    }
+
+    fn run(bytecode: &[u8]) -> Result<Place, String> {
+        let regs = regs();
+        let expr = LocationExpression {
+            bytecode: bytecode.to_vec(),
+            encoding: gimli::Encoding {
+                format: gimli::Format::Dwarf32,
+                version: 4,
+                address_size: 8,
+            },
+        };
+        let frame = Frame {
+            regs: &regs,
+            fpregs: None,
+            cfa: CFA,
+            frame_base: Some(FRAME_BASE),
+            read_memory: &read_memory,
+        };
+        evaluate(&expr, &frame)
+    }
+
+    fn value(value: u64) -> Place {
+        Place::Value(value.to_le_bytes().to_vec())
+    }
+
+    #[test]
+    fn evaluate_computes_addresses() {
+        // DW_OP_fbreg -16
+        assert_eq!(run(&[0x91, 0x70]), Ok(Place::Memory(FRAME_BASE - 16)));
+        // DW_OP_breg6 (rbp) 8
+        assert_eq!(run(&[0x76, 0x08]), Ok(Place::Memory(RBP as usize + 8)));
+        // DW_OP_call_frame_cfa
+        assert_eq!(run(&[0x9c]), Ok(Place::Memory(CFA)));
+        // DW_OP_addr 0x601040
+        assert_eq!(
+            run(&[0x03, 0x40, 0x10, 0x60, 0, 0, 0, 0, 0]),
+            Ok(Place::Memory(0x601040))
+        );
+        // DW_OP_reg0 (rax)
+        assert_eq!(run(&[0x50]), Ok(Place::Register(0)));
+    }
+
+    #[test]
+    fn evaluate_reads_memory_for_deref() {
+        // DW_OP_breg7 (rsp) 4; DW_OP_deref_size 2
+        assert_eq!(run(&[0x77, 0x04, 0x94, 0x02]), Ok(Place::Memory(0x0504)));
+        // DW_OP_lit0; DW_OP_deref
+        assert_eq!(
+            run(&[0x30, 0x06]),
+            Err("Cannot access memory at address 0x0".to_string())
+        );
+    }
+
+    #[test]
+    fn evaluate_runs_stack_operations() {
+        // DW_OP_lit5; DW_OP_lit3; DW_OP_minus; DW_OP_stack_value
+        assert_eq!(run(&[0x35, 0x33, 0x1c, 0x9f]), Ok(value(2)));
+        // DW_OP_lit1; DW_OP_lit2; DW_OP_swap; DW_OP_minus; DW_OP_stack_value
+        assert_eq!(run(&[0x31, 0x32, 0x16, 0x1c, 0x9f]), Ok(value(1)));
+        // DW_OP_lit1; DW_OP_lit2; DW_OP_lit3; DW_OP_rot; DW_OP_drop; DW_OP_drop; DW_OP_stack_value
+        assert_eq!(
+            run(&[0x31, 0x32, 0x33, 0x17, 0x13, 0x13, 0x9f]),
+            Ok(value(3))
+        );
+        // DW_OP_lit7; DW_OP_dup; DW_OP_mul; DW_OP_over (which needs two entries)
+        assert_eq!(
+            run(&[0x37, 0x12, 0x1e, 0x14]),
+            Err("DWARF expression stack underflow".to_string())
+        );
+        // DW_OP_lit7; DW_OP_dup; DW_OP_dup; DW_OP_mul; DW_OP_plus; DW_OP_stack_value
+        assert_eq!(run(&[0x37, 0x12, 0x12, 0x1e, 0x22, 0x9f]), Ok(value(56)));
+        // DW_OP_const1s -4; DW_OP_abs; DW_OP_plus_uconst 2; DW_OP_neg; DW_OP_stack_value
+        assert_eq!(
+            run(&[0x09, 0xfc, 0x19, 0x23, 0x02, 0x1f, 0x9f]),
+            Ok(value(-6i64 as u64))
+        );
+        // DW_OP_implicit_value 2 0xab 0xcd
+        assert_eq!(
+            run(&[0x9e, 0x02, 0xab, 0xcd]),
+            Ok(Place::Value(vec![0xab, 0xcd]))
+        );
+    }
+
+    #[test]
+    fn binary_op_treats_operands_as_signed_where_c_does() {
+        type Op = gimli::Operation<gimli::EndianSlice<'static, gimli::LittleEndian>>;
+        let minus_seven = -7i64 as u64;
+        assert_eq!(binary_op(&Op::Div, minus_seven, 2), Ok(-3i64 as u64));
+        assert_eq!(binary_op(&Op::Lt, minus_seven, 2), Ok(1));
+        assert_eq!(binary_op(&Op::Ge, minus_seven, 2), Ok(0));
+        assert_eq!(binary_op(&Op::Shra, minus_seven, 1), Ok(-4i64 as u64));
+        assert_eq!(binary_op(&Op::Shra, minus_seven, 64), Ok(u64::MAX));
+        assert_eq!(binary_op(&Op::Shr, minus_seven, 64), Ok(0));
+        assert_eq!(binary_op(&Op::Shl, 1, 64), Ok(0));
+        assert_eq!(binary_op(&Op::Mod, 7, 3), Ok(1));
+        assert_eq!(
+            binary_op(&Op::Div, 7, 0),
+            Err("Division by zero in DWARF expression".to_string())
+        );
+    }
+
+    #[test]
+    fn evaluate_follows_branches() {
+        // <cond>; DW_OP_bra +4; DW_OP_lit10; DW_OP_skip +1; DW_OP_lit20; DW_OP_stack_value
+        let program = |cond| vec![cond, 0x28, 0x04, 0x00, 0x3a, 0x2f, 0x01, 0x00, 0x44, 0x9f];
+        assert_eq!(run(&program(0x30)), Ok(value(10)));
+        assert_eq!(run(&program(0x31)), Ok(value(20)));
+        // DW_OP_lit3; DW_OP_lit1; DW_OP_minus; DW_OP_dup; DW_OP_bra -6; DW_OP_stack_value
+        // counts down to 0, branching back while the counter isn't
+        assert_eq!(
+            run(&[0x33, 0x31, 0x1c, 0x12, 0x28, 0xfa, 0xff, 0x9f]),
+            Ok(value(0))
+        );
+        // DW_OP_skip past either end
+        assert_eq!(
+            run(&[0x2f, 0x10, 0x00]),
+            Err("Invalid branch target in DWARF expression".to_string())
+        );
+        assert_eq!(
+            run(&[0x2f, 0xf0, 0xff]),
+            Err("Invalid branch target in DWARF expression".to_string())
+        );
+    }
+
+    #[test]
+    fn evaluate_collects_pieces() {
+        // DW_OP_reg0; DW_OP_piece 4; DW_OP_breg6 0; DW_OP_piece 4; DW_OP_piece 2
+        assert_eq!(
+            run(&[0x50, 0x93, 0x04, 0x76, 0x00, 0x93, 0x04, 0x93, 0x02]),
+            Ok(Place::Pieces(vec![
+                (Place::Register(0), 4),
+                (Place::Memory(RBP as usize), 4),
+                (Place::OptimizedOut, 2),
+            ]))
+        );
+    }
+
+    #[test]
+    fn read_place_joins_pieces() {
+        let regs = regs();
+        let frame = Frame {
+            regs: &regs,
+            fpregs: None,
+            cfa: CFA,
+            frame_base: None,
+            read_memory: &read_memory,
+        };
+        let pieces = Place::Pieces(vec![(Place::Register(0), 2), (Place::Memory(DATA + 8), 2)]);
+        assert_eq!(read_place(&pieces, 4, &frame), Ok(vec![0x44, 0x33, 8, 9]));
+        let partly_optimized_out =
+            Place::Pieces(vec![(Place::Register(0), 2), (Place::OptimizedOut, 2)]);
+        assert_eq!(read_place(&partly_optimized_out, 4, &frame), Ok(Vec::new()));
+        assert_eq!(
+            read_place(&Place::Register(DWARF_XMM0), 16, &frame),
+            Err("Floating point registers are not available.".to_string())
+        );
+    }
+
+    #[test]
+    fn evaluate_reports_unusable_expressions() {
+        assert_eq!(run(&[]), Ok(Place::OptimizedOut));
+        // DW_OP_plus on an empty stack
+        assert_eq!(
+            run(&[0x22]),
+            Err("DWARF expression stack underflow".to_string())
+        );
+        // DW_OP_entry_value 1 DW_OP_reg5; DW_OP_stack_value
+        assert_eq!(run(&[0xa3, 0x01, 0x55, 0x9f]), Ok(Place::OptimizedOut));
+        // DW_OP_fbreg without a frame base
+        let regs = regs();
+        let frame = Frame {
+            regs: &regs,
+            fpregs: None,
+            cfa: CFA,
+            frame_base: None,
+            read_memory: &read_memory,
+        };
+        let expr = LocationExpression {
+            bytecode: vec![0x91, 0x00],
+            encoding: gimli::Encoding {
+                format: gimli::Format::Dwarf32,
+                version: 4,
+                address_size: 8,
+            },
+        };
+        assert_eq!(
+            evaluate(&expr, &frame),
+            Err("Frame base is not available.".to_string())
+        );
+        // DW_OP_call2
+        assert!(run(&[0x98, 0x00, 0x00])
+            .unwrap_err()
+            .starts_with("Unsupported DWARF expression operation"));
+    }
+}
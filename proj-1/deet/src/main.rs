@@ -1,22 +1,76 @@
+mod core_dump;
 mod debugger;
 mod debugger_command;
+mod disassembler;
+mod dwarf_data;
+mod expression;
+mod gimli_wrapper;
 mod inferior;
+mod location;
+mod registers;
+mod shared_library;
+mod signals;
+mod source;
+mod target;
+mod unwind;
+mod value;
 
 use crate::debugger::Debugger;
 use nix::sys::signal::{signal, SigHandler, Signal};
+use nix::unistd::Pid;
 use std::env;
 
+const USAGE: &str = "Usage: deet [-batch] [-x <file>] [-ex <command>] <target program> \
+                     [<core file>] | -p <pid>";
+
+fn usage() -> ! {
+    println!("{}", USAGE);
+    std::process::exit(1);
+}
+
 fn main() {
-    let args: Vec<String> = env::args().collect();
-    if args.len() != 2 {
-        println!("Usage: {} <target program>", args[0]);
-        std::process::exit(1);
+    // Either `deet <target program> [<core file>]` or `deet -p <pid>` to attach to a running
+    // process, optionally preceded by commands to run first: `-ex <command>` and `-x <file>`
+    // (which sources the file), in the order given. With -batch, deet quits after running them.
+    let mut args = env::args().skip(1);
+    let mut commands = Vec::new();
+    let mut batch = false;
+    let mut pid = None;
+    let mut files = Vec::new();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-batch" | "--batch" => batch = true,
+            "-x" => commands.push(format!("source {}", args.next().unwrap_or_else(|| usage()))),
+            "-ex" => commands.push(args.next().unwrap_or_else(|| usage())),
+            "-p" => {
+                let arg = args.next().unwrap_or_else(|| usage());
+                match arg.parse::<i32>() {
+                    Ok(num) => pid = Some(Pid::from_raw(num)),
+                    Err(_) => {
+                        println!("Invalid process id {}", arg);
+                        std::process::exit(1);
+                    }
+                }
+            }
+            _ => files.push(arg),
+        }
     }
-    let target = &args[1];
+    let target = match (pid, files.len()) {
+        (Some(pid), 0) => format!("/proc/{}/exe", pid),
+        (None, 1) | (None, 2) => files[0].clone(),
+        _ => usage(),
+    };
 
     // Disable handling of ctrl+c in this process (so that ctrl+c only gets delivered to child
     // processes)
     unsafe { signal(Signal::SIGINT, SigHandler::SigIgn) }.expect("Error disabling SIGINT handling");
 
-    Debugger::new(target).run();
+    let mut debugger = Debugger::new(&target);
+    if let Some(pid) = pid {
+        debugger.attach(pid);
+    } else if files.len() == 2 {
+        debugger.load_core(&files[1]);
+    }
+    debugger.queue_commands(commands, batch);
+    std::process::exit(debugger.run());
 }
@@ -0,0 +1,97 @@
+//! Access to the inferior's registers by name, for `$reg` in expressions, `info registers` and
+//! `set $reg = value`.
+
+use nix::libc::{user_fpregs_struct, user_regs_struct};
+
+/// The registers shown by `info registers`, in gdb's order.
+pub const REGISTER_NAMES: &[&str] = &[
+    "rax", "rbx", "rcx", "rdx", "rsi", "rdi", "rbp", "rsp", "r8", "r9", "r10", "r11", "r12", "r13",
+    "r14", "r15", "rip", "eflags", "cs", "ss", "ds", "es", "fs", "gs", "fs_base", "gs_base",
+];
+
+// Flags in the low bits of eflags, by bit number
+const EFLAGS: &[(u32, &str)] = &[
+    (0, "CF"),
+    (2, "PF"),
+    (4, "AF"),
+    (6, "ZF"),
+    (7, "SF"),
+    (8, "TF"),
+    (9, "IF"),
+    (10, "DF"),
+    (11, "OF"),
+    (14, "NT"),
+    (16, "RF"),
+    (17, "VM"),
+    (18, "AC"),
+    (19, "VIF"),
+    (20, "VIP"),
+    (21, "ID"),
+];
+
+/// Returns a general-purpose, flags or segment register given its name (without the `$`),
+/// including gdb's aliases `pc`, `sp` and `fp`.
+pub fn register_mut<'a>(regs: &'a mut user_regs_struct, name: &str) -> Option<&'a mut u64> {
+    Some(match name {
+        "rip" | "pc" => &mut regs.rip,
+        "rsp" | "sp" => &mut regs.rsp,
+        "rbp" | "fp" => &mut regs.rbp,
+        "rax" => &mut regs.rax,
+        "rbx" => &mut regs.rbx,
+        "rcx" => &mut regs.rcx,
+        "rdx" => &mut regs.rdx,
+        "rsi" => &mut regs.rsi,
+        "rdi" => &mut regs.rdi,
+        "r8" => &mut regs.r8,
+        "r9" => &mut regs.r9,
+        "r10" => &mut regs.r10,
+        "r11" => &mut regs.r11,
+        "r12" => &mut regs.r12,
+        "r13" => &mut regs.r13,
+        "r14" => &mut regs.r14,
+        "r15" => &mut regs.r15,
+        "eflags" => &mut regs.eflags,
+        "cs" => &mut regs.cs,
+        "ss" => &mut regs.ss,
+        "ds" => &mut regs.ds,
+        "es" => &mut regs.es,
+        "fs" => &mut regs.fs,
+        "gs" => &mut regs.gs,
+        "fs_base" => &mut regs.fs_base,
+        "gs_base" => &mut regs.gs_base,
+        _ => return None,
+    })
+}
+
+/// Returns the value of a register given its name, as accepted by register_mut.
+pub fn register_by_name(regs: &user_regs_struct, name: &str) -> Option<u64> {
+    let mut regs = *regs;
+    register_mut(&mut regs, name).map(|value| *value)
+}
+
+/// Lists the flags set in eflags, e.g. "[ PF ZF IF ]".
+pub fn format_eflags(eflags: u64) -> String {
+    let flags: Vec<&str> = EFLAGS
+        .iter()
+        .filter(|(bit, _)| eflags & (1 << bit) != 0)
+        .map(|(_, name)| *name)
+        .collect();
+    format!("[ {} ]", flags.join(" "))
+}
+
+/// Returns the 10-byte x87 value of st(i). FXSAVE stores the stack in 16-byte slots, top first.
+pub fn st_bytes(fpregs: &user_fpregs_struct, i: usize) -> Vec<u8> {
+    fpregs.st_space[i * 4..i * 4 + 4]
+        .iter()
+        .flat_map(|word| word.to_le_bytes().to_vec())
+        .take(10)
+        .collect()
+}
+
+/// Returns the 16 bytes of xmm(i).
+pub fn xmm_bytes(fpregs: &user_fpregs_struct, i: usize) -> Vec<u8> {
+    fpregs.xmm_space[i * 4..i * 4 + 4]
+        .iter()
+        .flat_map(|word| word.to_le_bytes().to_vec())
+        .collect()
+}
@@ -0,0 +1,138 @@
+//! Finding the shared libraries a process has loaded.
+//!
+//! The libraries are read from /proc/<pid>/maps, which also tells us where each one was placed.
+//! To find out when that changes, we stop where the dynamic linker reports changes to its list of
+//! libraries: the r_brk function in its struct r_debug, which is reached through the DT_DEBUG
+//! entry of the program's dynamic section.
+
+use crate::dwarf_data::DwarfData;
+use crate::target::Target;
+use nix::unistd::Pid;
+use std::fs;
+
+const DT_NULL: u64 = 0;
+const DT_DEBUG: u64 = 21;
+
+// Offset of r_brk in struct r_debug on x86-64
+const R_DEBUG_BRK: usize = 16;
+
+/// A shared library mapped into the inferior
+pub struct SharedLibrary {
+    pub path: String,
+    /// The range of addresses the library is mapped at
+    pub start: usize,
+    pub end: usize,
+    /// Its symbols and debugging information, relocated to where the library is mapped. None if
+    /// the file couldn't be read.
+    pub debug_data: Option<DwarfData>,
+}
+
+impl SharedLibrary {
+    pub fn contains(&self, addr: usize) -> bool {
+        addr >= self.start && addr < self.end
+    }
+}
+
+/// A file mapped into a process, as listed in /proc/<pid>/maps
+struct Mapping {
+    start: usize,
+    end: usize,
+    /// Offset into the file of the first mapped byte
+    offset: usize,
+    path: String,
+}
+
+fn file_mappings(pid: Pid) -> Vec<Mapping> {
+    let maps = match fs::read_to_string(format!("/proc/{}/maps", pid)) {
+        Ok(maps) => maps,
+        Err(_) => return Vec::new(),
+    };
+    let mut mappings = Vec::new();
+    for line in maps.lines() {
+        // start-end perms offset dev inode path
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() < 6 || !fields[5].starts_with('/') {
+            continue;
+        }
+        let mut range = fields[0].splitn(2, '-');
+        let parse_hex = |hex: Option<&str>| usize::from_str_radix(hex?, 16).ok();
+        let (start, end, offset) = match (
+            parse_hex(range.next()),
+            parse_hex(range.next()),
+            parse_hex(Some(fields[2])),
+        ) {
+            (Some(start), Some(end), Some(offset)) => (start, end, offset),
+            _ => continue,
+        };
+        mappings.push(Mapping {
+            start,
+            end,
+            offset,
+            path: fields[5..].join(" "),
+        });
+    }
+    mappings
+}
+
+fn executable_path(pid: Pid) -> Option<String> {
+    let path = fs::read_link(format!("/proc/{}/exe", pid)).ok()?;
+    Some(path.to_string_lossy().to_string())
+}
+
+/// Returns the address that the first page of the process's executable is mapped at.
+pub fn executable_start(pid: Pid) -> Option<usize> {
+    let executable = executable_path(pid)?;
+    file_mappings(pid)
+        .into_iter()
+        .find(|mapping| mapping.path == executable && mapping.offset == 0)
+        .map(|mapping| mapping.start)
+}
+
+/// Lists the shared libraries mapped into a process, in address order. Their data isn't loaded
+/// yet.
+pub fn mapped_libraries(pid: Pid) -> Vec<SharedLibrary> {
+    let executable = executable_path(pid);
+    let mut libraries: Vec<SharedLibrary> = Vec::new();
+    for mapping in file_mappings(pid) {
+        let file_name = mapping.path.rsplit('/').next().unwrap_or("");
+        if !file_name.contains(".so") || executable.as_ref() == Some(&mapping.path) {
+            continue;
+        }
+        // A library's mappings are contiguous, starting with its first page
+        match libraries.last_mut() {
+            Some(library) if library.path == mapping.path && library.end == mapping.start => {
+                library.end = mapping.end
+            }
+            _ if mapping.offset == 0 => libraries.push(SharedLibrary {
+                path: mapping.path,
+                start: mapping.start,
+                end: mapping.end,
+                debug_data: None,
+            }),
+            _ => {}
+        }
+    }
+    libraries
+}
+
+/// Returns the address of the dynamic linker's r_brk function, given the address of the
+/// program's dynamic section. The dynamic linker fills in DT_DEBUG as it starts up, so until the
+/// program reaches its entry point this returns None.
+pub fn dynamic_linker_breakpoint(target: &dyn Target, dynamic_address: usize) -> Option<usize> {
+    let mut entry = dynamic_address;
+    loop {
+        match target.read_word(entry).ok()? as u64 {
+            DT_NULL => return None,
+            DT_DEBUG => break,
+            _ => entry += 16,
+        }
+    }
+    let r_debug = target.read_word(entry + 8).ok()?;
+    if r_debug == 0 {
+        return None;
+    }
+    match target.read_word(r_debug + R_DEBUG_BRK).ok()? {
+        0 => None,
+        r_brk => Some(r_brk),
+    }
+}
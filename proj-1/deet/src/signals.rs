@@ -0,0 +1,197 @@
+//! What to do when the inferior receives a signal, as set with `handle`.
+
+use nix::libc;
+use nix::sys::signal::Signal;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::ffi::CStr;
+
+#[derive(Clone, Copy)]
+pub struct Handling {
+    /// Whether the debugger stops and gives the user control
+    pub stop: bool,
+    /// Whether the debugger mentions the signal
+    pub print: bool,
+    /// Whether the signal is delivered to the program when it resumes
+    pub pass: bool,
+}
+
+// Signals that happen in normal operation and that gdb lets through silently
+const QUIET_SIGNALS: &[Signal] = &[
+    Signal::SIGALRM,
+    Signal::SIGURG,
+    Signal::SIGCHLD,
+    Signal::SIGWINCH,
+    Signal::SIGIO,
+    Signal::SIGVTALRM,
+    Signal::SIGPROF,
+];
+
+#[derive(Clone)]
+pub struct SignalTable {
+    handling: HashMap<Signal, Handling>,
+}
+
+impl SignalTable {
+    /// Returns the table with gdb's defaults: most signals stop, are printed and are passed on.
+    /// SIGTRAP and SIGINT are used by the debugger itself, so they aren't passed on.
+    pub fn new() -> SignalTable {
+        let mut handling = HashMap::new();
+        for signal in Signal::iterator() {
+            let quiet = QUIET_SIGNALS.contains(&signal);
+            handling.insert(
+                signal,
+                Handling {
+                    stop: !quiet,
+                    print: !quiet,
+                    pass: signal != Signal::SIGTRAP && signal != Signal::SIGINT,
+                },
+            );
+        }
+        SignalTable { handling }
+    }
+
+    pub fn get(&self, signal: Signal) -> Handling {
+        self.handling[&signal]
+    }
+
+    /// Applies the `handle` keywords (stop, nostop, print, noprint, pass, nopass, and gdb's
+    /// synonyms noignore and ignore) to a signal. As in gdb, stopping implies printing, and not
+    /// printing implies not stopping.
+    pub fn update(&mut self, signal: Signal, keywords: &[&str]) -> Result<(), String> {
+        let handling = self.handling.get_mut(&signal).unwrap();
+        for keyword in keywords {
+            match *keyword {
+                "stop" => {
+                    handling.stop = true;
+                    handling.print = true;
+                }
+                "nostop" => handling.stop = false,
+                "print" => handling.print = true,
+                "noprint" => {
+                    handling.print = false;
+                    handling.stop = false;
+                }
+                "pass" | "noignore" => handling.pass = true,
+                "nopass" | "ignore" => handling.pass = false,
+                _ => {
+                    return Err(format!(
+                        "Unrecognized or ambiguous flag word: \"{}\".",
+                        keyword
+                    ))
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Prints the handling of the given signals as a table.
+    pub fn print(&self, signals: &[Signal]) {
+        println!(
+            "{:<14}{:<6}{:<7}{:<17}Description",
+            "Signal", "Stop", "Print", "Pass to program"
+        );
+        let yes_no = |flag| if flag { "Yes" } else { "No" };
+        for &signal in signals {
+            let handling = self.get(signal);
+            println!(
+                "{:<14}{:<6}{:<7}{:<17}{}",
+                signal.as_str(),
+                yes_no(handling.stop),
+                yes_no(handling.print),
+                yes_no(handling.pass),
+                describe(signal)
+            );
+        }
+    }
+}
+
+/// Parses a signal given by name (SIGUSR1 or USR1) or number.
+pub fn parse_signal(name: &str) -> Option<Signal> {
+    if let Ok(num) = name.parse::<i32>() {
+        return Signal::try_from(num).ok();
+    }
+    let name = name.to_uppercase();
+    if name.starts_with("SIG") {
+        name.parse().ok()
+    } else {
+        format!("SIG{}", name).parse().ok()
+    }
+}
+
+/// Returns the C library's description of a signal, e.g. "User defined signal 1".
+pub fn describe(signal: Signal) -> String {
+    let description = unsafe { libc::strsignal(signal as libc::c_int) };
+    if description.is_null() {
+        return String::new();
+    }
+    unsafe { CStr::from_ptr(description) }
+        .to_string_lossy()
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn flags(handling: Handling) -> (bool, bool, bool) {
+        (handling.stop, handling.print, handling.pass)
+    }
+
+    #[test]
+    fn parse_signal_takes_names_with_or_without_sig() {
+        assert_eq!(parse_signal("SIGUSR1"), Some(Signal::SIGUSR1));
+        assert_eq!(parse_signal("USR1"), Some(Signal::SIGUSR1));
+        assert_eq!(parse_signal("sigsegv"), Some(Signal::SIGSEGV));
+        assert_eq!(parse_signal("int"), Some(Signal::SIGINT));
+    }
+
+    #[test]
+    fn parse_signal_takes_numbers() {
+        assert_eq!(parse_signal("9"), Some(Signal::SIGKILL));
+        assert_eq!(parse_signal("11"), Some(Signal::SIGSEGV));
+        assert_eq!(parse_signal("0"), None);
+        assert_eq!(parse_signal("999"), None);
+        assert_eq!(parse_signal("-2"), None);
+    }
+
+    #[test]
+    fn parse_signal_rejects_unknown_names() {
+        assert_eq!(parse_signal("SIGFOO"), None);
+        assert_eq!(parse_signal("SIG"), None);
+        assert_eq!(parse_signal(""), None);
+    }
+
+    #[test]
+    fn defaults_follow_gdb() {
+        let table = SignalTable::new();
+        assert_eq!(flags(table.get(Signal::SIGSEGV)), (true, true, true));
+        assert_eq!(flags(table.get(Signal::SIGTRAP)), (true, true, false));
+        assert_eq!(flags(table.get(Signal::SIGINT)), (true, true, false));
+        assert_eq!(flags(table.get(Signal::SIGCHLD)), (false, false, true));
+    }
+
+    #[test]
+    fn update_keeps_stop_and_print_consistent() {
+        let mut table = SignalTable::new();
+        table
+            .update(Signal::SIGUSR1, &["noprint", "nopass"])
+            .unwrap();
+        assert_eq!(flags(table.get(Signal::SIGUSR1)), (false, false, false));
+        table
+            .update(Signal::SIGUSR1, &["stop", "noignore"])
+            .unwrap();
+        assert_eq!(flags(table.get(Signal::SIGUSR1)), (true, true, true));
+        table.update(Signal::SIGUSR1, &["nostop"]).unwrap();
+        assert_eq!(flags(table.get(Signal::SIGUSR1)), (false, true, true));
+    }
+
+    #[test]
+    fn update_rejects_unknown_keywords() {
+        let mut table = SignalTable::new();
+        assert_eq!(
+            table.update(Signal::SIGUSR1, &["pass", "loudly"]),
+            Err("Unrecognized or ambiguous flag word: \"loudly\".".to_string())
+        );
+    }
+}
@@ -0,0 +1,93 @@
+//! Locating and caching the source files named in the debugging information, for `list`.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+pub struct SourceFiles {
+    /// Directories searched for sources that aren't found at the path recorded in the binary,
+    /// e.g. because it was compiled inside a container with the sources mounted elsewhere
+    search_path: Vec<String>,
+    /// Lines of each file, by the name recorded in the binary (None if it couldn't be found)
+    cache: HashMap<String, Option<Vec<String>>>,
+}
+
+impl SourceFiles {
+    pub fn new() -> SourceFiles {
+        SourceFiles {
+            search_path: Vec::new(),
+            cache: HashMap::new(),
+        }
+    }
+
+    /// Adds a directory to the front of the search path, like gdb's `directory` command.
+    pub fn add_directory(&mut self, dir: &str) {
+        self.search_path.retain(|existing| existing != dir);
+        self.search_path.insert(0, dir.to_string());
+        // Files that weren't found before may be found now
+        self.cache.clear();
+    }
+
+    pub fn reset_search_path(&mut self) {
+        self.search_path.clear();
+        self.cache.clear();
+    }
+
+    /// Describes the search path, with $cwd standing for the current directory (which is always
+    /// searched last).
+    pub fn search_path(&self) -> String {
+        let mut dirs = self.search_path.clone();
+        dirs.push("$cwd".to_string());
+        dirs.join(":")
+    }
+
+    /// Returns the lines of a source file given the name recorded in the debugging information.
+    pub fn get_lines(&mut self, name: &str) -> Option<&[String]> {
+        if !self.cache.contains_key(name) {
+            let lines = self.find(name).and_then(|path| {
+                let contents = fs::read(path).ok()?;
+                Some(
+                    String::from_utf8_lossy(&contents)
+                        .lines()
+                        .map(|line| line.to_string())
+                        .collect(),
+                )
+            });
+            self.cache.insert(name.to_string(), lines);
+        }
+        self.cache[name].as_deref()
+    }
+
+    /// Finds a source file. The recorded path is tried first. Then, for each directory in the
+    /// search path, the recorded path is tried relative to that directory with fewer and fewer
+    /// of its leading components: /deet/samples/a.c is looked for as <dir>/deet/samples/a.c,
+    /// <dir>/samples/a.c and finally <dir>/a.c.
+    fn find(&self, name: &str) -> Option<PathBuf> {
+        let path = Path::new(name);
+        if path.is_file() {
+            return Some(path.to_path_buf());
+        }
+        let components: Vec<&Path> = path
+            .components()
+            .filter_map(|component| match component {
+                Component::Normal(part) => Some(Path::new(part)),
+                _ => None,
+            })
+            .collect();
+        let dirs = self
+            .search_path
+            .iter()
+            .map(Path::new)
+            .chain(std::iter::once(Path::new(".")));
+        for dir in dirs {
+            for start in 0..components.len() {
+                let mut candidate = dir.to_path_buf();
+                candidate.extend(&components[start..]);
+                if candidate.is_file() {
+                    return Some(candidate);
+                }
+            }
+        }
+        None
+    }
+}
@@ -0,0 +1,238 @@
+//! Read-only access to the state of a program being debugged, which is either a live inferior
+//! or a core dump. Everything that only looks at registers and memory (backtraces, printing
+//! variables, examining memory) goes through this trait.
+
+use crate::dwarf_data::DwarfData;
+use crate::value::read_unsigned;
+use nix::libc::{user_fpregs_struct, user_regs_struct};
+use std::mem::size_of;
+
+const AT_NULL: u64 = 0;
+const AT_ENTRY: u64 = 9;
+
+pub trait Target {
+    /// Returns the general-purpose registers (of the selected thread).
+    fn get_registers(&self) -> Result<user_regs_struct, nix::Error>;
+
+    /// Returns the floating point and SSE registers.
+    fn get_fpregs(&self) -> Result<user_fpregs_struct, nix::Error>;
+
+    /// Reads len bytes of memory starting at addr.
+    fn read_memory(&self, addr: usize, len: usize) -> Result<Vec<u8>, nix::Error>;
+
+    /// Returns the current instruction pointer, or 0 if the registers cannot be read.
+    fn rip(&self) -> usize {
+        self.get_registers().map_or(0, |regs| regs.rip as usize)
+    }
+
+    /// Reads the 8-byte word at addr.
+    fn read_word(&self, addr: usize) -> Result<usize, nix::Error> {
+        let bytes = self.read_memory(addr, size_of::<usize>())?;
+        let mut word = [0; size_of::<usize>()];
+        word.copy_from_slice(&bytes);
+        Ok(usize::from_le_bytes(word))
+    }
+
+    /// Like read_memory, but rather than failing when part of the range is unmapped, returns the
+    /// bytes up to the first word that couldn't be read.
+    fn read_memory_prefix(&self, addr: usize, len: usize) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        while bytes.len() < len {
+            let chunk_addr = addr + bytes.len();
+            let chunk_len = (align_addr_to_word(chunk_addr) + size_of::<usize>() - chunk_addr)
+                .min(len - bytes.len());
+            match self.read_memory(chunk_addr, chunk_len) {
+                Ok(chunk) => bytes.extend_from_slice(&chunk),
+                Err(_) => break,
+            }
+        }
+        bytes
+    }
+
+    /// Returns the canonical frame address of the current function, i.e. the value rsp had
+    /// before the call instruction that entered it. Uses the CFI for rip if there is any, and
+    /// otherwise assumes the function keeps a frame pointer.
+    fn get_cfa(&self, debug_data: &DwarfData) -> Result<usize, nix::Error> {
+        let regs = self.get_registers()?;
+        let read_memory = |addr, len| self.read_memory(addr, len);
+        let call_frame_info = debug_data.call_frame_info(regs.rip as usize);
+        if let Some(cfa) = call_frame_info.cfa(&regs, regs.rip as usize, &read_memory) {
+            return Ok(cfa);
+        }
+        Ok(match self.get_prologue_rsp_offset(regs.rip as usize)? {
+            Some(offset) => regs.rsp as usize + offset + 8,
+            None => regs.rbp as usize + 16,
+        })
+    }
+
+    /// Returns the registers of the caller of the frame with the given registers (see
+    /// CallFrameInfo::unwind), or None at the bottom of the stack. innermost says whether the
+    /// frame is the one currently executing, rather than one suspended in a call.
+    fn unwind(
+        &self,
+        debug_data: &DwarfData,
+        regs: &user_regs_struct,
+        innermost: bool,
+    ) -> Result<Option<user_regs_struct>, nix::Error> {
+        let rip = regs.rip as usize;
+        // Return addresses point just past the call instruction, which may be the last one in
+        // the function, so look up the call itself.
+        let lookup_addr = if innermost { rip } else { rip - 1 };
+        let read_memory = |addr, len| self.read_memory(addr, len);
+        if let Some(caller) =
+            debug_data
+                .call_frame_info(lookup_addr)
+                .unwind(regs, lookup_addr, &read_memory)
+        {
+            return Ok(if caller.rip == 0 { None } else { Some(caller) });
+        }
+
+        // Without CFI, follow the chain of saved frame pointers. If we stopped before the
+        // prologue set up the frame pointer, rbp still points at the caller's frame, so the
+        // return address has to be found relative to rsp.
+        let mut caller = *regs;
+        let prologue_rsp_offset = if innermost {
+            self.get_prologue_rsp_offset(rip)?
+        } else {
+            None
+        };
+        let return_addr_slot = match prologue_rsp_offset {
+            Some(offset) => regs.rsp as usize + offset,
+            None if regs.rbp == 0 => return Ok(None),
+            None if regs.rbp > regs.rsp => {
+                caller.rbp = self.read_word(regs.rbp as usize)? as u64;
+                regs.rbp as usize + 8
+            }
+            // A frame pointer below the stack pointer isn't one. Code without CFI that doesn't
+            // keep a frame pointer (such as the end of glibc's clone3, which deliberately has
+            // none) is usually a leaf that hasn't pushed anything, leaving the return address
+            // on top of the stack.
+            None if innermost => regs.rsp as usize,
+            None => return Ok(None),
+        };
+        caller.rip = self.read_word(return_addr_slot)? as u64;
+        caller.rsp = return_addr_slot as u64 + 8;
+        // As with CFI, a zero return address marks the outermost frame
+        Ok(if caller.rip == 0 { None } else { Some(caller) })
+    }
+
+    /// Prints one line per stack frame, unwinding from the current registers until reaching main
+    /// (or the bottom of the stack).
+    fn print_backtrace(&self, debug_data: &DwarfData) -> Result<(), nix::Error> {
+        let mut regs = self.get_registers()?;
+        let mut innermost = true;
+        loop {
+            let instruction_ptr = regs.rip as usize;
+            let lookup_addr = if innermost {
+                instruction_ptr
+            } else {
+                instruction_ptr - 1
+            };
+            let function = debug_data.get_function_from_addr(lookup_addr);
+            let line = debug_data.get_line_from_addr(lookup_addr);
+            // Code without line numbers is shown by its offset into the function
+            match (
+                &function,
+                &line,
+                debug_data.get_symbol_for_addr(lookup_addr),
+            ) {
+                (Some(function), Some(line), _) => println!("{} ({})", function, line),
+                (Some(_), None, Some((name, offset))) => println!(
+                    "{}+{:#x} ({:#x})",
+                    name,
+                    offset + instruction_ptr - lookup_addr,
+                    instruction_ptr
+                ),
+                (Some(function), None, None) => println!("{} ({:#x})", function, instruction_ptr),
+                (None, ..) => println!("unknown function ({:#x})", instruction_ptr),
+            }
+            if function.as_deref() == Some("main") {
+                break;
+            }
+            let caller = match self.unwind(debug_data, &regs, innermost)? {
+                Some(caller) => caller,
+                None => break,
+            };
+            // Callers' frames are higher up the stack; anything else means the CFI or the stack
+            // is corrupt (the frame pointer fallback never goes down the stack)
+            if caller.rsp <= regs.rsp {
+                println!("Backtrace stopped: previous frame inner to this frame (corrupt stack?)");
+                break;
+            }
+            regs = caller;
+            innermost = false;
+        }
+        Ok(())
+    }
+
+    /// Returns the offset from rsp of the return address if rip is still in the part of the
+    /// prologue that sets up the frame pointer (`push rbp; mov rbp, rsp`). In that case rbp still
+    /// belongs to the caller. Returns None once the frame is established.
+    fn get_prologue_rsp_offset(&self, rip: usize) -> Result<Option<usize>, nix::Error> {
+        let code = self.read_memory(rip, 4)?;
+        Ok(match code[..] {
+            // endbr64 or push rbp
+            [0xf3, 0x0f, 0x1e, 0xfa] | [0x55, ..] => Some(0),
+            // mov rbp, rsp
+            [0x48, 0x89, 0xe5, _] => Some(8),
+            _ => None,
+        })
+    }
+}
+
+/// Finds the program's entry point (AT_ENTRY) in an auxiliary vector, the list of (type, value)
+/// words that the kernel passes to a new program.
+pub fn auxv_entry_point(auxv: &[u8]) -> Option<usize> {
+    auxv.chunks_exact(16)
+        .map(|entry| (read_unsigned(&entry[..8]), read_unsigned(&entry[8..])))
+        .take_while(|&(entry_type, _)| entry_type != AT_NULL)
+        .find(|&(entry_type, _)| entry_type == AT_ENTRY)
+        .map(|(_, value)| value as usize)
+}
+
+pub fn align_addr_to_word(addr: usize) -> usize {
+    addr & (-(size_of::<usize>() as isize) as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn auxv(entries: &[(u64, u64)]) -> Vec<u8> {
+        entries
+            .iter()
+            .flat_map(|&(entry_type, value)| {
+                let mut entry = entry_type.to_le_bytes().to_vec();
+                entry.extend_from_slice(&value.to_le_bytes());
+                entry
+            })
+            .collect()
+    }
+
+    #[test]
+    fn auxv_entry_point_finds_at_entry() {
+        let auxv = auxv(&[
+            (6, 4096),
+            (AT_ENTRY, 0x401020),
+            (25, 0x7ffc0000),
+            (AT_NULL, 0),
+        ]);
+        assert_eq!(auxv_entry_point(&auxv), Some(0x401020));
+    }
+
+    #[test]
+    fn auxv_entry_point_stops_at_at_null() {
+        let auxv = auxv(&[(6, 4096), (AT_NULL, 0), (AT_ENTRY, 0x401020)]);
+        assert_eq!(auxv_entry_point(&auxv), None);
+    }
+
+    #[test]
+    fn auxv_entry_point_handles_missing_and_truncated_entries() {
+        assert_eq!(auxv_entry_point(&[]), None);
+        assert_eq!(auxv_entry_point(&auxv(&[(6, 4096)])), None);
+        // A partial entry at the end (e.g. from a short read) is ignored
+        let mut truncated = auxv(&[(6, 4096)]);
+        truncated.extend_from_slice(&auxv(&[(AT_ENTRY, 0x401020)])[..12]);
+        assert_eq!(auxv_entry_point(&truncated), None);
+    }
+}
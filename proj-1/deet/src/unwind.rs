@@ -0,0 +1,205 @@
+//! Stack unwinding with the call frame information (CFI) in .eh_frame and .debug_frame. For every
+//! instruction, the CFI says how to compute the canonical frame address (CFA) and where the
+//! caller's registers were saved, so it works without frame pointers, e.g. in optimized code and
+//! inside libc.
+
+use crate::dwarf_data::LocationExpression;
+use crate::location::{self, Frame, Place};
+use crate::value::{read_unsigned, MemoryReader};
+use gimli::{
+    BaseAddresses, CfaRule, DebugFrame, EhFrame, EndianRcSlice, LittleEndian, Register,
+    RegisterRule, UninitializedUnwindContext, UnwindSection, UnwindTableRow,
+};
+use nix::libc::user_regs_struct;
+use object::{Object, ObjectSection};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+// Sections are reference counted so that the unwind context can outlive a lookup
+type Slice = EndianRcSlice<LittleEndian>;
+
+// DWARF register numbers for x86-64 (System V ABI, figure 3.36)
+const DWARF_RSP: u16 = 7;
+const DWARF_RETURN_ADDRESS: u16 = 16;
+
+pub struct CallFrameInfo {
+    eh_frame: Rc<[u8]>,
+    /// Pointers in .eh_frame are usually relative to where the section is loaded
+    eh_frame_address: u64,
+    debug_frame: Rc<[u8]>,
+    text_address: u64,
+    /// How far the file's code has been moved from the addresses in its CFI (see
+    /// DwarfData::relocate)
+    bias: usize,
+    /// Scratch space for running CFI programs, kept to save allocating it for every lookup
+    context: RefCell<UninitializedUnwindContext<Slice>>,
+}
+
+impl CallFrameInfo {
+    /// Reads the CFI sections of an executable. Either may be missing, in which case we know
+    /// nothing about how to unwind the code it would have covered.
+    pub fn load(object: &object::File) -> CallFrameInfo {
+        let section_address = |name| {
+            object
+                .section_by_name(name)
+                .map_or(0, |section| section.address())
+        };
+        let section_data = |name| {
+            object
+                .section_data_by_name(name)
+                .map(|data| Rc::from(data.into_owned()))
+                .unwrap_or_else(|| Rc::from(Vec::new()))
+        };
+        CallFrameInfo {
+            eh_frame: section_data(".eh_frame"),
+            eh_frame_address: section_address(".eh_frame"),
+            debug_frame: section_data(".debug_frame"),
+            text_address: section_address(".text"),
+            bias: 0,
+            context: RefCell::new(UninitializedUnwindContext::new()),
+        }
+    }
+
+    pub fn relocate(&mut self, bias: usize) {
+        self.bias = self.bias.wrapping_add(bias);
+    }
+
+    /// Computes the CFA of the frame with the given registers, whose code is at lookup_addr (rip,
+    /// or for callers the call instruction before the return address). Returns None if there is
+    /// no CFI for the address.
+    pub fn cfa(
+        &self,
+        regs: &user_regs_struct,
+        lookup_addr: usize,
+        read_memory: MemoryReader,
+    ) -> Option<usize> {
+        self.with_row(lookup_addr, |row| evaluate_cfa(row, regs, read_memory))
+    }
+
+    /// Recovers the registers of the caller of the frame with the given registers, as they were
+    /// when it made the call. rip is the return address, rsp the CFA, and the callee-saved
+    /// registers are read from wherever the frame saved them. (Registers the frame didn't save
+    /// are assumed unchanged.) The outermost frame's caller has rip 0. Returns None if there is
+    /// no CFI for lookup_addr, or it can't be evaluated.
+    pub fn unwind(
+        &self,
+        regs: &user_regs_struct,
+        lookup_addr: usize,
+        read_memory: MemoryReader,
+    ) -> Option<user_regs_struct> {
+        self.with_row(lookup_addr, |row| {
+            let cfa = evaluate_cfa(row, regs, read_memory)?;
+            let mut caller = *regs;
+            for register in 0..=DWARF_RETURN_ADDRESS {
+                let value = match row.register(Register(register)) {
+                    // Outermost frames (such as _start) mark the return address as undefined
+                    RegisterRule::Undefined if register == DWARF_RETURN_ADDRESS => 0,
+                    RegisterRule::Undefined
+                    | RegisterRule::SameValue
+                    | RegisterRule::Architectural => continue,
+                    RegisterRule::Offset(offset) => {
+                        read_word(read_memory, cfa.wrapping_add(offset as usize))?
+                    }
+                    RegisterRule::ValOffset(offset) => cfa.wrapping_add(offset as usize) as u64,
+                    RegisterRule::Register(other) => {
+                        location::register_value(regs, other.0).ok()?
+                    }
+                    RegisterRule::Expression(expr) => {
+                        let addr = evaluate_expression(&expr.0, regs, cfa, read_memory)?;
+                        read_word(read_memory, addr)?
+                    }
+                    RegisterRule::ValExpression(expr) => {
+                        evaluate_expression(&expr.0, regs, cfa, read_memory)? as u64
+                    }
+                };
+                *location::register_value_mut(&mut caller, register).ok()? = value;
+            }
+            // The caller's rsp is the CFA by definition, once the return address is popped
+            *location::register_value_mut(&mut caller, DWARF_RSP).ok()? = cfa as u64;
+            Some(caller)
+        })
+    }
+
+    /// Finds the row of the CFI table for addr, in .eh_frame or else .debug_frame, and passes it
+    /// to f.
+    fn with_row<T>(
+        &self,
+        addr: usize,
+        f: impl Fn(&UnwindTableRow<Slice>) -> Option<T>,
+    ) -> Option<T> {
+        let bases = BaseAddresses::default()
+            .set_eh_frame(self.eh_frame_address)
+            .set_text(self.text_address);
+        let addr = addr.wrapping_sub(self.bias);
+        let eh_frame = EhFrame::from(Slice::new(Rc::clone(&self.eh_frame), LittleEndian));
+        if let Some(row) = self.find_row(&eh_frame, &bases, addr) {
+            return f(&row);
+        }
+        let debug_frame = DebugFrame::from(Slice::new(Rc::clone(&self.debug_frame), LittleEndian));
+        self.find_row(&debug_frame, &bases, addr)
+            .and_then(|row| f(&row))
+    }
+
+    fn find_row<S: UnwindSection<Slice>>(
+        &self,
+        section: &S,
+        bases: &BaseAddresses,
+        addr: usize,
+    ) -> Option<UnwindTableRow<Slice>> {
+        section
+            .unwind_info_for_address(
+                bases,
+                &mut self.context.borrow_mut(),
+                addr as u64,
+                S::cie_from_offset,
+            )
+            .ok()
+    }
+}
+
+fn evaluate_cfa(
+    row: &UnwindTableRow<Slice>,
+    regs: &user_regs_struct,
+    read_memory: MemoryReader,
+) -> Option<usize> {
+    match row.cfa() {
+        CfaRule::RegisterAndOffset { register, offset } => {
+            let base = location::register_value(regs, register.0).ok()?;
+            Some(base.wrapping_add(*offset as u64) as usize)
+        }
+        // Used in PLT entries, where the CFA depends on how far into the entry rip is
+        CfaRule::Expression(expr) => evaluate_expression(&expr.0, regs, 0, read_memory),
+    }
+}
+
+/// Runs a DWARF expression from the CFI, which leaves an address or value on the stack.
+fn evaluate_expression(
+    bytecode: &Slice,
+    regs: &user_regs_struct,
+    cfa: usize,
+    read_memory: MemoryReader,
+) -> Option<usize> {
+    let expr = LocationExpression {
+        bytecode: bytecode.to_vec(),
+        encoding: gimli::Encoding {
+            format: gimli::Format::Dwarf32,
+            version: 4,
+            address_size: 8,
+        },
+    };
+    let frame = Frame {
+        regs,
+        fpregs: None,
+        cfa,
+        frame_base: None,
+        read_memory,
+    };
+    match location::evaluate(&expr, &frame) {
+        Ok(Place::Memory(addr)) => Some(addr),
+        _ => None,
+    }
+}
+
+fn read_word(read_memory: MemoryReader, addr: usize) -> Option<u64> {
+    read_memory(addr, 8).ok().map(|bytes| read_unsigned(&bytes))
+}
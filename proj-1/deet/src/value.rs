@@ -0,0 +1,692 @@
+//! Formatting of values read from the inferior according to their DWARF type.
+
+use crate::dwarf_data::{DwarfData, Member, Type, TypeKind, Variant};
+use std::convert::TryInto;
+use std::fmt;
+
+// Maximum number of array elements or string characters to print
+pub const MAX_ELEMENTS: usize = 200;
+
+/// A value read from the inferior, along with the address it was read from (if it lives in
+/// memory).
+#[derive(Debug, Clone)]
+pub struct Value {
+    pub entity_type: Type,
+    pub bytes: Vec<u8>,
+    pub address: Option<usize>,
+}
+
+/// Reads `len` bytes of the inferior's memory at an address.
+pub type MemoryReader<'a> = &'a dyn Fn(usize, usize) -> Result<Vec<u8>, nix::Error>;
+
+/// Formats a value of the given type from its bytes in the inferior's memory (little-endian).
+/// read_memory is used to show the strings that `char *` values point to, and the contents of
+/// Rust strings and vectors.
+pub fn format_value(
+    debug_data: &DwarfData,
+    entity_type: &Type,
+    bytes: &[u8],
+    read_memory: MemoryReader,
+) -> String {
+    let dtype = debug_data.resolve_type(entity_type);
+    if bytes.is_empty() && dtype.size > 0 {
+        return "<optimized out>".to_string();
+    }
+    if bytes.len() < dtype.size {
+        return "<unavailable>".to_string();
+    }
+    match dtype.kind {
+        TypeKind::Base => format_base_value(&dtype.name, bytes),
+        TypeKind::Pointer(target) => {
+            let addr = read_unsigned(bytes) as usize;
+            let points_to_char = target
+                .and_then(|target| debug_data.get_type(target))
+                .is_some_and(|target| is_char(debug_data.resolve_type(target)));
+            if points_to_char && addr != 0 {
+                if let Some(string) = read_c_string(read_memory, addr) {
+                    return format!("{:#x} {}", addr, string);
+                }
+            }
+            format!("{:#x}", addr)
+        }
+        TypeKind::Struct(ref members) | TypeKind::Union(ref members) => {
+            if let Some(value) = format_rust_std_value(debug_data, dtype, bytes, read_memory) {
+                return value;
+            }
+            let fields: Vec<String> = members
+                .iter()
+                .map(|member| {
+                    format!(
+                        "{} = {}",
+                        member.name,
+                        format_member(debug_data, member, bytes, read_memory)
+                    )
+                })
+                .collect();
+            format!("{{{}}}", fields.join(", "))
+        }
+        TypeKind::Variants(ref discriminant, ref variants) => {
+            format_variant(debug_data, discriminant, variants, bytes, read_memory)
+        }
+        TypeKind::Array(element_type, ref counts) => {
+            format_array(debug_data, element_type, counts, bytes, read_memory)
+        }
+        TypeKind::Enum(ref enumerators) => {
+            let value = read_signed(&bytes[..dtype.size]);
+            match enumerators
+                .iter()
+                .find(|(_, enum_value)| *enum_value == value)
+            {
+                Some((name, _)) => name.clone(),
+                None => value.to_string(),
+            }
+        }
+        TypeKind::Function(..) => "<function>".to_string(),
+        // resolve_type only leaves these if the underlying type is unknown
+        TypeKind::Typedef(_) | TypeKind::Const(_) | TypeKind::Volatile(_) => {
+            "<unknown type>".to_string()
+        }
+    }
+}
+
+/// Returns the value as an integer, if it has an integral (or pointer/enum) type.
+pub fn value_as_integer(debug_data: &DwarfData, value: &Value) -> Option<i64> {
+    let dtype = debug_data.resolve_type(&value.entity_type);
+    let bytes = &value.bytes[..dtype.size.min(value.bytes.len())];
+    match dtype.kind {
+        TypeKind::Base => match dtype.name.as_str() {
+            "float" | "double" | "long double" | "f32" | "f64" => None,
+            name if is_unsigned(name) => Some(read_unsigned(bytes) as i64),
+            _ => Some(read_signed(bytes)),
+        },
+        TypeKind::Enum(_) => Some(read_signed(bytes)),
+        TypeKind::Pointer(_) => Some(read_unsigned(bytes) as i64),
+        _ => None,
+    }
+}
+
+/// Returns the value as an f64, if it has a floating point type.
+pub fn value_as_float(debug_data: &DwarfData, value: &Value) -> Option<f64> {
+    let dtype = debug_data.resolve_type(&value.entity_type);
+    let bytes = &value.bytes;
+    match dtype.kind {
+        TypeKind::Base => match dtype.name.as_str() {
+            "float" | "f32" if bytes.len() == 4 => {
+                Some(f32::from_bits(read_unsigned(bytes) as u32) as f64)
+            }
+            "double" | "f64" if bytes.len() == 8 => Some(f64::from_bits(read_unsigned(bytes))),
+            "long double" if bytes.len() >= 10 => Some(x87_extended_to_f64(&bytes[..10])),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Converts a value to the given type, the way C converts the right-hand side of an assignment:
+/// integers are truncated to the size of the type and converted to or from floating point as
+/// needed. Structs, unions and arrays can only be copied from a value of the same type.
+pub fn encode_value(
+    debug_data: &DwarfData,
+    dtype: &Type,
+    value: &Value,
+) -> Result<Vec<u8>, String> {
+    let target = debug_data.resolve_type(dtype);
+    match target.kind {
+        TypeKind::Base | TypeKind::Enum(_) | TypeKind::Pointer(_) => {}
+        _ if value.entity_type.name == dtype.name && value.bytes.len() == target.size => {
+            return Ok(value.bytes.clone())
+        }
+        _ => return Err("Invalid cast.".to_string()),
+    }
+    let integer = value_as_integer(debug_data, value);
+    let float = match integer {
+        Some(integer) => integer as f64,
+        None => value_as_float(debug_data, value).ok_or_else(|| "Invalid cast.".to_string())?,
+    };
+    let name = match target.kind {
+        TypeKind::Base => target.name.as_str(),
+        _ => "",
+    };
+    let mut bytes = match name {
+        "float" | "f32" => (float as f32).to_bits().to_le_bytes().to_vec(),
+        "double" | "f64" => float.to_bits().to_le_bytes().to_vec(),
+        "long double" => return Err("Assigning to long double is not supported.".to_string()),
+        "_Bool" | "bool" => vec![(float != 0.0) as u8],
+        _ => {
+            let integer = match integer {
+                Some(integer) => integer,
+                None if is_unsigned(name) => float as u64 as i64,
+                None => float as i64,
+            };
+            integer.to_le_bytes().to_vec()
+        }
+    };
+    bytes.resize(target.size, 0);
+    Ok(bytes)
+}
+
+fn format_member(
+    debug_data: &DwarfData,
+    member: &Member,
+    bytes: &[u8],
+    read_memory: MemoryReader,
+) -> String {
+    match member_bytes(debug_data, member, bytes) {
+        Some((member_type, member_bytes)) => {
+            format_value(debug_data, member_type, member_bytes, read_memory)
+        }
+        None => "<unknown type>".to_string(),
+    }
+}
+
+/// Returns the type of a struct member and its part of the struct's bytes.
+fn member_bytes<'a>(
+    debug_data: &'a DwarfData,
+    member: &Member,
+    bytes: &'a [u8],
+) -> Option<(&'a Type, &'a [u8])> {
+    let member_type = debug_data.get_type(member.member_type)?;
+    let end = (member.offset + member_type.size).min(bytes.len());
+    Some((member_type, &bytes[member.offset.min(end)..end]))
+}
+
+/// Looks up a struct member by name, returning its type (with typedefs resolved) and bytes.
+fn named_member<'a>(
+    debug_data: &'a DwarfData,
+    dtype: &Type,
+    name: &str,
+    bytes: &'a [u8],
+) -> Option<(&'a Type, &'a [u8])> {
+    let members = match dtype.kind {
+        TypeKind::Struct(ref members) => members,
+        _ => return None,
+    };
+    let member = members.iter().find(|member| member.name == name)?;
+    let (member_type, member_bytes) = member_bytes(debug_data, member, bytes)?;
+    Some((debug_data.resolve_type(member_type), member_bytes))
+}
+
+/// Formats the Rust standard library types whose contents live on the heap: String and &str as
+/// strings, and Vec<T> as its elements. Returns None for other types, or if the layout isn't
+/// the expected one, in which case the value is shown as a plain struct.
+fn format_rust_std_value(
+    debug_data: &DwarfData,
+    dtype: &Type,
+    bytes: &[u8],
+    read_memory: MemoryReader,
+) -> Option<String> {
+    let name = dtype.name.as_str();
+    if name == "&str" || name == "&mut str" || name == "String" {
+        let (addr, len) = if name == "String" {
+            let (vec_type, vec_bytes) = named_member(debug_data, dtype, "vec", bytes)?;
+            read_vec(debug_data, vec_type, vec_bytes)?
+        } else {
+            let (_, data_ptr) = named_member(debug_data, dtype, "data_ptr", bytes)?;
+            let (_, length) = named_member(debug_data, dtype, "length", bytes)?;
+            (
+                read_unsigned(data_ptr) as usize,
+                read_unsigned(length) as usize,
+            )
+        };
+        let string = read_memory(addr, len.min(MAX_ELEMENTS)).ok()?;
+        let ellipsis = if len > MAX_ELEMENTS { "..." } else { "" };
+        return Some(format!(
+            "{:?}{}",
+            String::from_utf8_lossy(&string),
+            ellipsis
+        ));
+    }
+    if name.starts_with("Vec<") {
+        let (addr, len) = read_vec(debug_data, dtype, bytes)?;
+        let element_type = debug_data.get_type(*dtype.type_params.first()?)?;
+        let size = element_type.size;
+        let elements_bytes = read_memory(addr, len.min(MAX_ELEMENTS) * size).ok()?;
+        let mut elements: Vec<String> = (0..len.min(MAX_ELEMENTS))
+            .map(|i| {
+                let element_bytes = &elements_bytes[i * size..(i + 1) * size];
+                format_value(debug_data, element_type, element_bytes, read_memory)
+            })
+            .collect();
+        if len > MAX_ELEMENTS {
+            elements.push("...".to_string());
+        }
+        return Some(format!("Vec(size={}) = {{{}}}", len, elements.join(", ")));
+    }
+    None
+}
+
+/// Returns the address of a Vec's buffer and its length. The buffer pointer is buried a few
+/// structs deep in the Vec's RawVec, where exactly depending on the Rust version, so it is found
+/// by looking for the first pointer in there.
+fn read_vec(debug_data: &DwarfData, dtype: &Type, bytes: &[u8]) -> Option<(usize, usize)> {
+    let (buf_type, buf_bytes) = named_member(debug_data, dtype, "buf", bytes)?;
+    let (_, len) = named_member(debug_data, dtype, "len", bytes)?;
+    let addr = find_pointer(debug_data, buf_type, buf_bytes, 0)?;
+    Some((addr, read_unsigned(len) as usize))
+}
+
+// Bounds the recursion in find_pointer
+const MAX_POINTER_DEPTH: usize = 8;
+
+fn find_pointer(debug_data: &DwarfData, dtype: &Type, bytes: &[u8], depth: usize) -> Option<usize> {
+    match debug_data.resolve_type(dtype).kind {
+        TypeKind::Pointer(_) => Some(read_unsigned(bytes) as usize),
+        TypeKind::Struct(ref members) if depth < MAX_POINTER_DEPTH => {
+            members.iter().find_map(|member| {
+                let (member_type, member_bytes) = member_bytes(debug_data, member, bytes)?;
+                find_pointer(debug_data, member_type, member_bytes, depth + 1)
+            })
+        }
+        _ => None,
+    }
+}
+
+/// Formats a Rust enum as the variant its discriminant selects, like Rust would write it:
+/// `None`, `Some(7)` or `Point {x = 3, y = 4}`.
+fn format_variant(
+    debug_data: &DwarfData,
+    discriminant: &Option<Member>,
+    variants: &[Variant],
+    bytes: &[u8],
+    read_memory: MemoryReader,
+) -> String {
+    let variant = match discriminant {
+        Some(discriminant) => {
+            let (_, discriminant_bytes) = match member_bytes(debug_data, discriminant, bytes) {
+                Some(member) => member,
+                None => return "<unknown type>".to_string(),
+            };
+            // Values are compared at the discriminant's size, since rustc may encode them as
+            // signed
+            let truncate = |value: u64| match discriminant_bytes.len() {
+                size if size >= 8 => value,
+                size => value & ((1 << (8 * size)) - 1),
+            };
+            let value = read_unsigned(discriminant_bytes);
+            variants
+                .iter()
+                .find(|variant| variant.discriminant.map(truncate) == Some(value))
+                .or_else(|| {
+                    variants
+                        .iter()
+                        .find(|variant| variant.discriminant.is_none())
+                })
+        }
+        None => variants.first(),
+    };
+    let member = match variant.and_then(|variant| variant.members.first()) {
+        Some(member) => member,
+        None => return "<invalid discriminant>".to_string(),
+    };
+    let (variant_type, variant_bytes) = match member_bytes(debug_data, member, bytes) {
+        Some(variant) => variant,
+        None => return format!("{} = <unknown type>", member.name),
+    };
+    let fields = match debug_data.resolve_type(variant_type).kind {
+        TypeKind::Struct(ref fields) => fields,
+        _ => return member.name.clone(),
+    };
+    if fields.is_empty() {
+        member.name.clone()
+    } else if fields.iter().all(|field| field.name.starts_with("__")) {
+        // Tuple variants' fields are named __0, __1, ...
+        let values: Vec<String> = fields
+            .iter()
+            .map(|field| format_member(debug_data, field, variant_bytes, read_memory))
+            .collect();
+        format!("{}({})", member.name, values.join(", "))
+    } else {
+        format!(
+            "{} {}",
+            member.name,
+            format_value(debug_data, variant_type, variant_bytes, read_memory)
+        )
+    }
+}
+
+fn format_base_value(name: &str, bytes: &[u8]) -> String {
+    match name {
+        "_Bool" | "bool" => match read_unsigned(bytes) {
+            0 => "false".to_string(),
+            1 => "true".to_string(),
+            other => other.to_string(),
+        },
+        "float" | "f32" if bytes.len() == 4 => {
+            format_float(f32::from_bits(read_unsigned(bytes) as u32))
+        }
+        "double" | "f64" if bytes.len() == 8 => format_float(f64::from_bits(read_unsigned(bytes))),
+        "long double" if bytes.len() >= 10 => format_float(x87_extended_to_f64(&bytes[..10])),
+        // Rust chars are 4-byte unicode scalar values
+        "char" if bytes.len() == 4 => match std::char::from_u32(read_unsigned(bytes) as u32) {
+            Some(c) => format!("{:?}", c),
+            None => format!("{:#x}", read_unsigned(bytes)),
+        },
+        _ if name.contains("char") => {
+            let value = if is_unsigned(name) {
+                read_unsigned(bytes) as i64
+            } else {
+                read_signed(bytes)
+            };
+            format!("{} '{}'", value, escape_c_char(value as u8))
+        }
+        _ if is_unsigned(name) => read_unsigned(bytes).to_string(),
+        _ => read_signed(bytes).to_string(),
+    }
+}
+
+fn format_array(
+    debug_data: &DwarfData,
+    element_type_offset: usize,
+    counts: &[Option<usize>],
+    bytes: &[u8],
+    read_memory: MemoryReader,
+) -> String {
+    let element_type = match debug_data.get_type(element_type_offset) {
+        Some(element_type) => element_type,
+        None => return "<unknown type>".to_string(),
+    };
+    let count = match counts.first() {
+        Some(Some(count)) => *count,
+        _ => return "{...}".to_string(),
+    };
+    if counts.len() == 1 && is_char(debug_data.resolve_type(element_type)) {
+        let len = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+        return quote_c_string(&bytes[..len.min(MAX_ELEMENTS)]);
+    }
+
+    // A multi-dimensional array is an array of arrays with one dimension fewer
+    let row_type;
+    let element_type = if counts.len() > 1 {
+        row_type = Type {
+            name: String::new(),
+            size: bytes.len().checked_div(count).unwrap_or(0),
+            kind: TypeKind::Array(element_type_offset, counts[1..].to_vec()),
+            type_params: Vec::new(),
+            offset: None,
+        };
+        &row_type
+    } else {
+        element_type
+    };
+
+    let size = element_type.size;
+    let mut elements: Vec<String> = (0..count.min(MAX_ELEMENTS))
+        .map(|i| {
+            let end = ((i + 1) * size).min(bytes.len());
+            let element_bytes = &bytes[(i * size).min(end)..end];
+            format_value(debug_data, element_type, element_bytes, read_memory)
+        })
+        .collect();
+    if count > MAX_ELEMENTS {
+        elements.push("...".to_string());
+    }
+    format!("{{{}}}", elements.join(", "))
+}
+
+fn is_char(dtype: &Type) -> bool {
+    match dtype.kind {
+        TypeKind::Base => dtype.size == 1 && dtype.name.contains("char"),
+        _ => false,
+    }
+}
+
+fn is_unsigned(name: &str) -> bool {
+    name.contains("unsigned") || name.starts_with('u')
+}
+
+/// Reads a NUL-terminated string from the inferior, returning it quoted and escaped. Returns
+/// None if the memory can't be read.
+fn read_c_string(read_memory: MemoryReader, addr: usize) -> Option<String> {
+    let mut string = Vec::new();
+    while string.len() < MAX_ELEMENTS {
+        let chunk = read_memory(addr + string.len(), 8).ok()?;
+        match chunk.iter().position(|&b| b == 0) {
+            Some(len) => {
+                string.extend_from_slice(&chunk[..len]);
+                return Some(quote_c_string(&string));
+            }
+            None => string.extend_from_slice(&chunk),
+        }
+    }
+    Some(format!("{}...", quote_c_string(&string[..MAX_ELEMENTS])))
+}
+
+pub fn quote_c_string(bytes: &[u8]) -> String {
+    let escaped: Vec<String> = bytes
+        .iter()
+        .map(|&b| match b {
+            b'"' => "\\\"".to_string(),
+            b'\'' => "'".to_string(),
+            _ => escape_c_char(b),
+        })
+        .collect();
+    format!("\"{}\"", escaped.concat())
+}
+
+/// Escapes a byte the way it would be written in a C character literal.
+pub fn escape_c_char(b: u8) -> String {
+    match b {
+        b'\n' => "\\n".to_string(),
+        b'\t' => "\\t".to_string(),
+        b'\r' => "\\r".to_string(),
+        b'\\' => "\\\\".to_string(),
+        b'\'' => "\\'".to_string(),
+        0x20..=0x7e => (b as char).to_string(),
+        _ => format!("\\{:o}", b),
+    }
+}
+
+/// Formats a floating point number, switching to scientific notation for very large or small
+/// magnitudes (like printf's %g) rather than printing hundreds of digits.
+pub fn format_float<T: Copy + Into<f64> + fmt::Display + fmt::LowerExp>(value: T) -> String {
+    let magnitude = value.into().abs();
+    if magnitude == 0.0 || !magnitude.is_finite() || (1e-4..1e16).contains(&magnitude) {
+        value.to_string()
+    } else {
+        format!("{:e}", value)
+    }
+}
+
+/// Zero-extends up to 8 little-endian bytes into a u64.
+pub fn read_unsigned(bytes: &[u8]) -> u64 {
+    let mut buf = [0u8; 8];
+    let len = bytes.len().min(8);
+    buf[..len].copy_from_slice(&bytes[..len]);
+    u64::from_le_bytes(buf)
+}
+
+/// Sign-extends up to 8 little-endian bytes into an i64.
+pub fn read_signed(bytes: &[u8]) -> i64 {
+    let len = bytes.len().min(8);
+    if len == 0 {
+        return 0;
+    }
+    let shift = 64 - 8 * len as u32;
+    ((read_unsigned(bytes) << shift) as i64) >> shift
+}
+
+/// Converts an x87 80-bit extended precision float (as used for C's long double) to an f64.
+pub fn x87_extended_to_f64(bytes: &[u8]) -> f64 {
+    let mantissa = u64::from_le_bytes(bytes[..8].try_into().unwrap());
+    let sign_exponent = u16::from_le_bytes(bytes[8..10].try_into().unwrap());
+    let sign = if sign_exponent & 0x8000 != 0 {
+        -1.0
+    } else {
+        1.0
+    };
+    let exponent = (sign_exponent & 0x7fff) as i32;
+    if exponent == 0 && mantissa == 0 {
+        return sign * 0.0;
+    }
+    if exponent == 0x7fff {
+        return if mantissa << 1 == 0 {
+            sign * f64::INFINITY
+        } else {
+            f64::NAN
+        };
+    }
+    // The mantissa has an explicit integer bit, so it represents mantissa / 2^63.
+    sign * (mantissa as f64) * 2f64.powi(exponent - 16383 - 63)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nix::errno::Errno;
+    use std::fs;
+    use std::mem::size_of;
+    use std::process::Command;
+
+    // A Rust program whose debugging information gives the types of std's String and Vec
+    const RUST_TYPES: &str = "
+        fn show(blank: String, empty: Vec<i32>, name: String, numbers: Vec<i32>) {
+            println!(\"{} {:?} {} {:?}\", blank, empty, name, numbers);
+        }
+        fn main() {
+            show(String::new(), Vec::new(), String::from(\"deet\"), vec![1, 2, 3]);
+        }
+    ";
+
+    /// Compiles RUST_TYPES into a temporary directory and loads its debugging information.
+    fn rust_types() -> DwarfData {
+        let dir = std::env::temp_dir().join(format!("deet-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let source_path = dir.join("rust_types.rs");
+        let executable = dir.join("rust_types");
+        fs::write(&source_path, RUST_TYPES).unwrap();
+        let status = Command::new("rustc")
+            .arg("-g")
+            .arg("-o")
+            .arg(&executable)
+            .arg(&source_path)
+            .status()
+            .expect("failed to run rustc");
+        assert!(status.success(), "failed to compile rust_types");
+        DwarfData::from_file(executable.to_str().unwrap()).unwrap()
+    }
+
+    /// Returns the bytes of a value in this process, which the compiled program lays out the
+    /// same way.
+    fn bytes_of<T>(value: &T) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(value as *const T as *const u8, size_of::<T>()) }
+    }
+
+    fn buffer<T>(values: &[T]) -> (usize, Vec<u8>) {
+        let bytes = unsafe {
+            std::slice::from_raw_parts(values.as_ptr() as *const u8, std::mem::size_of_val(values))
+        };
+        (values.as_ptr() as usize, bytes.to_vec())
+    }
+
+    fn x87(mantissa: u64, sign_exponent: u16) -> Vec<u8> {
+        let mut bytes = mantissa.to_le_bytes().to_vec();
+        bytes.extend_from_slice(&sign_exponent.to_le_bytes());
+        bytes
+    }
+
+    #[test]
+    fn base_values_follow_their_type_name() {
+        assert_eq!(format_base_value("int", &(-5i32).to_le_bytes()), "-5");
+        assert_eq!(
+            format_base_value("unsigned int", &u32::MAX.to_le_bytes()),
+            "4294967295"
+        );
+        assert_eq!(format_base_value("u8", &[200]), "200");
+        assert_eq!(format_base_value("_Bool", &[1]), "true");
+        assert_eq!(format_base_value("bool", &[0]), "false");
+        assert_eq!(format_base_value("bool", &[7]), "7");
+        assert_eq!(format_base_value("double", &2.5f64.to_le_bytes()), "2.5");
+        assert_eq!(format_base_value("f32", &0.25f32.to_le_bytes()), "0.25");
+    }
+
+    #[test]
+    fn chars_show_their_value_and_literal() {
+        assert_eq!(format_base_value("char", b"A"), "65 'A'");
+        assert_eq!(format_base_value("char", &[0xff]), "-1 '\\377'");
+        assert_eq!(format_base_value("unsigned char", b"\n"), "10 '\\n'");
+        // Rust's char is a 4-byte unicode scalar value
+        assert_eq!(
+            format_base_value("char", &('é' as u32).to_le_bytes()),
+            "'é'"
+        );
+        assert_eq!(
+            format_base_value("char", &0xd800u32.to_le_bytes()),
+            "0xd800"
+        );
+    }
+
+    #[test]
+    fn c_strings_are_quoted_and_escaped() {
+        assert_eq!(quote_c_string(b"hi"), "\"hi\"");
+        assert_eq!(
+            quote_c_string(b"say \"it's\"\t\\\x01"),
+            "\"say \\\"it's\\\"\\t\\\\\\1\""
+        );
+        assert_eq!(escape_c_char(b'\''), "\\'");
+        assert_eq!(escape_c_char(0x7f), "\\177");
+    }
+
+    #[test]
+    fn floats_switch_to_scientific_notation_at_the_extremes() {
+        assert_eq!(format_float(1.5f64), "1.5");
+        assert_eq!(format_float(0.0f64), "0");
+        assert_eq!(format_float(1e20f64), "1e20");
+        assert_eq!(format_float(1.5e-7f64), "1.5e-7");
+        assert_eq!(format_float(f64::INFINITY), "inf");
+    }
+
+    #[test]
+    fn integers_are_extended_from_their_size() {
+        assert_eq!(read_unsigned(&[0xff, 0xff]), 0xffff);
+        assert_eq!(read_signed(&[0xff, 0xff]), -1);
+        assert_eq!(read_signed(&[0xff, 0x7f]), 0x7fff);
+        assert_eq!(read_signed(&[]), 0);
+        assert_eq!(read_signed(&i64::MIN.to_le_bytes()), i64::MIN);
+    }
+
+    #[test]
+    fn x87_extended_floats_convert_to_f64() {
+        assert_eq!(x87_extended_to_f64(&x87(1 << 63, 0x3fff)), 1.0);
+        assert_eq!(x87_extended_to_f64(&x87(3 << 62, 0xc000)), -3.0);
+        assert_eq!(x87_extended_to_f64(&x87(0, 0x8000)), 0.0);
+        assert_eq!(x87_extended_to_f64(&x87(1 << 63, 0x7fff)), f64::INFINITY);
+        assert_eq!(
+            x87_extended_to_f64(&x87(1 << 63, 0xffff)),
+            f64::NEG_INFINITY
+        );
+        assert!(x87_extended_to_f64(&x87(3 << 62, 0x7fff)).is_nan());
+    }
+
+    #[test]
+    fn rust_strings_and_vectors_show_their_contents() {
+        let debug_data = rust_types();
+        let show = debug_data.get_addr_for_function(None, "show").unwrap();
+        let param_type = |name| &debug_data.get_variable(show, name).unwrap().entity_type;
+        let blank = String::new();
+        let empty: Vec<i32> = Vec::new();
+        let name = String::from("deet");
+        let numbers = vec![1, 2, 3];
+        // The only memory there is holds the contents of name and numbers. As with Inferior and
+        // CoreDump, reading nothing succeeds anywhere: the buffer of an empty String or Vec is a
+        // dangling pointer.
+        let memory = [buffer(name.as_bytes()), buffer(&numbers)];
+        let read_memory = |addr: usize, len: usize| {
+            if len == 0 {
+                return Ok(Vec::new());
+            }
+            memory
+                .iter()
+                .find(|(start, bytes)| addr >= *start && addr + len <= start + bytes.len())
+                .map(|(start, bytes)| bytes[addr - start..addr - start + len].to_vec())
+                .ok_or(nix::Error::Sys(Errno::EIO))
+        };
+        let format = |name, bytes| format_value(&debug_data, param_type(name), bytes, &read_memory);
+        assert_eq!(format("blank", bytes_of(&blank)), "\"\"");
+        assert_eq!(format("empty", bytes_of(&empty)), "Vec(size=0) = {}");
+        assert_eq!(format("name", bytes_of(&name)), "\"deet\"");
+        assert_eq!(
+            format("numbers", bytes_of(&numbers)),
+            "Vec(size=3) = {1, 2, 3}"
+        );
+    }
+}